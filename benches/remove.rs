@@ -0,0 +1,40 @@
+//! Time of `MicroTable::remove` as the number of categories grows. A remove touches only the posting sets of the
+//! record's own categories, so the time per remove should stay flat. Run with `cargo bench`.
+
+use std::{hint::black_box, time::Instant};
+use microtable::{MicroRecord, MicroTable};
+
+#[derive(Clone)]
+struct Item {
+	id: u32,
+	cats: [u32; 3],
+}
+
+impl MicroRecord for Item {
+	type Key = u32;
+	type Category = u32;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.cats
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
+
+const RECORDS: u32 = 100_000;
+
+fn main() {
+	for categories in [10, 1_000, 100_000] {
+		let mut table: MicroTable<Item> = (0..RECORDS)
+			.map(|id| Item { id, cats: [id % categories, (id / 3) % categories, (id * 7) % categories] })
+			.collect();
+		let start = Instant::now();
+		// every other record, so the posting sets shrink but don't empty
+		for id in (0..RECORDS).step_by(2) {
+			black_box(table.remove(&id));
+		}
+		let per_remove = start.elapsed() / (RECORDS / 2);
+		println!("remove, {RECORDS} records in {categories} categories: {per_remove:?} per record");
+		black_box(table);
+	}
+}
@@ -0,0 +1,210 @@
+//! A table owned by a thread of its own, used by messages: [`TableActor::spawn`] moves the table to the thread, which
+//! runs the [`TableCommand`]s sent by [`TableHandle`]s one at a time, and sends each reply back on a oneshot channel.
+//! The commands are serializable, to come from a socket or a queue as they are.
+
+use core::{fmt, hash::BuildHasher};
+use alloc::vec::Vec;
+use std::thread::JoinHandle;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use crate::{KeyError, MicroRecord, MicroTable, TableOp};
+
+/// A query or a change for a [`TableActor`].
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(
+	serialize = "T: Serialize, T::Key: Serialize, T::Category: Serialize",
+	deserialize = "T: Deserialize<'de>, T::Key: Deserialize<'de>, T::Category: Deserialize<'de>",
+))]
+pub enum TableCommand<T: MicroRecord> {
+	/// Replies [`TableReply::Record`].
+	Get(T::Key),
+	/// Replies [`TableReply::Found`].
+	ContainsKey(T::Key),
+	/// Replies [`TableReply::Records`].
+	Find(T::Category),
+	/// Replies [`TableReply::Count`].
+	Len,
+	/// Replies [`TableReply::Applied`], see [`MicroTable::apply_op`].
+	Apply(TableOp<T>),
+}
+
+impl<T: MicroRecord + fmt::Debug> fmt::Debug for TableCommand<T>
+where T::Key: fmt::Debug, T::Category: fmt::Debug {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Get(key) => f.debug_tuple("Get").field(key).finish(),
+			Self::ContainsKey(key) => f.debug_tuple("ContainsKey").field(key).finish(),
+			Self::Find(cat) => f.debug_tuple("Find").field(cat).finish(),
+			Self::Len => f.write_str("Len"),
+			Self::Apply(op) => f.debug_tuple("Apply").field(op).finish(),
+		}
+	}
+}
+
+/// The reply to a [`TableCommand`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableReply<T: MicroRecord> {
+	Record(Option<T>),
+	Found(bool),
+	Records(Vec<T>),
+	Count(usize),
+	Applied(Result<(), KeyError<T::Key>>),
+}
+
+/// The actor has stopped, so the command wasn't run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stopped;
+
+impl fmt::Display for Stopped {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("the table actor has stopped")
+	}
+}
+
+impl std::error::Error for Stopped {}
+
+enum Message<T: MicroRecord> {
+	Command(TableCommand<T>, oneshot::Sender<TableReply<T>>),
+	Stop,
+}
+
+/// Sends commands to a [`TableActor`]. Clones send to the same actor.
+pub struct TableHandle<T: MicroRecord> {
+	sender: mpsc::UnboundedSender<Message<T>>,
+}
+
+impl<T: MicroRecord> Clone for TableHandle<T> {
+	fn clone(&self) -> Self {
+		Self { sender: self.sender.clone() }
+	}
+}
+
+impl<T: MicroRecord> TableHandle<T> {
+	fn post(&self, command: TableCommand<T>) -> Result<oneshot::Receiver<TableReply<T>>, Stopped> {
+		let (reply, receiver) = oneshot::channel();
+		self.sender.send(Message::Command(command, reply)).map_err(|_| Stopped)?;
+		Ok(receiver)
+	}
+
+	/// Runs the command after the ones sent before it, and waits for the reply.
+	pub async fn send(&self, command: TableCommand<T>) -> Result<TableReply<T>, Stopped> {
+		self.post(command)?.await.map_err(|_| Stopped)
+	}
+
+	/// [`TableHandle::send`] for code that isn't async. It blocks the thread, so it panics in an async context.
+	pub fn send_blocking(&self, command: TableCommand<T>) -> Result<TableReply<T>, Stopped> {
+		self.post(command)?.blocking_recv().map_err(|_| Stopped)
+	}
+}
+
+/// The thread that owns a table, see the [module docs](crate::actor).
+pub struct TableActor<T: MicroRecord, H = crate::RandomState> {
+	handle: TableHandle<T>,
+	thread: JoinHandle<MicroTable<T, H>>,
+}
+
+impl<T, H> TableActor<T, H>
+where T: MicroRecord + Clone + Send + 'static, T::Key: Send + Sync, T::Category: Send + Sync, H: BuildHasher + Clone + Send + Sync + 'static,
+	// with `imbl` the records are in chunks shared by `Arc`, so it takes `T: Sync` too
+	MicroTable<T, H>: Send {
+	/// Moves the table to a new thread, which runs commands until [`TableActor::stop`].
+	pub fn spawn(table: MicroTable<T, H>) -> Self {
+		let (sender, mut receiver) = mpsc::unbounded_channel();
+		let thread = std::thread::spawn(move || {
+			let mut table = table;
+			while let Some(Message::Command(command, reply)) = receiver.blocking_recv() {
+				// the sender may have stopped waiting, which is its business
+				let _ = reply.send(table.execute(command));
+			}
+			table
+		});
+		Self { handle: TableHandle { sender }, thread }
+	}
+
+	pub fn handle(&self) -> TableHandle<T> {
+		self.handle.clone()
+	}
+
+	/// Runs the commands sent so far, stops the thread and returns the table. Later commands fail with [`Stopped`].
+	pub fn stop(self) -> MicroTable<T, H> {
+		// a closed channel means the thread is gone already, and join says why
+		let _ = self.handle.sender.send(Message::Stop);
+		self.thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Runs a command of a [`TableActor`] on this table.
+	pub fn execute(&mut self, command: TableCommand<T>) -> TableReply<T>
+	where T: Clone {
+		match command {
+			TableCommand::Get(key) => TableReply::Record(self.get(&key).cloned()),
+			TableCommand::ContainsKey(key) => TableReply::Found(self.contains_key(&key)),
+			TableCommand::Find(cat) => TableReply::Records(self.find_iter(&cat).cloned().collect()),
+			TableCommand::Len => TableReply::Count(self.len()),
+			TableCommand::Apply(op) => TableReply::Applied(self.apply_op(op)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Device {
+		id: u32,
+		room: u8,
+	}
+
+	impl MicroRecord for Device {
+		type Key = u32;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.room]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn commands_from_threads() {
+		let actor = TableActor::spawn(MicroTable::<Device>::new());
+		std::thread::scope(|s| {
+			for room in 0..4 {
+				let devices = actor.handle();
+				s.spawn(move || {
+					for id in 0..25 {
+						let insert = TableCommand::Apply(TableOp::Insert(Device { id: room as u32 * 100 + id, room }));
+						assert_eq!(devices.send_blocking(insert), Ok(TableReply::Applied(Ok(()))));
+					}
+				});
+			}
+		});
+		let devices = actor.handle();
+		assert_eq!(devices.send_blocking(TableCommand::Len), Ok(TableReply::Count(100)));
+		// as it comes over the wire
+		let command = serde_json::from_str(r#"{"Apply":{"Upsert":[101,{"id":101,"room":3}]}}"#).unwrap();
+		assert_eq!(devices.send_blocking(command), Ok(TableReply::Applied(Ok(()))));
+		let Ok(TableReply::Records(in_room)) = devices.send_blocking(TableCommand::Find(3)) else { panic!() };
+		assert_eq!(in_room.len(), 26);
+		let remove = TableCommand::Apply(TableOp::Remove(1000));
+		assert_eq!(devices.send_blocking(remove), Ok(TableReply::Applied(Ok(()))));
+		let insert = TableCommand::Apply(TableOp::Insert(Device { id: 101, room: 0 }));
+		assert_eq!(devices.send_blocking(insert), Ok(TableReply::Applied(Err(KeyError::Collision))));
+
+		let table = actor.stop();
+		assert_eq!(table.get(&101), Some(&Device { id: 101, room: 3 }));
+		assert_eq!(devices.send_blocking(TableCommand::ContainsKey(101)), Err(Stopped));
+	}
+
+	#[tokio::test]
+	async fn commands_from_tasks() {
+		let actor = TableActor::spawn(MicroTable::<Device>::new());
+		let devices = actor.handle();
+		devices.send(TableCommand::Apply(TableOp::Insert(Device { id: 1, room: 2 }))).await.unwrap();
+		assert_eq!(devices.send(TableCommand::Get(1)).await, Ok(TableReply::Record(Some(Device { id: 1, room: 2 }))));
+		assert_eq!(devices.send(TableCommand::ContainsKey(2)).await, Ok(TableReply::Found(false)));
+	}
+}
@@ -0,0 +1,195 @@
+//! Zero-copy archives with rkyv: [`MicroTable::to_rkyv`] writes the records, keys and category index into
+//! one buffer, and [`RkyvTable::open`] queries the buffer in place, returning the archived records without
+//! deserializing anything. The buffer is validated once, on open.
+//!
+//! Keys and categories are found through hash tables hashed with rkyv's `FxHasher64` of their `Hash`,
+//! so an archive is read on platforms whose `Hash` of the keys matches the writer's (e.g. same pointer width for `usize` keys).
+
+use core::{hash::Hash, marker::PhantomData};
+use alloc::vec::Vec;
+use rkyv::{Archive, Archived, Serialize, api::high::{HighSerializer, HighValidator}, bytecheck::CheckBytes, hash::{FxHasher64, hash_value}, rancor::Error, ser::allocator::ArenaHandle, util::AlignedVec, with::{Inline, Map}};
+use crate::{MicroRecord, MicroTable, probe};
+
+#[derive(Archive, Serialize)]
+struct Repr<'a, T: Archive, K, C> {
+	#[rkyv(with = Map<Inline>)]
+	records: Vec<&'a T>,
+	keys: Vec<K>,
+	/// Open addressing table of record numbers + 1 (0 is empty).
+	key_table: Vec<u32>,
+	cats: Vec<C>,
+	/// Open addressing table of category numbers + 1.
+	cat_table: Vec<u32>,
+	/// Where each category's record numbers start in `postings`, and the end.
+	offsets: Vec<u32>,
+	postings: Vec<u32>,
+}
+
+/// [`probe::build`] of the items, hashed with `FxHasher64`.
+fn hash_table<Q: Hash>(items: &[Q]) -> Vec<u32> {
+	probe::build(items.iter().map(hash_value::<Q, FxHasher64>)).into_iter().map(|e| e as u32).collect()
+}
+
+/// Finds the item number in an archived hash table.
+fn lookup<Q: Hash, A: PartialEq<Q>>(table: &[Archived<u32>], items: &[A], item: &Q) -> Option<usize> {
+	probe::find(table.len(), hash_value::<Q, FxHasher64>(item), |s| table[s].to_native() as usize, |i| items[i] == *item)
+}
+
+type Serializer<'a> = HighSerializer<AlignedVec, ArenaHandle<'a>, Error>;
+
+impl<T: MicroRecord, H: core::hash::BuildHasher + Clone> MicroTable<T, H> {
+	/// Archives the table into a buffer for [`RkyvTable::open`]. Buckets, partial indexes and TTLs aren't stored.
+	pub fn to_rkyv(&self) -> Result<AlignedVec, Error>
+	where
+		T: Archive + for<'a> Serialize<Serializer<'a>>,
+		T::Key: Archive + for<'a> Serialize<Serializer<'a>>,
+		T::Category: Archive + for<'a> Serialize<Serializer<'a>>,
+	{
+		// records are numbered without the empty slots
+		let mut numbers = vec![u32::MAX; self.data.iter_slots().last().map_or(0, |(slot, ..)| slot + 1)];
+		let (mut records, mut keys) = (Vec::with_capacity(self.len()), Vec::with_capacity(self.len()));
+		for (i, (slot, key, val)) in self.data.iter_slots().enumerate() {
+			numbers[slot] = i as u32;
+			records.push(val);
+			keys.push(key.clone());
+		}
+		let (mut cats, mut offsets, mut postings) = (Vec::with_capacity(self.index.len()), vec![0], vec![]);
+		for (cat, slots) in self.index.iter() {
+			cats.push(cat.clone());
+			postings.extend(slots.iter().map(|s| numbers[s as usize]));
+			offsets.push(postings.len() as u32);
+		}
+		let (key_table, cat_table) = (hash_table(&keys), hash_table(&cats));
+		rkyv::to_bytes::<Error>(&Repr { records, keys, key_table, cats, cat_table, offsets, postings })
+	}
+}
+
+/// Read-only table over a buffer made by [`MicroTable::to_rkyv`]. Queries return the records' archived form,
+/// `Archived<T>`, which is read in place. The buffer must be aligned as rkyv requires, e.g. an `AlignedVec` or a memory map.
+pub struct RkyvTable<'a, T: MicroRecord + Archive>
+where T::Key: Archive, T::Category: Archive {
+	repr: &'a ArchivedRepr<'a, T, T::Key, T::Category>,
+	record: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: MicroRecord + Archive> RkyvTable<'a, T>
+where
+	T::Key: Archive,
+	T::Category: Archive,
+	Archived<T>: for<'b> CheckBytes<HighValidator<'b, Error>>,
+	Archived<T::Key>: for<'b> CheckBytes<HighValidator<'b, Error>> + PartialEq<T::Key>,
+	Archived<T::Category>: for<'b> CheckBytes<HighValidator<'b, Error>> + PartialEq<T::Category>,
+{
+	/// Checks that the buffer holds a valid archive, and opens it.
+	pub fn open(bytes: &'a [u8]) -> Result<Self, Error> {
+		let repr = rkyv::access::<ArchivedRepr<'a, T, T::Key, T::Category>, Error>(bytes)?;
+		// hash tables and postings refer to entries by number, so they must stay in range
+		let (len, cats) = (repr.records.len(), repr.cats.len());
+		let fits = |table: &[Archived<u32>], n: usize| table.len().is_power_of_two() && table.len() > n && table.iter().all(|e| e.to_native() as usize <= n);
+		let offsets_ok = repr.offsets.len() == cats + 1 && repr.offsets.windows(2).all(|w| w[0] <= w[1]) && repr.offsets[cats].to_native() as usize == repr.postings.len();
+		if repr.keys.len() != len || !fits(&repr.key_table, len) || !fits(&repr.cat_table, cats) || !offsets_ok || repr.postings.iter().any(|p| p.to_native() as usize >= len) {
+			return Err(<Error as rkyv::rancor::Source>::new(InvalidArchive));
+		}
+		Ok(Self { repr, record: PhantomData })
+	}
+
+	pub fn len(&self) -> usize {
+		self.repr.records.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.repr.records.is_empty()
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		lookup(&self.repr.key_table, &self.repr.keys, key).is_some()
+	}
+
+	pub fn contains_cat(&self, cat: &T::Category) -> bool {
+		lookup(&self.repr.cat_table, &self.repr.cats, cat).is_some()
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<&'a Archived<T>> {
+		lookup(&self.repr.key_table, &self.repr.keys, key).map(|i| &self.repr.records[i])
+	}
+
+	pub fn find_iter(&self, cat: &T::Category) -> impl Iterator<Item = &'a Archived<T>> + 'a {
+		let repr = self.repr;
+		let range = lookup(&repr.cat_table, &repr.cats, cat).map_or(0..0, |c| repr.offsets[c].to_native() as usize..repr.offsets[c + 1].to_native() as usize);
+		repr.postings[range].iter().map(move |i| &repr.records[i.to_native() as usize])
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<&'a Archived<T>> {
+		self.find_iter(cat).collect()
+	}
+
+	pub fn values(&self) -> impl Iterator<Item = &'a Archived<T>> + 'a {
+		self.repr.records.iter()
+	}
+
+	pub fn iter_keys(&self) -> impl Iterator<Item = &'a Archived<T::Key>> + 'a {
+		self.repr.keys.iter()
+	}
+
+	pub fn iter_cats(&self) -> impl Iterator<Item = &'a Archived<T::Category>> + 'a {
+		self.repr.cats.iter()
+	}
+}
+
+#[derive(Debug)]
+struct InvalidArchive;
+
+impl core::fmt::Display for InvalidArchive {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.write_str("inconsistent table archive")
+	}
+}
+
+impl std::error::Error for InvalidArchive {}
+
+impl<T: MicroRecord + Archive> core::fmt::Debug for RkyvTable<'_, T>
+where T::Key: Archive, T::Category: Archive {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("RkyvTable").field("len", &self.repr.records.len()).field("categories", &self.repr.cats.len()).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+
+	#[derive(Debug, Archive, Serialize)]
+	struct Station {
+		id: u32,
+		line: String,
+		name: String,
+	}
+
+	impl MicroRecord for Station {
+		type Key = u32;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.line.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn archive_and_query() {
+		let mut stations: MicroTable<Station> = (0..20).map(|id| Station { id, line: format!("L{}", id % 3), name: format!("s{id}") }).collect();
+		stations.remove(&4);
+		let bytes = stations.to_rkyv().unwrap();
+		let archived = RkyvTable::<Station>::open(&bytes).unwrap();
+		assert_eq!(archived.len(), 19);
+		assert_eq!(archived.get(&7).map(|s| s.name.as_str()), Some("s7"));
+		assert!(archived.get(&4).is_none() && !archived.contains_key(&40));
+		let mut on_l1: Vec<u32> = archived.find_iter(&"L1".into()).map(|s| s.id.to_native()).collect();
+		on_l1.sort();
+		assert_eq!(on_l1, vec![1, 7, 10, 13, 16, 19]);
+		assert!(archived.find(&"L9".into()).is_empty() && archived.contains_cat(&"L2".into()));
+		assert!(RkyvTable::<Station>::open(&bytes[..bytes.len() / 2]).is_err());
+	}
+}
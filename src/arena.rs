@@ -0,0 +1,111 @@
+//! Tables of records allocated in a bump arena, for tables rebuilt wholesale, e.g. every tick: the records are moved
+//! into a [`Bump`] next to each other and the table holds an [`ArenaRef`] to each, so loading a record is a pointer bump,
+//! and dropping the table and [`Bump::reset`] free them all at once. The borrow of the arena keeps it from being
+//! reset while a table still refers to it.
+//!
+//! Only the records are packed in the arena: the table keeps the references in its own storage, with the keys, in
+//! slots like any table, so that storage is allocated and freed as usual.
+//!
+//! The arena doesn't run destructors, so heap memory a record owns (a `String`, a `Vec`) isn't freed by the reset.
+//! It suits records of plain data, or of bumpalo's own collections allocated in the same arena.
+
+use core::{hash::BuildHasher, ops::Deref};
+use bumpalo::Bump;
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Record in an arena, as an [`ArenaTable`] stores it. It derefs to the record.
+#[derive(Debug, PartialEq)]
+pub struct ArenaRef<'a, T>(&'a T);
+
+impl<'a, T> ArenaRef<'a, T> {
+	/// The record, borrowed from the arena rather than from the table.
+	pub fn get(self) -> &'a T {
+		self.0
+	}
+}
+
+impl<T> Clone for ArenaRef<'_, T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T> Copy for ArenaRef<'_, T> {}
+
+impl<T> Deref for ArenaRef<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		self.0
+	}
+}
+
+/// Records in an arena are indexed as the records themselves.
+impl<T: MicroRecord> MicroRecord for ArenaRef<'_, T> {
+	type Key = T::Key;
+	type Category = T::Category;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.0.categories()
+	}
+	fn key(&self) -> Self::Key {
+		self.0.key()
+	}
+	fn buckets(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.0.buckets()
+	}
+	fn heap_size(&self) -> usize {
+		core::mem::size_of::<T>() + self.0.heap_size()
+	}
+}
+
+/// Table of records in a [`Bump`] arena, see the [module docs](crate::arena).
+pub type ArenaTable<'a, T, H = RandomState> = MicroTable<ArenaRef<'a, T>, H>;
+
+impl<'a, T: MicroRecord, H: BuildHasher + Clone> MicroTable<ArenaRef<'a, T>, H> {
+	/// Moves the records into the arena and inserts them, like [`MicroTable::try_extend`]. A record that fails
+	/// stays in the arena until it's reset.
+	pub fn extend_in(&mut self, arena: &'a Bump, vals: impl IntoIterator<Item = T>) -> Result<(), KeyError<T::Key>> {
+		self.try_extend(vals.into_iter().map(|val| ArenaRef(&*arena.alloc(val))))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, Copy, PartialEq)]
+	struct Unit {
+		id: u32,
+		team: u8,
+		x: f32,
+	}
+
+	impl MicroRecord for Unit {
+		type Key = u32;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.team]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn rebuild_every_tick() {
+		let mut arena = Bump::new();
+		for tick in 0..3 {
+			let mut units = ArenaTable::<Unit>::new();
+			units.extend_in(&arena, (0..100).map(|id| Unit { id, team: (id % 4) as u8, x: tick as f32 })).unwrap();
+			assert_eq!((units.len(), units.find(&2).len()), (100, 25));
+			assert_eq!(units.get(&7).map(|u| u.x), Some(tick as f32));
+			let unit: &Unit = units.get(&7).copied().unwrap().get();
+			assert_eq!(unit.id, 7);
+			// the records are packed in the arena
+			assert!(arena.allocated_bytes() >= 100 * core::mem::size_of::<Unit>());
+			assert_eq!(units.extend_in(&arena, [Unit { id: 7, team: 0, x: 0.0 }]), Err(KeyError::Collision));
+			drop(units);
+			arena.reset();
+		}
+	}
+}
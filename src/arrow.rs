@@ -0,0 +1,72 @@
+//! Export to Apache Arrow through serde_arrow: records become the rows of `RecordBatch`es, their fields the
+//! columns. The schema is traced from the records, so nullable fields and enums come out as they're used.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use arrow_array::RecordBatch;
+use arrow_schema::FieldRef;
+use serde::Serialize;
+use serde_arrow::schema::{SchemaLike, TracingOptions};
+use crate::{MicroRecord, MicroTable};
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Arrow fields of the records, traced from all of them. Fails for an empty table, which has nothing to trace.
+	pub fn arrow_fields(&self) -> serde_arrow::Result<Vec<FieldRef>>
+	where T: Serialize {
+		Vec::<FieldRef>::from_samples(self.values().collect::<Vec<_>>(), TracingOptions::default())
+	}
+
+	/// All the records as one batch.
+	pub fn to_record_batch(&self) -> serde_arrow::Result<RecordBatch>
+	where T: Serialize {
+		let records: Vec<&T> = self.values().collect();
+		serde_arrow::to_record_batch(&self.arrow_fields()?, &records)
+	}
+
+	/// The records in batches of up to `batch_size` rows, with one schema.
+	pub fn to_record_batches(&self, batch_size: usize) -> serde_arrow::Result<Vec<RecordBatch>>
+	where T: Serialize {
+		assert!(batch_size > 0, "batch size must be positive");
+		let fields = self.arrow_fields()?;
+		let records: Vec<&T> = self.values().collect();
+		records.chunks(batch_size).map(|chunk| serde_arrow::to_record_batch(&fields, &chunk)).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+	use arrow_array::Array;
+
+	#[derive(Debug, Serialize)]
+	struct Trip {
+		id: u32,
+		route: String,
+		minutes: f64,
+		note: Option<String>,
+	}
+
+	impl MicroRecord for Trip {
+		type Key = u32;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.route.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn record_batches() {
+		let trips: MicroTable<Trip> = (0..10).map(|id| Trip { id, route: format!("r{}", id % 2), minutes: id as f64 * 1.5, note: (id == 3).then(|| "late".into()) }).collect();
+		let batch = trips.to_record_batch().unwrap();
+		assert_eq!((batch.num_rows(), batch.num_columns()), (10, 4));
+		assert_eq!(batch.schema().field(0).name(), "id");
+		assert_eq!(batch.column_by_name("note").unwrap().null_count(), 9);
+		let batches = trips.to_record_batches(4).unwrap();
+		assert_eq!(batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(), vec![4, 4, 2]);
+		assert!(MicroTable::<Trip>::new().to_record_batch().is_err());
+	}
+}
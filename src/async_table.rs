@@ -0,0 +1,271 @@
+//! Tables for async code, behind tokio's `RwLock`: waiting for the lock yields to other tasks instead of blocking
+//! the thread. Changes and compound reads take closures, which can't `.await`, so the lock is never held across
+//! an await point by accident.
+//!
+//! [`AsyncTable::watch`] gives a channel of the record of a key, which gets every change to it, and
+//! [`AsyncTable::subscribe_cat`] a channel of the [`CatEvent`]s of a category.
+
+use core::hash::BuildHasher;
+use core::cell::RefCell;
+use alloc::{boxed::Box, vec, vec::Vec};
+use std::{collections::HashMap, sync::{Mutex, PoisonError}};
+use tokio::sync::{RwLock, mpsc, watch};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// A [`MicroTable`] behind an async lock, see the [module docs](crate::async_table).
+pub struct AsyncTable<T: MicroRecord, H = RandomState> {
+	table: RwLock<MicroTable<T, H>>,
+	/// Senders of [`AsyncTable::watch`] and [`AsyncTable::subscribe_cat`] channels, called after every change with
+	/// the keys it changed, or `None` if it may have changed any. They return false once their receivers are dropped.
+	watchers: Mutex<Vec<Watcher<T, H>>>,
+}
+
+type Watcher<T, H> = Box<dyn FnMut(&MicroTable<T, H>, Option<&[<T as MicroRecord>::Key]>) -> bool + Send + Sync>;
+
+/// A change to the records of a category, from [`AsyncTable::subscribe_cat`]. A change of key is the removal of
+/// the record under the old key and the addition under the new one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CatEvent<T> {
+	Added(T),
+	Updated { old: T, new: T },
+	Removed(T),
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for AsyncTable<T, H> {
+	fn default() -> Self {
+		Self::new(MicroTable::default())
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> AsyncTable<T, H> {
+	pub fn new(table: MicroTable<T, H>) -> Self {
+		Self { table: RwLock::new(table), watchers: Mutex::new(Vec::new()) }
+	}
+
+	pub fn into_inner(self) -> MicroTable<T, H> {
+		self.table.into_inner()
+	}
+
+	/// Runs `f` under the read lock, so its queries all see the same state of the table.
+	pub async fn read<R>(&self, f: impl FnOnce(&MicroTable<T, H>) -> R) -> R {
+		f(&*self.table.read().await)
+	}
+
+	/// Runs `f` under the write lock, for changes that go together. The watchers get the changes after `f` returns.
+	pub async fn write<R>(&self, f: impl FnOnce(&mut MicroTable<T, H>) -> R) -> R {
+		self.change(|t| (f(t), None)).await
+	}
+
+	/// Runs `f` under the write lock, and passes the keys it returns as changed to the watchers.
+	async fn change<R>(&self, f: impl FnOnce(&mut MicroTable<T, H>) -> (R, Option<Vec<T::Key>>)) -> R {
+		let mut table = self.table.write().await;
+		let (result, keys) = f(&mut table);
+		self.watchers.lock().unwrap_or_else(PoisonError::into_inner).retain_mut(|send| send(&table, keys.as_deref()));
+		result
+	}
+
+	/// A channel with the record of the key, or `None` while there's none, which gets a new value whenever a change
+	/// leaves the key with another one. Every change looks up the watched keys, so watch only a few at a time, and
+	/// drop the receivers that aren't needed anymore.
+	pub async fn watch(&self, key: T::Key) -> watch::Receiver<Option<T>>
+	where T: Clone + PartialEq + Send + Sync + 'static, T::Key: Send + Sync + 'static, H: 'static {
+		let table = self.table.read().await;
+		let (sender, receiver) = watch::channel(table.get(&key).cloned());
+		self.watchers.lock().unwrap_or_else(PoisonError::into_inner).push(Box::new(move |table: &MicroTable<T, H>, keys: Option<&[T::Key]>| {
+			if keys.is_some_and(|keys| !keys.contains(&key)) {
+				return !sender.is_closed();
+			}
+			sender.send_if_modified(|current| {
+				let val = table.get(&key);
+				let changed = current.as_ref() != val;
+				if changed {
+					*current = val.cloned();
+				}
+				changed
+			});
+			!sender.is_closed()
+		}));
+		receiver
+	}
+
+	/// A channel of the changes to the records of the category, from now on. Each subscription keeps copies of
+	/// the records of its category, to tell what changed; a change made by [`AsyncTable::write`] is found by
+	/// comparing all of them.
+	pub async fn subscribe_cat(&self, cat: T::Category) -> mpsc::UnboundedReceiver<CatEvent<T>>
+	where T: Clone + PartialEq + Send + Sync + 'static, T::Key: Send + Sync + 'static, T::Category: Send + Sync + 'static, H: 'static {
+		let table = self.table.read().await;
+		let mut members: HashMap<T::Key, T> = table.find_iter(&cat).map(|val| (val.key(), val.clone())).collect();
+		let (sender, receiver) = mpsc::unbounded_channel();
+		self.watchers.lock().unwrap_or_else(PoisonError::into_inner).push(Box::new(move |table: &MicroTable<T, H>, keys: Option<&[T::Key]>| {
+			let keys: Vec<T::Key> = match keys {
+				Some(keys) => keys.to_vec(),
+				None => members.keys().cloned().chain(table.find_iter(&cat).map(|val| val.key())).collect(),
+			};
+			for key in keys {
+				let new = table.get(&key).filter(|val| val.categories().into_iter().any(|c| c == cat));
+				let event = match (members.get(&key), new) {
+					(None, Some(new)) => CatEvent::Added(new.clone()),
+					(Some(old), Some(new)) if old != new => CatEvent::Updated { old: old.clone(), new: new.clone() },
+					(Some(_), None) => CatEvent::Removed(members.remove(&key).expect("matched above")),
+					_ => continue,
+				};
+				if let CatEvent::Added(new) | CatEvent::Updated { new, .. } = &event {
+					members.insert(key, new.clone());
+				}
+				if sender.send(event).is_err() {
+					return false;
+				}
+			}
+			!sender.is_closed()
+		}));
+		receiver
+	}
+
+	pub async fn len(&self) -> usize {
+		self.read(|t| t.len()).await
+	}
+
+	pub async fn is_empty(&self) -> bool {
+		self.read(|t| t.is_empty()).await
+	}
+
+	pub async fn contains_key(&self, key: &T::Key) -> bool {
+		self.read(|t| t.contains_key(key)).await
+	}
+
+	/// A copy of the record.
+	pub async fn get(&self, key: &T::Key) -> Option<T>
+	where T: Clone {
+		self.read(|t| t.get(key).cloned()).await
+	}
+
+	/// Copies of the records of the category.
+	pub async fn find(&self, cat: &T::Category) -> Vec<T>
+	where T: Clone {
+		self.read(|t| t.find_iter(cat).cloned().collect()).await
+	}
+
+	pub async fn insert(&self, val: T) -> Result<(), KeyError<T::Key>> {
+		let key = val.key();
+		self.change(|t| (t.insert(val), Some(vec![key]))).await
+	}
+
+	pub async fn upsert(&self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
+		let new_key = new_val.key();
+		self.change(|t| (t.upsert(key.clone(), new_val), Some(vec![key, new_key]))).await
+	}
+
+	pub async fn update_with(&self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let new_key = RefCell::new(None);
+		self.change(|t| {
+			let result = t.update_with(old_key.clone(), &|val: &mut T| {
+				cb(val);
+				*new_key.borrow_mut() = Some(val.key());
+			});
+			(result, Some([old_key].into_iter().chain(new_key.into_inner()).collect()))
+		}).await
+	}
+
+	pub async fn remove(&self, key: &T::Key) -> Option<T> {
+		self.change(|t| (t.remove(key), Some(vec![key.clone()]))).await
+	}
+
+	pub async fn remove_cat(&self, cat: &T::Category) -> Vec<T> {
+		self.change(|t| {
+			let removed = t.remove_cat(cat);
+			let keys = removed.iter().map(|val| val.key()).collect();
+			(removed, Some(keys))
+		}).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Item;
+	use alloc::sync::Arc;
+
+	#[tokio::test]
+	async fn tasks_share_a_table() {
+		let jobs = Arc::new(AsyncTable::<Item>::default());
+		let tasks: Vec<_> = (0..4).map(|t| {
+			let jobs = Arc::clone(&jobs);
+			tokio::spawn(async move {
+				for id in (t * 10)..((t + 1) * 10) {
+					jobs.insert(Item { id, kind: "queued" }).await.unwrap();
+					tokio::task::yield_now().await;
+				}
+				jobs.update_with(t * 10, &|j| j.kind = "running").await.unwrap();
+			})
+		}).collect();
+		for task in tasks {
+			task.await.unwrap();
+		}
+		assert_eq!((jobs.len().await, jobs.find(&"running").await.len()), (40, 4));
+		assert_eq!(jobs.insert(Item { id: 3, kind: "queued" }).await, Err(KeyError::Collision));
+		assert_eq!(jobs.get(&10).await.map(|j| j.kind), Some("running"));
+
+		// take the next queued job, atomically
+		let next = jobs.write(|t| {
+			let id = t.find_iter(&"queued").map(|j| j.id).min()?;
+			t.update_with(id, &|j| j.kind = "running").ok()?;
+			Some(id)
+		}).await;
+		assert_eq!(next, Some(1));
+		assert_eq!(jobs.remove_cat(&"running").await.len(), 5);
+		assert_eq!(jobs.read(|t| t.find(&"queued").len()).await, 35);
+		assert!(jobs.contains_key(&2).await && jobs.remove(&2).await.is_some());
+		assert!(!Arc::into_inner(jobs).unwrap().into_inner().is_empty());
+	}
+
+	#[tokio::test]
+	async fn watch_a_key() {
+		let jobs = AsyncTable::<Item>::default();
+		let mut first = jobs.watch(1).await;
+		let other = jobs.watch(2).await;
+		assert_eq!(*first.borrow_and_update(), None);
+
+		jobs.insert(Item { id: 1, kind: "queued" }).await.unwrap();
+		assert!(first.has_changed().unwrap());
+		assert_eq!(first.borrow_and_update().as_ref().map(|j| j.kind), Some("queued"));
+		jobs.update_with(1, &|j| j.kind = "running").await.unwrap();
+		first.changed().await.unwrap();
+		assert_eq!(first.borrow_and_update().as_ref().map(|j| j.kind), Some("running"));
+		// the same record again isn't a change
+		jobs.upsert(1, Item { id: 1, kind: "running" }).await.unwrap();
+		assert!(!first.has_changed().unwrap());
+		jobs.write(|t| t.update_with(1, &|j| j.id = 5)).await.unwrap();
+		assert_eq!(*first.borrow_and_update(), None);
+		assert!(!other.has_changed().unwrap());
+
+		drop((first, other));
+		jobs.remove(&5).await;
+		assert!(jobs.watchers.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn subscribe_to_a_category() {
+		let jobs = AsyncTable::<Item>::default();
+		jobs.insert(Item { id: 1, kind: "queued" }).await.unwrap();
+		let mut running = jobs.subscribe_cat("running").await;
+		jobs.insert(Item { id: 2, kind: "queued" }).await.unwrap();
+		jobs.update_with(1, &|j| j.kind = "running").await.unwrap();
+		jobs.update_with(1, &|j| j.id = 3).await.unwrap();
+		jobs.write(|t| t.update_with(2, &|j| j.kind = "running")).await.unwrap();
+		jobs.remove_cat(&"running").await;
+
+		let mut events = vec![];
+		while let Ok(event) = running.try_recv() {
+			events.push(event);
+		}
+		let job = |id, kind| Item { id, kind };
+		assert_eq!(events[..4], [
+			CatEvent::Added(job(1, "running")),
+			CatEvent::Removed(job(1, "running")),
+			CatEvent::Added(job(3, "running")),
+			CatEvent::Added(job(2, "running")),
+		]);
+		assert!(events[4..].contains(&CatEvent::Removed(job(2, "running"))) && events.len() == 6);
+	}
+}
@@ -0,0 +1,206 @@
+//! Compact binary snapshots: records encoded with postcard, between a header and a checksum, so a truncated
+//! or damaged file fails on load with a clear error instead of loading part of the table.
+//!
+//! Layout: the magic bytes, the format version and the record count (`u32`, `u64`, little-endian), then every
+//! record as a `u32` length and its postcard bytes, then the FNV-1a hash of all these lengths and bytes (`u64`).
+
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, vec::Vec};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{IndexPresize, MicroRecord, MicroTable, RecordError, SkippedRecord, fnv1a, seed::MAX_PRESIZE};
+
+const MAGIC: [u8; 8] = *b"MTSNAP\0\0";
+/// Version of the layout, not of the records.
+const VERSION: u32 = 1;
+
+/// Writer that hashes what goes through it.
+struct Hashing<W> {
+	inner: W,
+	hash: u64,
+}
+
+impl<W: Write> Hashing<W> {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+		self.hash = fnv1a(self.hash, bytes);
+		self.inner.write_all(bytes)
+	}
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+	let mut buf = [0; N];
+	r.read_exact(&mut buf).map_err(|e| if e.kind() == io::ErrorKind::UnexpectedEof { io::Error::new(e.kind(), "truncated snapshot") } else { e })?;
+	Ok(buf)
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Writes a binary snapshot of the records, see the [layout](crate::binary).
+	pub fn save_snapshot(&self, writer: impl Write) -> io::Result<()>
+	where T: Serialize {
+		let mut w = BufWriter::new(writer);
+		w.write_all(&MAGIC)?;
+		w.write_all(&VERSION.to_le_bytes())?;
+		w.write_all(&(self.len() as u64).to_le_bytes())?;
+		let mut w = Hashing { inner: w, hash: crate::FNV_OFFSET };
+		for val in self.values() {
+			let bytes = postcard::to_allocvec(val).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+			let len = u32::try_from(bytes.len()).map_err(|_| invalid("record longer than 4 GiB"))?;
+			w.write(&len.to_le_bytes())?;
+			w.write(&bytes)?;
+		}
+		let (mut w, hash) = (w.inner, w.hash);
+		w.write_all(&hash.to_le_bytes())?;
+		w.flush()
+	}
+
+	/// Reads a snapshot written by [`MicroTable::save_snapshot`]. Fails if the header, a record or the checksum
+	/// doesn't match, or if two records have one key.
+	pub fn load_snapshot(reader: impl Read) -> io::Result<Self>
+	where T: DeserializeOwned, H: Default {
+		Self::read_snapshot(reader, None)
+	}
+
+	/// Like [`MicroTable::load_snapshot`], but skips the records that don't decode or that the table rejects,
+	/// and returns them with the table. The header and the checksum must still match.
+	pub fn load_snapshot_lenient(reader: impl Read) -> io::Result<(Self, Vec<SkippedRecord<T::Key>>)>
+	where T: DeserializeOwned, H: Default {
+		let mut skipped = vec![];
+		let table = Self::read_snapshot(reader, Some(&mut skipped))?;
+		Ok((table, skipped))
+	}
+
+	/// Reads a snapshot, failing at the first bad record unless there's a list for `skipped`.
+	fn read_snapshot(reader: impl Read, mut skipped: Option<&mut Vec<SkippedRecord<T::Key>>>) -> io::Result<Self>
+	where T: DeserializeOwned, H: Default {
+		let mut frames = Frames::open(reader)?;
+		// the count is only a hint until the checksum confirms it
+		let expected = frames.count.min(MAX_PRESIZE as u64) as usize;
+		let mut table = Self::with_capacity_and_hasher(expected, 0, H::default());
+		let mut presize = IndexPresize::new(&table, expected);
+		let mut buf = vec![];
+		for position in 0..frames.count {
+			presize.reserve(&mut table, position as usize);
+			buf.clear();
+			frames.next_into(&mut buf)?;
+			let error = match postcard::from_bytes::<T>(&buf) {
+				Ok(val) => match table.insert(val) {
+					Ok(()) => continue,
+					Err(e) => RecordError::Rejected(e),
+				},
+				Err(e) => RecordError::Decode(Box::new(e)),
+			};
+			match skipped.as_deref_mut() {
+				Some(skipped) => skipped.push(SkippedRecord { position, error }),
+				None => return Err(match error {
+					RecordError::Decode(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+					RecordError::Rejected(_) => invalid("duplicate key in snapshot"),
+				}),
+			}
+		}
+		frames.finish()?;
+		Ok(table)
+	}
+}
+
+/// Reader of the records of a snapshot, which hashes them for the checksum.
+pub(crate) struct Frames<R> {
+	r: BufReader<R>,
+	hash: u64,
+	/// Records in the snapshot, as the header says.
+	pub(crate) count: u64,
+}
+
+impl<R: Read> Frames<R> {
+	/// Reads and checks the header.
+	pub(crate) fn open(reader: R) -> io::Result<Self> {
+		let mut r = BufReader::new(reader);
+		if read_array(&mut r)? != MAGIC {
+			return Err(invalid("not a microtable snapshot"));
+		}
+		if u32::from_le_bytes(read_array(&mut r)?) != VERSION {
+			return Err(invalid("unsupported snapshot version"));
+		}
+		let count = u64::from_le_bytes(read_array(&mut r)?);
+		Ok(Self { r, hash: crate::FNV_OFFSET, count })
+	}
+
+	/// Appends the bytes of the next record to `buf`.
+	pub(crate) fn next_into(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+		let len = read_array::<4>(&mut self.r)?;
+		self.hash = fnv1a(self.hash, &len);
+		// read rather than allocated up front, so a damaged length can't ask for gigabytes
+		let len = u32::from_le_bytes(len) as u64;
+		let start = buf.len();
+		if (&mut self.r).take(len).read_to_end(buf)? as u64 != len {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated snapshot"));
+		}
+		self.hash = fnv1a(self.hash, &buf[start..]);
+		Ok(())
+	}
+
+	/// Checks the checksum, after the last record.
+	pub(crate) fn finish(mut self) -> io::Result<()> {
+		if u64::from_le_bytes(read_array(&mut self.r)?) != self.hash {
+			return Err(invalid("snapshot checksum mismatch"));
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Reading;
+	use serde::Deserialize;
+
+	#[test]
+	fn snapshot_integrity() {
+		let readings: MicroTable<Reading> = (0..50).map(|id| Reading { id, sensor: (id % 4) as u16, value: id as f32 / 2.0 }).collect();
+		let mut bytes = vec![];
+		readings.save_snapshot(&mut bytes).unwrap();
+		let back = MicroTable::<Reading>::load_snapshot(&bytes[..]).unwrap();
+		assert_eq!((back.len(), back.find(&1).len()), (50, 13));
+		assert_eq!(back.get(&7), readings.get(&7));
+
+		let truncated = MicroTable::<Reading>::load_snapshot(&bytes[..bytes.len() - 20]).unwrap_err();
+		assert_eq!(truncated.kind(), io::ErrorKind::UnexpectedEof);
+		let mut damaged = bytes.clone();
+		damaged[40] ^= 1;
+		assert!(MicroTable::<Reading>::load_snapshot(&damaged[..]).is_err());
+		assert!(MicroTable::<Reading>::load_snapshot(&b"not a snapshot at all"[..]).is_err());
+	}
+
+	#[test]
+	fn lenient_snapshot() {
+		#[derive(Deserialize)]
+		struct Checked {
+			id: u32,
+			sensor: core::num::NonZeroU16,
+			#[allow(dead_code)]
+			value: f32,
+		}
+
+		impl MicroRecord for Checked {
+			type Key = u32;
+			type Category = u16;
+			fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+				[self.sensor.get()]
+			}
+			fn key(&self) -> Self::Key {
+				self.id
+			}
+		}
+
+		let readings: MicroTable<Reading> = (0..20).map(|id| Reading { id, sensor: (id % 4) as u16, value: 0.5 }).collect();
+		let mut bytes = vec![];
+		readings.save_snapshot(&mut bytes).unwrap();
+		assert!(MicroTable::<Checked>::load_snapshot(&bytes[..]).is_err());
+		let (checked, skipped) = MicroTable::<Checked>::load_snapshot_lenient(&bytes[..]).unwrap();
+		assert_eq!((checked.len(), skipped.len()), (15, 5));
+		assert!(skipped.iter().all(|s| matches!(s.error, RecordError::Decode(_))));
+	}
+}
@@ -0,0 +1,193 @@
+//! Writes batched by each thread: a [`WriteBuffer`] holds the inserts, upserts and removes of a thread, without any
+//! lock, and [`WriteBuffer::flush`] applies them to a [`ConcurrentTable`] with each shard they touch locked once,
+//! rather than once per write. Other threads see the writes only once flushed.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use crate::{ConcurrentTable, KeyError, MicroRecord, MicroTable, concurrent::ShardGuard};
+
+/// Writes of a thread waiting for a flush, see the [module docs](crate::buffer).
+pub struct WriteBuffer<T: MicroRecord> {
+	writes: Vec<Write<T>>,
+}
+
+enum Write<T: MicroRecord> {
+	Insert(T),
+	Upsert(T::Key, T),
+	Remove(T::Key),
+}
+
+impl<T: MicroRecord> Default for WriteBuffer<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T: MicroRecord> WriteBuffer<T> {
+	pub fn new() -> Self {
+		Self { writes: Vec::new() }
+	}
+
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self { writes: Vec::with_capacity(capacity) }
+	}
+
+	/// Writes waiting for a flush.
+	pub fn len(&self) -> usize {
+		self.writes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.writes.is_empty()
+	}
+
+	/// Drops the writes, without applying them.
+	pub fn clear(&mut self) {
+		self.writes.clear();
+	}
+
+	pub fn insert(&mut self, val: T) {
+		self.writes.push(Write::Insert(val));
+	}
+
+	pub fn upsert(&mut self, key: T::Key, new_val: T) {
+		self.writes.push(Write::Upsert(key, new_val));
+	}
+
+	/// Removes the record if it's there when flushed.
+	pub fn remove(&mut self, key: T::Key) {
+		self.writes.push(Write::Remove(key));
+	}
+
+	/// Applies the writes to the table in the order they were made, and returns the errors of the ones that failed,
+	/// with their positions in the buffer. A failed write leaves the table as it was, and the later ones still apply.
+	/// The buffer is empty afterwards, and keeps its capacity for the next batch.
+	pub fn flush_into<H: BuildHasher + Clone>(&mut self, table: &mut MicroTable<T, H>) -> Vec<(usize, KeyError<T::Key>)> {
+		let mut failed = Vec::new();
+		for (i, write) in self.writes.drain(..).enumerate() {
+			let result = match write {
+				Write::Insert(val) => table.insert(val),
+				Write::Upsert(key, val) => table.upsert(key, val),
+				Write::Remove(key) => {
+					table.remove(&key);
+					Ok(())
+				}
+			};
+			if let Err(e) = result {
+				failed.push((i, e));
+			}
+		}
+		failed
+	}
+
+	/// [`WriteBuffer::flush_into`] a [`ConcurrentTable`]. The shards the writes touch are all locked first, in their
+	/// order, and held until the last write, so other threads see either none of the batch or all of it.
+	pub fn flush<H: BuildHasher + Clone>(&mut self, table: &ConcurrentTable<T, H>) -> Vec<(usize, KeyError<T::Key>)> {
+		let mut touched = vec![false; table.shard_count()];
+		for write in &self.writes {
+			match write {
+				Write::Insert(val) => touched[table.shard_of(&val.key())] = true,
+				Write::Upsert(key, val) => {
+					touched[table.shard_of(key)] = true;
+					touched[table.shard_of(&val.key())] = true;
+				}
+				Write::Remove(key) => touched[table.shard_of(key)] = true,
+			}
+		}
+		let mut shards: Vec<Option<ShardGuard<'_, T, H>>> = touched.into_iter().enumerate()
+			.map(|(shard, touched)| touched.then(|| table.write_shard(shard))).collect();
+		let mut failed = Vec::new();
+		for (i, write) in self.writes.drain(..).enumerate() {
+			let result = match write {
+				Write::Insert(val) => locked(&mut shards, table.shard_of(&val.key())).insert(val),
+				Write::Upsert(key, val) => {
+					let (from, to) = (table.shard_of(&key), table.shard_of(&val.key()));
+					if from == to {
+						locked(&mut shards, from).upsert(key, val)
+					} else {
+						// the record keeps its TTL deadline in the new shard, as in `ConcurrentTable::upsert`
+						let expires = locked(&mut shards, from).deadline(&key);
+						locked(&mut shards, to).insert_with_deadline(val, expires).map(|()| { locked(&mut shards, from).remove(&key); })
+					}
+				}
+				Write::Remove(key) => {
+					locked(&mut shards, table.shard_of(&key)).remove(&key);
+					Ok(())
+				}
+			};
+			if let Err(e) = result {
+				failed.push((i, e));
+			}
+		}
+		failed
+	}
+}
+
+fn locked<'a, 'g, T: MicroRecord, H>(shards: &'a mut [Option<ShardGuard<'g, T, H>>], shard: usize) -> &'a mut MicroTable<T, H> {
+	shards[shard].as_mut().expect("locked above")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Account;
+
+	#[test]
+	fn producers_flush_batches() {
+		let events = ConcurrentTable::<Account>::with_shards(8);
+		std::thread::scope(|s| {
+			for source in 0..16 {
+				let events = &events;
+				s.spawn(move || {
+					let mut buffer = WriteBuffer::with_capacity(50);
+					for id in 0..200 {
+						buffer.insert(Account { id: source * 1000 + id, owner: source, balance: 0 });
+						if buffer.len() == 50 {
+							assert!(buffer.flush(events).is_empty());
+						}
+					}
+				});
+			}
+		});
+		assert_eq!((events.len(), events.find(&3).len()), (3_200, 200));
+
+		let mut buffer = WriteBuffer::new();
+		buffer.insert(Account { id: 1, owner: 0, balance: 0 });
+		buffer.upsert(1, Account { id: 99_999, owner: 20, balance: 0 });
+		buffer.remove(2);
+		buffer.remove(123_456);
+		buffer.upsert(3, Account { id: 4, owner: 20, balance: 0 });
+		let failed = buffer.flush(&events);
+		assert!(buffer.is_empty());
+		assert_eq!(failed, [(0, KeyError::Collision), (4, KeyError::Collision)]);
+		assert_eq!(events.find(&20), [Account { id: 99_999, owner: 20, balance: 0 }]);
+		assert!(!events.contains_key(&1) && !events.contains_key(&2) && events.contains_key(&3));
+
+		let mut table = events.into_table();
+		buffer.upsert(3, Account { id: 99_998, owner: 21, balance: 0 });
+		buffer.insert(Account { id: 3, owner: 21, balance: 0 });
+		assert!(buffer.flush_into(&mut table).is_empty());
+		assert_eq!((table.len(), table.find(&21).len()), (3_200, 2));
+	}
+
+	#[test]
+	fn moves_keep_the_ttl() {
+		use crate::fixtures::Item;
+		use std::time::{Duration, Instant};
+		let orders = ConcurrentTable::<Item>::with_shards(4);
+		for id in 0..8 {
+			orders.insert_with_ttl(Item { id, kind: "new" }, Duration::ZERO).unwrap();
+		}
+		let mut buffer = WriteBuffer::new();
+		// keys 100.. land in other shards for at least some of the records
+		for id in 0..8 {
+			buffer.upsert(id, Item { id: id + 100, kind: "paid" });
+		}
+		assert!(buffer.flush(&orders).is_empty());
+		assert_eq!(orders.find(&"paid").len(), 8);
+		// and merging the shards keeps it too
+		let mut merged = orders.into_table();
+		assert_eq!(merged.expire(Instant::now()).len(), 8);
+		assert!(merged.is_empty());
+	}
+}
@@ -0,0 +1,170 @@
+use core::ops::{Index, IndexMut};
+use std::sync::OnceLock;
+use alloc::{sync::Arc, vec::Vec};
+
+/// Slots per chunk. A power of two, so finding a slot's chunk is a shift.
+const CHUNK: usize = 64;
+
+/// Vector in chunks of [`CHUNK`] items, each behind an `Arc`, so a clone copies a pointer per chunk and shares the items.
+/// A write copies the chunk it lands in if a clone still holds it, and leaves the others shared. Only the last chunk
+/// may be partly filled, so an item's chunk and place follow from its index. It has the methods of `Vec` that
+/// the record storage uses, to stand in for it with the `imbl` feature.
+///
+/// Copying a chunk needs `E: Clone`, but writing doesn't: chunks only get shared by [`Chunks::clone`], which
+/// requires it, and leaves the function that copies them with both vectors.
+pub(crate) struct Chunks<E> {
+	chunks: Vec<Arc<Vec<E>>>,
+	len: usize,
+	copy: OnceLock<CopyFn<E>>,
+}
+
+/// Copies the items of a chunk that clones share.
+type CopyFn<E> = fn(&[E]) -> Vec<E>;
+
+/// Items of [`Chunks`] in order.
+pub(crate) type Iter<'a, E> = core::iter::FlatMap<core::slice::Iter<'a, Arc<Vec<E>>>, &'a Vec<E>, fn(&'a Arc<Vec<E>>) -> &'a Vec<E>>;
+
+impl<E> Chunks<E> {
+	pub(crate) fn new() -> Self {
+		Self { chunks: Vec::new(), len: 0, copy: OnceLock::new() }
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Items that fit before the list of chunks grows.
+	pub(crate) fn capacity(&self) -> usize {
+		self.chunks.capacity() * CHUNK
+	}
+
+	pub(crate) fn reserve(&mut self, additional: usize) {
+		let chunks = (self.len + additional).div_ceil(CHUNK);
+		self.chunks.reserve(chunks.saturating_sub(self.chunks.len()));
+	}
+
+	pub(crate) fn push(&mut self, item: E) {
+		if self.len.is_multiple_of(CHUNK) {
+			self.chunks.push(Arc::new(Vec::with_capacity(CHUNK.min(self.len.max(4)))));
+		}
+		self.chunk_mut(self.len / CHUNK).push(item);
+		self.len += 1;
+	}
+
+	/// The chunk to change, copied first if a clone shares it.
+	fn chunk_mut(&mut self, c: usize) -> &mut Vec<E> {
+		if Arc::get_mut(&mut self.chunks[c]).is_none() {
+			let copy = self.copy.get().expect("chunks are shared only by clones");
+			let chunk = &mut self.chunks[c];
+			let mut items = copy(chunk);
+			items.reserve(chunk.capacity() - items.len());
+			*chunk = Arc::new(items);
+		}
+		Arc::get_mut(&mut self.chunks[c]).expect("just copied")
+	}
+
+	pub(crate) fn iter(&self) -> Iter<'_, E> {
+		self.chunks.iter().flat_map(|c| &**c)
+	}
+
+	/// Keeps the items for which `keep` returns true, moving them together in order.
+	pub(crate) fn retain(&mut self, mut keep: impl FnMut(&E) -> bool) {
+		let old = core::mem::replace(self, Self { chunks: Vec::new(), len: 0, copy: self.copy.clone() });
+		for item in old {
+			if keep(&item) {
+				self.push(item);
+			}
+		}
+	}
+
+	pub(crate) fn clear(&mut self) {
+		self.chunks.clear();
+		self.len = 0;
+	}
+
+	/// Frees the spare room of the list of chunks and of the last chunk, unless a clone shares it.
+	pub(crate) fn shrink_to_fit(&mut self) {
+		self.chunks.shrink_to_fit();
+		if let Some(last) = self.chunks.last_mut().and_then(Arc::get_mut) {
+			last.shrink_to_fit();
+		}
+	}
+
+	/// Estimate of the allocated bytes, by capacity. Chunks shared with clones are counted in full.
+	pub(crate) fn bytes(&self) -> usize {
+		// each chunk sits in an `Arc` allocation, with two reference counts
+		let chunk = core::mem::size_of::<Vec<E>>() + 2 * core::mem::size_of::<usize>();
+		self.chunks.capacity() * core::mem::size_of::<Arc<Vec<E>>>()
+			+ self.chunks.iter().map(|c| chunk + c.capacity() * core::mem::size_of::<E>()).sum::<usize>()
+	}
+
+	/// Whether the item `i` of both is in the same chunk allocation, not copied yet.
+	#[cfg(test)]
+	pub(crate) fn shares(&self, other: &Self, i: usize) -> bool {
+		Arc::ptr_eq(&self.chunks[i / CHUNK], &other.chunks[i / CHUNK])
+	}
+}
+
+impl<E: Clone> Clone for Chunks<E> {
+	fn clone(&self) -> Self {
+		let copy = *self.copy.get_or_init(|| <[E]>::to_vec as CopyFn<E>);
+		Self { chunks: self.chunks.clone(), len: self.len, copy: OnceLock::from(copy) }
+	}
+}
+
+impl<E> Index<usize> for Chunks<E> {
+	type Output = E;
+
+	fn index(&self, i: usize) -> &E {
+		assert!(i < self.len, "index {} out of {} items", i, self.len);
+		&self.chunks[i / CHUNK][i % CHUNK]
+	}
+}
+
+/// Copies the item's chunk first if a clone shares it.
+impl<E> IndexMut<usize> for Chunks<E> {
+	fn index_mut(&mut self, i: usize) -> &mut E {
+		assert!(i < self.len, "index {} out of {} items", i, self.len);
+		&mut self.chunk_mut(i / CHUNK)[i % CHUNK]
+	}
+}
+
+/// The items by value. The chunks that clones still share are copied.
+impl<E> IntoIterator for Chunks<E> {
+	type Item = E;
+	type IntoIter = core::iter::Flatten<alloc::vec::IntoIter<Vec<E>>>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		let copy = self.copy.get().copied();
+		let owned = |c: Arc<Vec<E>>| Arc::try_unwrap(c).unwrap_or_else(|c| copy.expect("chunks are shared only by clones")(&c));
+		self.chunks.into_iter().map(owned).collect::<Vec<_>>().into_iter().flatten()
+	}
+}
+
+impl<E: core::fmt::Debug> core::fmt::Debug for Chunks<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_list().entries(self.iter()).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn copy_on_write() {
+		let mut a = Chunks::new();
+		for i in 0..CHUNK * 2 + 1 {
+			a.push(i);
+		}
+		let b = a.clone();
+		assert!((0..3).all(|c| a.shares(&b, c * CHUNK)));
+		a[CHUNK + 1] = 0;
+		a.push(1);
+		assert!(a.shares(&b, 0) && !a.shares(&b, CHUNK) && !a.shares(&b, CHUNK * 2));
+		assert_eq!((a[CHUNK + 1], b[CHUNK + 1], a.len(), b.len()), (0, CHUNK + 1, CHUNK * 2 + 2, CHUNK * 2 + 1));
+		a.retain(|i| i % 2 == 1);
+		assert_eq!(a.iter().copied().collect::<Vec<_>>(), (0..CHUNK * 2 + 1).filter(|i| i % 2 == 1 && *i != CHUNK + 1).chain([1]).collect::<Vec<_>>());
+		assert_eq!(b.into_iter().count(), CHUNK * 2 + 1);
+	}
+}
@@ -0,0 +1,160 @@
+use core::{any::Any, marker::PhantomData};
+use alloc::{boxed::Box, vec, vec::Vec};
+
+/// Handle of a column, returned by [`crate::MicroTable::add_column`]. It's typed, so reading the column needs no checks.
+pub struct ColumnId<U> {
+	index: usize,
+	value: PhantomData<fn() -> U>,
+}
+
+impl<U> Clone for ColumnId<U> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<U> Copy for ColumnId<U> {}
+
+impl<U> core::fmt::Debug for ColumnId<U> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_tuple("ColumnId").field(&self.index).finish()
+	}
+}
+
+pub(crate) trait Column<T> {
+	fn set(&mut self, slot: usize, val: &T);
+	/// Puts the default value into an emptied slot.
+	fn unset(&mut self, slot: usize);
+	/// Keeps the slots for which `keep` is true, moving them together.
+	fn retain(&mut self, keep: &[bool]);
+	fn clear(&mut self);
+	fn shrink_to_fit(&mut self);
+	fn bytes(&self) -> usize;
+	fn as_any(&self) -> &dyn Any;
+	fn box_clone(&self) -> Box<dyn Column<T> + Send + Sync>;
+}
+
+struct Values<T, U> {
+	field: fn(&T) -> U,
+	values: Vec<U>,
+}
+
+impl<T: 'static, U: Clone + Default + Send + Sync + 'static> Column<T> for Values<T, U> {
+	fn set(&mut self, slot: usize, val: &T) {
+		if self.values.len() <= slot {
+			self.values.resize(slot + 1, U::default());
+		}
+		self.values[slot] = (self.field)(val);
+	}
+
+	fn unset(&mut self, slot: usize) {
+		self.values[slot] = U::default();
+	}
+
+	fn retain(&mut self, keep: &[bool]) {
+		let mut slot = 0;
+		self.values.retain(|_| {
+			slot += 1;
+			keep[slot - 1]
+		});
+	}
+
+	fn clear(&mut self) {
+		self.values.clear();
+	}
+
+	fn shrink_to_fit(&mut self) {
+		self.values.shrink_to_fit();
+	}
+
+	fn bytes(&self) -> usize {
+		self.values.capacity() * core::mem::size_of::<U>()
+	}
+
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn box_clone(&self) -> Box<dyn Column<T> + Send + Sync> {
+		Box::new(Values { field: self.field, values: self.values.clone() })
+	}
+}
+
+/// Columns of a table: a field of every record, laid out by storage slot next to each other.
+pub(crate) struct Columns<T>(Vec<Box<dyn Column<T> + Send + Sync>>);
+
+impl<T> Columns<T> {
+	pub(crate) fn new() -> Self {
+		Self(vec![])
+	}
+
+	/// Adds a column filled from the records in their slots.
+	pub(crate) fn push<'a, U: Clone + Default + Send + Sync + 'static>(&mut self, field: fn(&T) -> U, slots: impl Iterator<Item = Option<&'a T>>) -> ColumnId<U>
+	where T: 'static {
+		let mut c = Values { field, values: vec![] };
+		for (slot, val) in slots.enumerate() {
+			match val {
+				Some(val) => c.set(slot, val),
+				None => c.values.push(U::default()),
+			}
+		}
+		self.0.push(Box::new(c));
+		ColumnId { index: self.0.len() - 1, value: PhantomData }
+	}
+
+	pub(crate) fn get<U: 'static>(&self, id: ColumnId<U>) -> &[U]
+	where T: 'static {
+		let c = self.0.get(id.index).and_then(|c| c.as_any().downcast_ref::<Values<T, U>>());
+		&c.expect("column of another table").values // the id is typed, so only a foreign id can mismatch
+	}
+
+	pub(crate) fn set(&mut self, slot: usize, val: &T) {
+		for c in self.0.iter_mut() {
+			c.set(slot, val);
+		}
+	}
+
+	pub(crate) fn unset(&mut self, slot: usize) {
+		for c in self.0.iter_mut() {
+			c.unset(slot);
+		}
+	}
+
+	pub(crate) fn retain(&mut self, keep: &[bool]) {
+		for c in self.0.iter_mut() {
+			c.retain(keep);
+		}
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub(crate) fn clear(&mut self) {
+		for c in self.0.iter_mut() {
+			c.clear();
+		}
+	}
+
+	pub(crate) fn shrink_to_fit(&mut self) {
+		for c in self.0.iter_mut() {
+			c.shrink_to_fit();
+		}
+	}
+
+	pub(crate) fn bytes(&self) -> usize {
+		self.0.iter().map(|c| c.bytes()).sum()
+	}
+}
+
+impl<T> Clone for Columns<T> {
+	fn clone(&self) -> Self {
+		Self(self.0.iter().map(|c| c.box_clone()).collect())
+	}
+}
+
+impl<T> core::fmt::Debug for Columns<T> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "Columns({})", self.0.len())
+	}
+}
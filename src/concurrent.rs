@@ -0,0 +1,608 @@
+//! A table shared between threads: [`ConcurrentTable`] spreads the records over shards by the hash of their keys,
+//! each a [`MicroTable`] behind its own `RwLock`, so writers to different shards don't wait for each other,
+//! and readers only wait for writers of the shard they read.
+//!
+//! [`ConcurrentTable::maintain`] starts a thread that expires records and gives back memory, a shard at a time.
+//!
+//! Records kept in an `Arc`, as in an [`ArcConcurrentTable`], are read without copying them: [`ConcurrentTable::get`]
+//! and [`ConcurrentTable::find`] copy the pointers, and the records stay valid after they are removed or replaced.
+//! A remove doesn't wait for the readers that hold them, and the memory is freed when the last one drops its `Arc`.
+//!
+//! Changes that span tables lock them all first with [`lock_all!`](crate::lock_all), which takes the locks in
+//! the same order in every thread, so two threads locking the same tables can't wait for each other.
+
+use core::hash::BuildHasher;
+use alloc::{sync::Arc, vec::Vec};
+use std::{sync::{Condvar, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard}, thread::JoinHandle, time::{Duration, Instant}};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Shards of a [`ConcurrentTable::default`].
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// Table of records shared via `Arc`, see the [module docs](crate::concurrent).
+pub type ArcConcurrentTable<T, H = RandomState> = ConcurrentTable<Arc<T>, H>;
+
+pub(crate) type ShardGuard<'a, T, H> = RwLockWriteGuard<'a, MicroTable<T, H>>;
+
+/// Table with `&self` methods for many threads, see the [module docs](crate::concurrent). Each shard has its own
+/// category index, so a lookup by key locks one shard, and a lookup by category locks every shard in turn.
+/// Queries over several shards don't see one moment of the table: a change can land in a shard already read.
+/// A panic in a callback doesn't lock the table up: the shard goes on as [`MicroTable`] leaves it after such a panic.
+pub struct ConcurrentTable<T: MicroRecord, H = RandomState> {
+	shards: Vec<RwLock<MicroTable<T, H>>>,
+	hasher: H,
+	/// Lock metrics of each shard.
+	#[cfg(feature = "metrics")]
+	metrics: Vec<crate::metrics::LockMetrics>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for ConcurrentTable<T, H> {
+	fn default() -> Self {
+		Self::with_shards_and_hasher(DEFAULT_SHARDS, H::default())
+	}
+}
+
+impl<T: MicroRecord> ConcurrentTable<T> {
+	pub fn with_shards(shards: usize) -> Self {
+		Self::with_shards_and_hasher(shards, RandomState::default())
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> ConcurrentTable<T, H> {
+	/// Panics if `shards` is 0.
+	pub fn with_shards_and_hasher(shards: usize, hasher: H) -> Self {
+		assert!(shards > 0, "a table needs a shard");
+		Self {
+			shards: (0..shards).map(|_| RwLock::new(MicroTable::with_hasher(hasher.clone()))).collect(),
+			hasher,
+			#[cfg(feature = "metrics")]
+			metrics: (0..shards).map(|_| Default::default()).collect(),
+		}
+	}
+
+	pub fn shard_count(&self) -> usize {
+		self.shards.len()
+	}
+
+	/// The shard of a key. It takes the high bits of the hash, since the shard's own maps index by the low ones.
+	pub(crate) fn shard_of(&self, key: &T::Key) -> usize {
+		((self.hasher.hash_one(key) as u128 * self.shards.len() as u128) >> 64) as usize
+	}
+
+	fn read_shard(&self, shard: usize) -> RwLockReadGuard<'_, MicroTable<T, H>> {
+		let lock = &self.shards[shard];
+		#[cfg(feature = "metrics")]
+		return self.metrics[shard].acquire(false, || lock.try_read(), || lock.read());
+		#[cfg(not(feature = "metrics"))]
+		lock.read().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	pub(crate) fn write_shard(&self, shard: usize) -> ShardGuard<'_, T, H> {
+		let lock = &self.shards[shard];
+		#[cfg(feature = "metrics")]
+		return self.metrics[shard].acquire(true, || lock.try_write(), || lock.write());
+		#[cfg(not(feature = "metrics"))]
+		lock.write().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Lock metrics of all shards since the table was made or [`ConcurrentTable::reset_lock_stats`].
+	#[cfg(feature = "metrics")]
+	pub fn lock_stats(&self) -> crate::metrics::LockStats {
+		self.metrics.iter().fold(Default::default(), |total, shard| total.merge(&shard.stats()))
+	}
+
+	/// Lock metrics of a shard, to find the hot ones.
+	#[cfg(feature = "metrics")]
+	pub fn shard_lock_stats(&self, shard: usize) -> crate::metrics::LockStats {
+		self.metrics[shard].stats()
+	}
+
+	#[cfg(feature = "metrics")]
+	pub fn reset_lock_stats(&self) {
+		self.metrics.iter().for_each(crate::metrics::LockMetrics::reset);
+	}
+
+	/// Write locks of two different shards, taken in the order of the shards, so that two threads locking
+	/// the same pair can't each hold the lock the other waits for.
+	fn write_pair(&self, a: usize, b: usize) -> (ShardGuard<'_, T, H>, ShardGuard<'_, T, H>) {
+		debug_assert_ne!(a, b);
+		if a < b {
+			let first = self.write_shard(a);
+			(first, self.write_shard(b))
+		} else {
+			let first = self.write_shard(b);
+			(self.write_shard(a), first)
+		}
+	}
+
+	/// Records in all shards, each counted under its lock.
+	pub fn len(&self) -> usize {
+		(0..self.shards.len()).map(|s| self.read_shard(s).len()).sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		(0..self.shards.len()).all(|s| self.read_shard(s).is_empty())
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.read_shard(self.shard_of(key)).contains_key(key)
+	}
+
+	/// A copy of the record.
+	pub fn get(&self, key: &T::Key) -> Option<T>
+	where T: Clone {
+		self.read_shard(self.shard_of(key)).get(key).cloned()
+	}
+
+	/// Runs `f` on the record under the shard's read lock, without copying it.
+	pub fn with_record<R>(&self, key: &T::Key, f: impl FnOnce(&T) -> R) -> Option<R> {
+		self.read_shard(self.shard_of(key)).get(key).map(f)
+	}
+
+	/// Copies of the records of the category.
+	pub fn find(&self, cat: &T::Category) -> Vec<T>
+	where T: Clone {
+		let mut found = Vec::new();
+		self.for_each_in(cat, |val| found.push(val.clone()));
+		found
+	}
+
+	/// [`MicroTable::iter_snapshot`] of the table, copied under the read locks of all shards, so it has the records
+	/// of one moment, as [`ConcurrentTable::read`] does.
+	pub fn iter_snapshot(&self) -> impl ExactSizeIterator<Item = T> + Send + 'static
+	where T: Clone + Send + 'static {
+		self.read().iter().map(|(_, val)| val.clone()).collect::<Vec<_>>().into_iter()
+	}
+
+	/// Runs `f` on every record of the category, a shard at a time under its read lock.
+	pub fn for_each_in(&self, cat: &T::Category, mut f: impl FnMut(&T)) {
+		for shard in 0..self.shards.len() {
+			self.read_shard(shard).find_iter(cat).for_each(&mut f);
+		}
+	}
+
+	pub fn insert(&self, val: T) -> Result<(), KeyError<T::Key>> {
+		self.write_shard(self.shard_of(&val.key())).insert(val)
+	}
+
+	/// [`MicroTable::insert_with_ttl`]. The record is removed by [`ConcurrentTable::expire`], or by a
+	/// [maintenance](ConcurrentTable::maintain) thread.
+	pub fn insert_with_ttl(&self, val: T, ttl: Duration) -> Result<(), KeyError<T::Key>> {
+		self.write_shard(self.shard_of(&val.key())).insert_with_ttl(val, ttl)
+	}
+
+	/// [`MicroTable::expire`] of every shard, a shard at a time.
+	pub fn expire(&self, now: Instant) -> Vec<T> {
+		(0..self.shards.len()).flat_map(|shard| self.write_shard(shard).expire(now)).collect()
+	}
+
+	/// [`MicroTable::upsert`]. A record whose key changes moves to the shard of the new key, with both shards locked,
+	/// and keeps its TTL deadline.
+	pub fn upsert(&self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
+		let (from, to) = (self.shard_of(&key), self.shard_of(&new_val.key()));
+		if from == to {
+			return self.write_shard(from).upsert(key, new_val);
+		}
+		let (mut from, mut to) = self.write_pair(from, to);
+		to.insert_with_deadline(new_val, from.deadline(&key))?;
+		from.remove(&key);
+		Ok(())
+	}
+
+	/// [`MicroTable::update_with`]. If the key changes to one of another shard, both shards are locked and the
+	/// callback runs again on the record as it is then, so it may run more than once.
+	pub fn update_with(&self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let from = self.shard_of(&old_key);
+		let to = {
+			let mut shard = self.write_shard(from);
+			let mut val = shard.get(&old_key).ok_or(KeyError::NotFound)?.clone();
+			cb(&mut val);
+			let to = self.shard_of(&val.key());
+			if to == from {
+				return shard.upsert(old_key, val);
+			}
+			to
+		};
+		let (mut from_shard, mut to_shard) = self.write_pair(from, to);
+		let mut val = from_shard.get(&old_key).ok_or(KeyError::NotFound)?.clone();
+		cb(&mut val);
+		if self.shard_of(&val.key()) != to {
+			// the record changed while the shard was unlocked, and the callback now moves it elsewhere
+			drop((from_shard, to_shard));
+			return self.update_with(old_key, cb);
+		}
+		to_shard.insert_with_deadline(val, from_shard.deadline(&old_key))?;
+		from_shard.remove(&old_key);
+		Ok(())
+	}
+
+	pub fn remove(&self, key: &T::Key) -> Option<T> {
+		self.write_shard(self.shard_of(key)).remove(key)
+	}
+
+	/// Removes the records of the category from every shard, a shard at a time.
+	pub fn remove_cat(&self, cat: &T::Category) -> Vec<T> {
+		(0..self.shards.len()).flat_map(|shard| self.write_shard(shard).remove_cat(cat)).collect()
+	}
+
+	pub fn clear(&self) {
+		for shard in 0..self.shards.len() {
+			self.write_shard(shard).clear();
+		}
+	}
+
+	/// Read locks of all shards, taken in their order, so that several queries on the guard see the same state of the
+	/// table. Writers wait until it's dropped; the thread that holds it must not write to the table meanwhile.
+	pub fn read(&self) -> ReadGuard<'_, T, H> {
+		ReadGuard { table: self, shards: (0..self.shards.len()).map(|shard| self.read_shard(shard)).collect() }
+	}
+
+	/// Write locks of all shards, taken in their order, for changes that go together. Readers and writers wait until
+	/// it's dropped; the thread that holds it must not use the table otherwise meanwhile. To lock several tables,
+	/// see [`lock_all!`](crate::lock_all).
+	pub fn write(&self) -> WriteGuard<'_, T, H> {
+		WriteGuard { table: self, shards: (0..self.shards.len()).map(|shard| self.write_shard(shard)).collect() }
+	}
+
+	/// Starts a thread that every `interval` expires the records whose TTL has run out, and shrinks the shards
+	/// that use less than half of their room. It locks one shard at a time, so a request waits for the work on one
+	/// shard at most. The thread stops when the [`Maintenance`] is dropped, or when the table is.
+	pub fn maintain(self: &Arc<Self>, interval: Duration) -> Maintenance
+	where T: Send + Sync + 'static, T::Key: Send + Sync, T::Category: Send + Sync, H: Send + Sync + 'static {
+		let table = Arc::downgrade(self);
+		let stop = Arc::new((Mutex::new(false), Condvar::new()));
+		let thread = {
+			let stop = Arc::clone(&stop);
+			std::thread::spawn(move || {
+				let (stopped, wake) = &*stop;
+				let mut guard = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+				loop {
+					// checks the flag before waiting, so a stop during the round isn't missed
+					guard = wake.wait_timeout_while(guard, interval, |stop| !*stop).unwrap_or_else(PoisonError::into_inner).0;
+					if *guard {
+						return;
+					}
+					let Some(table) = table.upgrade() else { return };
+					drop(guard);
+					table.maintain_shards();
+					guard = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+				}
+			})
+		};
+		Maintenance { stop, thread: Some(thread) }
+	}
+
+	/// A round of [`ConcurrentTable::maintain`].
+	fn maintain_shards(&self) {
+		for shard in 0..self.shards.len() {
+			let mut table = self.write_shard(shard);
+			table.expire(Instant::now());
+			if table.len() < table.capacity() / 2 {
+				table.shrink_to_fit();
+			}
+			drop(table);
+			std::thread::yield_now();
+		}
+	}
+
+	/// Merges the shards into one table. The records keep their TTL deadlines.
+	pub fn into_table(self) -> MicroTable<T, H> {
+		let mut table = MicroTable::with_hasher(self.hasher);
+		for shard in self.shards {
+			let shard = shard.into_inner().unwrap_or_else(PoisonError::into_inner);
+			let expiry = &shard.expiry;
+			for (key, val) in shard.data.into_pairs() {
+				let inserted = table.insert_with_deadline(val, expiry.get(&key).copied());
+				assert!(inserted.is_ok(), "shards hold different keys, and a new table has no constraints");
+			}
+		}
+		table
+	}
+}
+
+/// Locks tables in the order of their addresses, so that threads locking the same tables lock them in the same
+/// order and can't deadlock. Each table is given as `read name = table` or `write name = table`, where `table` is a
+/// reference to a [`ConcurrentTable`], and `name` is bound to the guard of its [`ConcurrentTable::read`] or
+/// [`ConcurrentTable::write`]. Panics if a table is given twice.
+///
+/// ```
+/// # use microtable::{ConcurrentTable, MicroRecord, lock_all};
+/// # #[derive(Clone)] struct Item { id: u32, qty: u32 }
+/// # impl MicroRecord for Item { type Key = u32; type Category = u32; fn key(&self) -> u32 { self.id } fn categories(&self) -> impl IntoIterator<Item = u32> { [] } }
+/// let (stock, orders) = (ConcurrentTable::<Item>::default(), ConcurrentTable::<Item>::default());
+/// stock.insert(Item { id: 1, qty: 5 }).unwrap();
+/// // take 2 from stock into an order, with no moment where other threads see only one of the changes
+/// lock_all!(write stock = &stock, write orders = &orders);
+/// stock.update_with(1, &|i| i.qty -= 2).unwrap();
+/// orders.insert(Item { id: 1, qty: 2 }).unwrap();
+/// ```
+#[macro_export]
+macro_rules! lock_all {
+	($($mode:ident $guard:ident = $table:expr),+ $(,)?) => {
+		$( let $guard = ($table, None); )+
+		let mut order = [$( $crate::concurrent::lock_rank($guard.0) ),+];
+		order.sort_unstable();
+		assert!(order.windows(2).all(|w| w[0] != w[1]), "a table is locked twice");
+		$( let mut $guard = $guard; )+
+		for rank in order {
+			$( if rank == $crate::concurrent::lock_rank($guard.0) { $guard.1 = Some($guard.0.$mode()); } )+
+		}
+		$( #[allow(unused_mut)] let mut $guard = $guard.1.expect("locked above"); )+
+	};
+}
+
+/// Position of a table in the order of [`lock_all!`](crate::lock_all).
+#[doc(hidden)]
+pub fn lock_rank<T: ?Sized>(table: &T) -> usize {
+	table as *const T as *const () as usize
+}
+
+/// All shards of a [`ConcurrentTable`] locked for writing, from [`ConcurrentTable::write`].
+pub struct WriteGuard<'a, T: MicroRecord, H> {
+	table: &'a ConcurrentTable<T, H>,
+	shards: Vec<RwLockWriteGuard<'a, MicroTable<T, H>>>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> WriteGuard<'_, T, H> {
+	pub fn len(&self) -> usize {
+		self.shards.iter().map(|shard| shard.len()).sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.shards.iter().all(|shard| shard.is_empty())
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.shards[self.table.shard_of(key)].contains_key(key)
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<&T> {
+		self.shards[self.table.shard_of(key)].get(key)
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<&T> {
+		self.shards.iter().flat_map(|shard| shard.find_iter(cat)).collect()
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		self.shards[self.table.shard_of(&val.key())].insert(val)
+	}
+
+	/// [`ConcurrentTable::upsert`].
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
+		let (from, to) = (self.table.shard_of(&key), self.table.shard_of(&new_val.key()));
+		if from == to {
+			return self.shards[from].upsert(key, new_val);
+		}
+		let expires = self.shards[from].deadline(&key);
+		self.shards[to].insert_with_deadline(new_val, expires)?;
+		self.shards[from].remove(&key);
+		Ok(())
+	}
+
+	/// [`ConcurrentTable::update_with`], with the callback run once.
+	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let mut val = self.get(&old_key).ok_or(KeyError::NotFound)?.clone();
+		cb(&mut val);
+		self.upsert(old_key, val)
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+		self.shards[self.table.shard_of(key)].remove(key)
+	}
+
+	pub fn remove_cat(&mut self, cat: &T::Category) -> Vec<T> {
+		self.shards.iter_mut().flat_map(|shard| shard.remove_cat(cat)).collect()
+	}
+}
+
+/// The thread of [`ConcurrentTable::maintain`]. Dropping it stops the thread, after the round in progress.
+pub struct Maintenance {
+	stop: Arc<(Mutex<bool>, Condvar)>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Maintenance {
+	fn drop(&mut self) {
+		let (stopped, wake) = &*self.stop;
+		*stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+		wake.notify_one();
+		if let Some(thread) = self.thread.take() {
+			// a panic in the thread was a panic in a shard's method, which the shard's lock recovers from
+			let _ = thread.join();
+		}
+	}
+}
+
+/// All shards of a [`ConcurrentTable`] locked for reading, from [`ConcurrentTable::read`].
+pub struct ReadGuard<'a, T: MicroRecord, H> {
+	table: &'a ConcurrentTable<T, H>,
+	shards: Vec<RwLockReadGuard<'a, MicroTable<T, H>>>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> ReadGuard<'_, T, H> {
+	pub fn len(&self) -> usize {
+		self.shards.iter().map(|shard| shard.len()).sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.shards.iter().all(|shard| shard.is_empty())
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.shards[self.table.shard_of(key)].contains_key(key)
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<&T> {
+		self.shards[self.table.shard_of(key)].get(key)
+	}
+
+	pub fn find_iter<'a>(&'a self, cat: &'a T::Category) -> impl Iterator<Item = &'a T> {
+		self.shards.iter().flat_map(move |shard| shard.find_iter(cat))
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<&T> {
+		self.shards.iter().flat_map(|shard| shard.find_iter(cat)).collect()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&T::Key, &T)> {
+		self.shards.iter().flat_map(|shard| shard.iter())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Item;
+
+	#[test]
+	fn threads_share_a_table() {
+		let orders = ConcurrentTable::<Item>::with_shards(4);
+		std::thread::scope(|s| {
+			for t in 0..4 {
+				let orders = &orders;
+				s.spawn(move || {
+					for id in (t * 250)..((t + 1) * 250) {
+						orders.insert(Item { id, kind: "new" }).unwrap();
+					}
+					for id in (t * 250..(t + 1) * 250).step_by(5) {
+						orders.update_with(id, &|o| o.kind = "paid").unwrap();
+					}
+				});
+			}
+		});
+		assert_eq!((orders.len(), orders.find(&"paid").len()), (1000, 200));
+		assert_eq!(orders.insert(Item { id: 7, kind: "new" }), Err(KeyError::Collision));
+
+		// moving records between shards by changing their keys
+		for id in 0..10 {
+			orders.update_with(id, &|o| o.id += 5000).unwrap();
+		}
+		orders.upsert(10, Item { id: 6000, kind: "paid" }).unwrap();
+		assert_eq!(orders.upsert(11, Item { id: 5001, kind: "new" }), Err(KeyError::Collision));
+		assert!(!orders.contains_key(&3) && orders.get(&5003).is_some() && orders.get(&11).is_some());
+		assert_eq!(orders.with_record(&6000, |o| o.kind), Some("paid"));
+		{
+			let all = orders.read();
+			let paid = all.find(&"paid");
+			assert_eq!((all.len(), paid.len()), (1000, 200));
+			assert!(paid.iter().all(|o| all.get(&o.id) == Some(*o)));
+			assert_eq!(all.iter().count(), all.len());
+		}
+		assert_eq!(orders.remove_cat(&"paid").len(), 200);
+		let table = orders.into_table();
+		assert_eq!((table.len(), table.find(&"new").len()), (800, 800));
+	}
+
+	#[test]
+	fn readers_keep_removed_records() {
+		let orders = ArcConcurrentTable::<Item>::with_shards(4);
+		for id in 0..10 {
+			orders.insert(Arc::new(Item { id, kind: "new" })).unwrap();
+		}
+		let held = orders.get(&3).unwrap();
+		let found = orders.find(&"new");
+		std::thread::scope(|s| {
+			s.spawn(|| {
+				orders.remove_cat(&"new");
+				orders.insert(Arc::new(Item { id: 3, kind: "paid" })).unwrap();
+			});
+		});
+		assert_eq!((held.kind, found.len()), ("new", 10));
+		assert!(found.iter().any(|o| Arc::ptr_eq(o, &held)));
+		assert_eq!(orders.get(&3).map(|o| o.kind), Some("paid"));
+		orders.update_with(3, &|o| Arc::make_mut(o).kind = "sent").unwrap();
+		assert_eq!((held.kind, Arc::strong_count(&held)), ("new", 2));
+	}
+
+	#[test]
+	fn lock_tables_in_order() {
+		let (new, paid) = (ConcurrentTable::<Item>::with_shards(4), ConcurrentTable::<Item>::with_shards(4));
+		for id in 0..100 {
+			new.insert(Item { id, kind: "new" }).unwrap();
+		}
+		// threads naming the tables in opposite orders would deadlock if they locked them in these orders
+		std::thread::scope(|s| {
+			for t in 0..4 {
+				let (new, paid) = (&new, &paid);
+				s.spawn(move || {
+					for id in (t..100).step_by(4) {
+						if t % 2 == 0 {
+							lock_all!(write from = new, write to = paid);
+							let order = from.remove(&id).unwrap();
+							to.insert(Item { kind: "paid", ..order }).unwrap();
+						} else {
+							lock_all!(write to = paid, read from = new, );
+							assert_eq!(from.len() + to.len(), 100);
+							drop((from, to));
+							lock_all!(write to = paid, write from = new);
+							let order = from.remove(&id).unwrap();
+							to.insert(Item { kind: "paid", ..order }).unwrap();
+						}
+					}
+				});
+			}
+		});
+		assert_eq!((new.len(), paid.find(&"paid").len()), (0, 100));
+
+		let mut all = paid.write();
+		all.update_with(5, &|o| o.id = 500).unwrap();
+		all.upsert(6, Item { id: 600, kind: "new" }).unwrap();
+		assert_eq!(all.upsert(7, Item { id: 500, kind: "new" }), Err(KeyError::Collision));
+		assert_eq!((all.remove_cat(&"new").len(), all.len(), all.get(&500).map(|o| o.kind)), (1, 99, Some("paid")));
+	}
+
+	#[test]
+	#[should_panic(expected = "a table is locked twice")]
+	fn lock_a_table_twice() {
+		let orders = ConcurrentTable::<Item>::with_shards(2);
+		lock_all!(read _a = &orders, read _b = &orders);
+	}
+
+	#[test]
+	fn maintenance_thread() {
+		let orders = Arc::new(ConcurrentTable::<Item>::with_shards(4));
+		for id in 0..1000 {
+			orders.insert_with_ttl(Item { id, kind: "new" }, Duration::from_millis(if id < 900 { 0 } else { 3_600_000 })).unwrap();
+		}
+		let maintenance = orders.maintain(Duration::from_millis(5));
+		let start = Instant::now();
+		while orders.len() > 100 {
+			assert!(start.elapsed() < Duration::from_secs(10), "records expire");
+			std::thread::sleep(Duration::from_millis(5));
+		}
+		assert_eq!(orders.find(&"new").len(), 100);
+		drop(maintenance);
+		// the thread holds no reference to the table
+		assert_eq!(Arc::into_inner(orders).map(|t| t.into_table().len()), Some(100));
+	}
+
+	#[test]
+	fn moves_keep_the_ttl() {
+		let orders = ConcurrentTable::<Item>::with_shards(4);
+		for id in 0..8 {
+			orders.insert_with_ttl(Item { id, kind: "new" }, Duration::ZERO).unwrap();
+		}
+		// keys 100.. land in other shards for at least some of the records
+		for id in 0..4 {
+			orders.upsert(id, Item { id: id + 100, kind: "paid" }).unwrap();
+		}
+		for id in 4..8 {
+			orders.update_with(id, &|o| o.id += 100).unwrap();
+		}
+		assert_eq!(orders.expire(Instant::now()).len(), 8);
+		assert!(orders.is_empty());
+	}
+
+	#[test]
+	fn maintenance_stops_promptly() {
+		let orders = Arc::new(ConcurrentTable::<Item>::with_shards(4));
+		let start = Instant::now();
+		for _ in 0..20 {
+			drop(orders.maintain(Duration::from_secs(3600)));
+		}
+		assert!(start.elapsed() < Duration::from_secs(10), "a stop doesn't wait for the interval");
+	}
+}
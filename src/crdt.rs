@@ -0,0 +1,166 @@
+//! Last-writer-wins merging of replicas that change independently: every record carries a [`Stamped::stamp`], and for
+//! each key the write with the greatest stamp wins, be it a record or a removal. Removals are kept as tombstones, so a
+//! merge doesn't bring removed records back. Merging is commutative, associative and idempotent, so replicas that
+//! merge each other's tables in any order end up with the same records.
+
+use core::hash::BuildHasher;
+use crate::{KeyError, MicroRecord, MicroTable, RandomState, map::{Map, map_swap_remove}};
+
+/// Record with the stamp of its last write. The stamps of two different writes must differ, so a timestamp alone
+/// is not enough: a pair of a timestamp and the id of the replica that wrote it is.
+pub trait Stamped: MicroRecord {
+	type Stamp: Ord + Clone;
+	fn stamp(&self) -> Self::Stamp;
+}
+
+/// Table merged by last writer wins, see the [module docs](crate::crdt). It reads like the [`MicroTable`] it derefs to,
+/// and changes go through its own methods.
+#[derive(Clone)]
+pub struct LwwTable<T: Stamped, H = RandomState> {
+	table: MicroTable<T, H>,
+	/// Stamps of the removals of keys that are not in the table.
+	tombstones: Map<T::Key, T::Stamp, H>,
+}
+
+impl<T: Stamped, H: BuildHasher + Clone + Default> Default for LwwTable<T, H> {
+	fn default() -> Self {
+		Self::new(MicroTable::default())
+	}
+}
+
+impl<T: Stamped, H: BuildHasher + Clone> LwwTable<T, H> {
+	pub fn new(table: MicroTable<T, H>) -> Self {
+		let tombstones = Map::with_hasher(table.hasher().clone());
+		Self { table, tombstones }
+	}
+
+	pub fn into_inner(self) -> MicroTable<T, H> {
+		self.table
+	}
+
+	/// The stamp of the last write to the key, a record or a removal.
+	fn last_write(&self, key: &T::Key) -> Option<T::Stamp> {
+		self.table.get(key).map(|val| val.stamp()).or_else(|| self.tombstones.get(key).cloned())
+	}
+
+	/// Writes the record unless the key has a write with the same or a greater stamp. Returns whether it was written.
+	pub fn put(&mut self, val: T) -> Result<bool, KeyError<T::Key>> {
+		let key = val.key();
+		if self.last_write(&key).is_some_and(|last| last >= val.stamp()) {
+			return Ok(false);
+		}
+		match self.table.contains_key(&key) {
+			true => self.table.upsert(key, val)?,
+			false => {
+				self.table.insert(val)?;
+				map_swap_remove(&mut self.tombstones, &key);
+			}
+		}
+		Ok(true)
+	}
+
+	/// Removes the key as a write stamped `stamp`, unless the key has a write with the same or a greater stamp.
+	/// The removal is remembered even if the key isn't in the table, in case the record comes later by a merge.
+	/// Returns whether it was written.
+	pub fn remove(&mut self, key: &T::Key, stamp: T::Stamp) -> bool {
+		if self.last_write(key).is_some_and(|last| last >= stamp) {
+			return false;
+		}
+		self.table.remove(key);
+		self.tombstones.insert(key.clone(), stamp);
+		true
+	}
+
+	/// Merges the writes of another replica into this one.
+	/// Fails if a record breaks a uniqueness constraint of this table; the writes before it are merged.
+	pub fn merge_crdt(&mut self, other: &Self) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		for (key, stamp) in other.tombstones.iter() {
+			self.remove(key, stamp.clone());
+		}
+		for val in other.table.values() {
+			self.put(val.clone())?;
+		}
+		Ok(())
+	}
+
+	/// The removed keys and the stamps of their removals.
+	pub fn tombstones(&self) -> impl Iterator<Item = (&T::Key, &T::Stamp)> {
+		self.tombstones.iter()
+	}
+
+	/// Drops the tombstones stamped before `stamp`, to free their memory. A replica that hasn't merged those removals
+	/// yet will bring the removed records back in the next merge.
+	pub fn forget_before(&mut self, stamp: &T::Stamp) {
+		self.tombstones.retain(|_, s| *s >= *stamp);
+	}
+}
+
+impl<T: Stamped, H> core::ops::Deref for LwwTable<T, H> {
+	type Target = MicroTable<T, H>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.table
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Note {
+		id: u32,
+		folder: &'static str,
+		/// Time of the write and the replica that made it.
+		stamp: (u64, u8),
+	}
+
+	impl MicroRecord for Note {
+		type Key = u32;
+		type Category = &'static str;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.folder]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	impl Stamped for Note {
+		type Stamp = (u64, u8);
+		fn stamp(&self) -> Self::Stamp {
+			self.stamp
+		}
+	}
+
+	#[test]
+	fn merge_replicas() {
+		let mut phone = LwwTable::<Note>::default();
+		for id in 1..=3 {
+			phone.put(Note { id, folder: "inbox", stamp: (1, 0) }).unwrap();
+		}
+		let mut laptop = phone.clone();
+
+		phone.put(Note { id: 1, folder: "work", stamp: (5, 0) }).unwrap();
+		laptop.put(Note { id: 1, folder: "home", stamp: (4, 1) }).unwrap();
+		assert!(!laptop.put(Note { id: 1, folder: "old", stamp: (2, 1) }).unwrap());
+		phone.remove(&2, (3, 0));
+		laptop.put(Note { id: 3, folder: "home", stamp: (6, 1) }).unwrap();
+		laptop.remove(&4, (7, 1));
+		phone.put(Note { id: 4, folder: "inbox", stamp: (2, 0) }).unwrap();
+
+		let mut merged = [phone.clone(), laptop.clone()];
+		merged[0].merge_crdt(&laptop).unwrap();
+		merged[1].merge_crdt(&phone).unwrap();
+		for table in &merged {
+			assert_eq!(table.len(), 2);
+			assert_eq!(table.get(&1).map(|n| n.folder), Some("work"));
+			assert_eq!(table.get(&3).map(|n| n.folder), Some("home"));
+			assert_eq!(table.tombstones().count(), 2);
+		}
+		let before = merged[0].clone();
+		merged[0].merge_crdt(&before).unwrap();
+		assert_eq!(merged[0].len(), 2);
+	}
+}
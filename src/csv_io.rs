@@ -0,0 +1,97 @@
+//! CSV import and export, for records that serde writes as flat rows (no nested structs or sequences).
+//! The first line is a header of the field names.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use std::io::{Read, Write};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{KeyError, MicroRecord, MicroTable, OnDuplicate};
+
+/// Why a line of a CSV file wasn't loaded.
+#[derive(Debug)]
+pub enum LineError<K> {
+	/// The line doesn't parse into a record.
+	Parse(csv::Error),
+	/// The table rejected the record: a repeated key (with [`OnDuplicate::Error`]) or unique value.
+	Rejected(KeyError<K>),
+}
+
+/// A line that [`MicroTable::from_csv`] skipped. `line` counts from 1, the header included.
+#[derive(Debug)]
+pub struct RejectedLine<K> {
+	pub line: u64,
+	pub error: LineError<K>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Writes the records as CSV rows, with a header.
+	pub fn to_csv(&self, writer: impl Write) -> csv::Result<()>
+	where T: Serialize {
+		let mut w = csv::Writer::from_writer(writer);
+		for val in self.values() {
+			w.serialize(val)?;
+		}
+		w.flush()?;
+		Ok(())
+	}
+
+	/// Reads records from CSV with a header, and returns the table with the lines it couldn't load.
+	/// A repeated key is handled by `on_duplicate`; with [`OnDuplicate::Error`] its line is rejected and loading goes on.
+	/// Fails only if the input can't be read.
+	pub fn from_csv(reader: impl Read, on_duplicate: OnDuplicate) -> csv::Result<(Self, Vec<RejectedLine<T::Key>>)>
+	where T: DeserializeOwned, H: Default {
+		let mut table = Self::default();
+		let mut rejected = vec![];
+		// rows of a wrong length are rejected by deserialization, rather than failing the whole read
+		let mut r = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+		let headers = r.byte_headers()?.clone();
+		let mut row = csv::ByteRecord::new();
+		while r.read_byte_record(&mut row)? {
+			let line = row.position().map_or(0, |p| p.line());
+			let val: T = match row.deserialize(Some(&headers)) {
+				Ok(val) => val,
+				Err(e) => {
+					rejected.push(RejectedLine { line, error: LineError::Parse(e) });
+					continue;
+				}
+			};
+			let key = val.key();
+			if table.contains_key(&key) {
+				match on_duplicate {
+					OnDuplicate::Error => {},
+					OnDuplicate::Skip => continue,
+					OnDuplicate::LastWins => { table.remove(&key); },
+				}
+			}
+			if let Err(error) = table.insert_keyed(key, val) {
+				rejected.push(RejectedLine { line, error: LineError::Rejected(error) });
+			}
+		}
+		Ok((table, rejected))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::City;
+
+	#[test]
+	fn csv_roundtrip() {
+		let text = "id,country,population\n1,FR,2100000\n2,FR,500000\nx,DE,1\n1,DE,3600000\n3,IT\n";
+		let (cities, rejected) = MicroTable::<City>::from_csv(text.as_bytes(), OnDuplicate::Error).unwrap();
+		assert_eq!((cities.len(), cities.find(&"FR".into()).len()), (2, 2));
+		let lines: Vec<u64> = rejected.iter().map(|r| r.line).collect();
+		assert_eq!(lines, vec![4, 5, 6]);
+		assert!(matches!(rejected[0].error, LineError::Parse(_)));
+		assert!(matches!(rejected[1].error, LineError::Rejected(KeyError::Collision)));
+
+		let (cities, _) = MicroTable::<City>::from_csv(text.as_bytes(), OnDuplicate::LastWins).unwrap();
+		assert_eq!(cities.get(&1).map(|c| c.population), Some(3600000));
+		let mut out = vec![];
+		cities.to_csv(&mut out).unwrap();
+		let (back, rejected) = MicroTable::<City>::from_csv(&out[..], OnDuplicate::Error).unwrap();
+		assert!(rejected.is_empty());
+		assert_eq!(back.get(&2), cities.get(&2));
+	}
+}
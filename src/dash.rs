@@ -0,0 +1,240 @@
+//! A table for many threads on [dashmap](https://docs.rs/dashmap): [`DashTable`] keeps the records in a `DashMap` by key,
+//! and the category index in another one, which writers update as they go.
+//!
+//! It's faster than [`crate::concurrent::ConcurrentTable`] under many writers, since a write locks only the dashmap
+//! shards of its key and categories, but it gives weaker guarantees:
+//! - the changes to a key are made one at a time, but a reader by category may see a record in its old category
+//!   while it's being updated, or miss it for a moment. [`DashTable::find`] checks the categories of what it returns,
+//!   so it never returns records of other categories.
+//! - a change of key is an insert under the new key and then a removal of the old one: readers can see both
+//!   for a moment, and a concurrent change to the old key is lost.
+//! - there are no uniqueness constraints, columns, expiry or budgets.
+//! - there's no guard for several queries to see one state of the table, like [`crate::concurrent::ConcurrentTable::read`].
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use std::collections::HashSet;
+use dashmap::{DashMap, mapref::entry::Entry};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Table of records shared via `Arc`: [`DashTable::get`] and [`DashTable::find`] copy pointers, and the records stay
+/// valid for their readers after they are removed or replaced.
+pub type ArcDashTable<T, H = RandomState> = DashTable<alloc::sync::Arc<T>, H>;
+
+/// Table with `&self` methods on dashmap, see the [module docs](crate::dash) for what it guarantees.
+pub struct DashTable<T: MicroRecord, H = RandomState> {
+	data: DashMap<T::Key, T, H>,
+	/// Keys by category. It's locked after the record's shard of `data`, never before, so the two can't deadlock.
+	index: DashMap<T::Category, HashSet<T::Key, H>, H>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for DashTable<T, H> {
+	fn default() -> Self {
+		Self::with_hasher(H::default())
+	}
+}
+
+impl<T: MicroRecord> DashTable<T> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> DashTable<T, H> {
+	pub fn with_hasher(hasher: H) -> Self {
+		Self { data: DashMap::with_hasher(hasher.clone()), index: DashMap::with_hasher(hasher) }
+	}
+
+	/// Called with the record's shard of `data` locked, so no other writer changes the key's entries meanwhile.
+	fn index_add(&self, key: &T::Key, val: &T) {
+		for cat in val.categories() {
+			self.index.entry(cat).or_insert_with(|| HashSet::with_hasher(self.data.hasher().clone())).insert(key.clone());
+		}
+	}
+
+	fn index_remove(&self, key: &T::Key, val: &T) {
+		for cat in val.categories() {
+			if let Entry::Occupied(mut keys) = self.index.entry(cat) {
+				keys.get_mut().remove(key);
+				if keys.get().is_empty() {
+					keys.remove();
+				}
+			}
+		}
+	}
+
+	/// Keys of the category, copied so that no lock of the index is held while `data` is read.
+	fn keys_of(&self, cat: &T::Category) -> Vec<T::Key> {
+		self.index.get(cat).map(|keys| keys.iter().cloned().collect()).unwrap_or_default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.data.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.data.contains_key(key)
+	}
+
+	/// A copy of the record.
+	pub fn get(&self, key: &T::Key) -> Option<T>
+	where T: Clone {
+		self.data.get(key).map(|val| val.clone())
+	}
+
+	/// Runs `f` on the record under its shard's read lock, without copying it.
+	pub fn with_record<R>(&self, key: &T::Key, f: impl FnOnce(&T) -> R) -> Option<R> {
+		self.data.get(key).map(|val| f(&val))
+	}
+
+	/// Copies of the records of the category.
+	pub fn find(&self, cat: &T::Category) -> Vec<T>
+	where T: Clone {
+		let mut found = Vec::new();
+		self.for_each_in(cat, |val| found.push(val.clone()));
+		found
+	}
+
+	/// [`MicroTable::iter_snapshot`] of the table. The shards are copied one at a time, so a change made meanwhile
+	/// may be in it or not.
+	pub fn iter_snapshot(&self) -> impl ExactSizeIterator<Item = T> + Send + 'static
+	where T: Clone + Send + 'static {
+		self.data.iter().map(|val| val.value().clone()).collect::<Vec<_>>().into_iter()
+	}
+
+	/// Runs `f` on every record of the category, each under its shard's read lock.
+	pub fn for_each_in(&self, cat: &T::Category, mut f: impl FnMut(&T)) {
+		for key in self.keys_of(cat) {
+			if let Some(val) = self.data.get(&key) {
+				if val.categories().into_iter().any(|c| c == *cat) {
+					f(&val);
+				}
+			}
+		}
+	}
+
+	pub fn insert(&self, val: T) -> Result<(), KeyError<T::Key>> {
+		match self.data.entry(val.key()) {
+			Entry::Occupied(_) => Err(KeyError::Collision),
+			Entry::Vacant(slot) => {
+				self.index_add(slot.key(), &val);
+				slot.insert(val);
+				Ok(())
+			}
+		}
+	}
+
+	/// [`MicroTable::upsert`]. A change of key inserts the new one first, see the [module docs](crate::dash).
+	pub fn upsert(&self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
+		if new_val.key() != key {
+			self.insert(new_val)?;
+			self.remove(&key);
+			return Ok(());
+		}
+		match self.data.entry(key) {
+			Entry::Occupied(mut old) => {
+				self.index_remove(old.key(), old.get());
+				self.index_add(old.key(), &new_val);
+				old.insert(new_val);
+			}
+			Entry::Vacant(slot) => {
+				self.index_add(slot.key(), &new_val);
+				slot.insert(new_val);
+			}
+		}
+		Ok(())
+	}
+
+	/// [`MicroTable::update_with`]. The callback runs on a copy of the record under its shard's write lock.
+	pub fn update_with(&self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let moved = {
+			let Entry::Occupied(mut old) = self.data.entry(old_key.clone()) else { return Err(KeyError::NotFound) };
+			let mut val = old.get().clone();
+			cb(&mut val);
+			if val.key() == old_key {
+				self.index_remove(&old_key, old.get());
+				self.index_add(&old_key, &val);
+				old.insert(val);
+				return Ok(());
+			}
+			val
+		};
+		self.upsert(old_key, moved)
+	}
+
+	pub fn remove(&self, key: &T::Key) -> Option<T> {
+		let Entry::Occupied(old) = self.data.entry(key.clone()) else { return None };
+		self.index_remove(old.key(), old.get());
+		Some(old.remove())
+	}
+
+	/// Removes the records of the category, one at a time.
+	pub fn remove_cat(&self, cat: &T::Category) -> Vec<T> {
+		self.keys_of(cat).into_iter().filter_map(|key| {
+			let Entry::Occupied(old) = self.data.entry(key) else { return None };
+			if !old.get().categories().into_iter().any(|c| c == *cat) {
+				return None;
+			}
+			self.index_remove(old.key(), old.get());
+			Some(old.remove())
+		}).collect()
+	}
+
+	/// Removes the records a shard at a time. Records inserted meanwhile may stay.
+	pub fn clear(&self) {
+		self.data.retain(|key, val| {
+			self.index_remove(key, val);
+			false
+		});
+	}
+
+	pub fn into_table(self) -> MicroTable<T, H> {
+		let mut table = MicroTable::with_hasher(self.data.hasher().clone());
+		for (_, val) in self.data {
+			// keys in a DashMap are different, and a new table has no constraints to fail
+			let _ = table.insert(val);
+		}
+		table
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Item;
+
+	#[test]
+	fn threads_share_a_dashmap() {
+		let sessions = DashTable::<Item>::new();
+		std::thread::scope(|s| {
+			for t in 0..4 {
+				let sessions = &sessions;
+				s.spawn(move || {
+					for id in (t * 250)..((t + 1) * 250) {
+						sessions.insert(Item { id, kind: "eu" }).unwrap();
+					}
+					for id in (t * 250..(t + 1) * 250).step_by(5) {
+						sessions.update_with(id, &|s| s.kind = "us").unwrap();
+					}
+				});
+			}
+		});
+		assert_eq!((sessions.len(), sessions.find(&"us").len(), sessions.find(&"eu").len()), (1000, 200, 800));
+		assert_eq!(sessions.insert(Item { id: 3, kind: "eu" }), Err(KeyError::Collision));
+
+		sessions.update_with(0, &|s| s.id = 2000).unwrap();
+		assert_eq!(sessions.upsert(1, Item { id: 2, kind: "eu" }), Err(KeyError::Collision));
+		sessions.upsert(5, Item { id: 5, kind: "us" }).unwrap();
+		assert!(!sessions.contains_key(&0) && sessions.get(&2000).is_some_and(|s| s.kind == "us"));
+		assert_eq!(sessions.with_record(&5, |s| s.kind), Some("us"));
+		assert_eq!(sessions.remove(&5).map(|s| s.id), Some(5));
+		assert_eq!(sessions.remove_cat(&"us").len(), 199);
+		let table = sessions.into_table();
+		assert_eq!((table.len(), table.find(&"eu").len()), (800, 800));
+	}
+}
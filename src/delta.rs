@@ -0,0 +1,244 @@
+//! Replicating a table by its changes: a [`DeltaTable`] numbers its changes with [`Version`]s and remembers the version
+//! of the last change to each key, so [`DeltaTable::delta`] can serialize only the records changed since the version
+//! a replica has, and [`MicroTable::apply_delta`] brings the replica up to date.
+
+use core::{hash::BuildHasher, ops::Deref};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize, Serializer};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState, map::Map};
+#[cfg(feature="imbl")]
+use crate::map::NoCapacity;
+
+/// Number of the changes a [`DeltaTable`] has made. A replica keeps the version of the last delta it applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Version(pub u64);
+
+/// The records changed between two versions, as [`DeltaTable::delta`] serializes them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delta<T, K> {
+	/// The version the delta starts from.
+	pub since: Version,
+	/// The version of the table when the delta was taken, to ask for the next delta since.
+	pub version: Version,
+	/// The table was cleared, or has forgotten removals since `since`: the replica is to be emptied first,
+	/// and `upserted` holds all the records.
+	pub full: bool,
+	/// Records that are new or changed, whole.
+	pub upserted: Vec<T>,
+	/// Keys that were removed.
+	pub removed: Vec<K>,
+}
+
+/// Table that numbers its changes, see the [module docs](crate::delta). It reads like the [`MicroTable`] it derefs to,
+/// and changes go through its own methods. Records that the table drops by itself, by TTL or by its size limit, are not tracked.
+#[derive(Clone)]
+pub struct DeltaTable<T: MicroRecord, H = RandomState> {
+	table: MicroTable<T, H>,
+	version: Version,
+	/// Version of the last change to each key, and whether the key is in the table since.
+	changes: Map<T::Key, (Version, bool), H>,
+	/// Deltas since before this version are full.
+	horizon: Version,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> DeltaTable<T, H> {
+	/// Starts tracking the changes to the table. Deltas since [`Version`] 0 hold all its records.
+	pub fn new(table: MicroTable<T, H>) -> Self {
+		let mut changes = Map::with_capacity_and_hasher(table.len(), table.hasher().clone());
+		for key in table.iter_keys() {
+			changes.insert(key.clone(), (Version(1), true));
+		}
+		let version = Version(if table.is_empty() { 0 } else { 1 });
+		Self { table, version, changes, horizon: Version(0) }
+	}
+
+	pub fn version(&self) -> Version {
+		self.version
+	}
+
+	pub fn into_inner(self) -> MicroTable<T, H> {
+		self.table
+	}
+
+	fn bump(&mut self) -> Version {
+		self.version.0 += 1;
+		self.version
+	}
+
+	fn changed(&mut self, key: T::Key, present: bool) {
+		let version = self.version;
+		self.changes.insert(key, (version, present));
+	}
+
+	/// The changes since `since`, to serialize as a [`Delta`] of the records.
+	pub fn delta(&self, since: Version) -> Delta<&T, &T::Key> {
+		let full = since < self.horizon;
+		let (mut upserted, mut removed) = (Vec::new(), Vec::new());
+		for (key, (version, present)) in self.changes.iter() {
+			if !full && *version <= since {
+				continue;
+			}
+			match present {
+				true => upserted.push(self.table.get(key).expect("present keys are in the table")),
+				false if !full => removed.push(key),
+				false => {},
+			}
+		}
+		Delta { since, version: self.version, full, upserted, removed }
+	}
+
+	/// Serializes [`DeltaTable::delta`].
+	pub fn serialize_delta<S: Serializer>(&self, since: Version, serializer: S) -> Result<S::Ok, S::Error>
+	where T: Serialize, T::Key: Serialize {
+		self.delta(since).serialize(serializer)
+	}
+
+	/// Drops what is kept of the keys removed up to `version`. Deltas since before it become full.
+	pub fn forget_before(&mut self, version: Version) {
+		self.changes.retain(|_, (v, present)| *present || *v >= version);
+		self.horizon = self.horizon.max(version);
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		let key = val.key();
+		self.table.insert(val)?;
+		self.bump();
+		self.changed(key, true);
+		Ok(())
+	}
+
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
+		let new_key = new_val.key();
+		self.table.upsert(key.clone(), new_val)?;
+		self.bump();
+		self.changed(key, false);
+		self.changed(new_key, true);
+		Ok(())
+	}
+
+	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let new_key = core::cell::RefCell::new(None);
+		self.table.update_with(old_key.clone(), &|val: &mut T| {
+			cb(val);
+			*new_key.borrow_mut() = Some(val.key());
+		})?;
+		self.bump();
+		self.changed(old_key, false);
+		self.changed(new_key.into_inner().expect("callback ran"), true);
+		Ok(())
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+		let val = self.table.remove(key)?;
+		self.bump();
+		self.changed(key.clone(), false);
+		Some(val)
+	}
+
+	pub fn remove_cat(&mut self, cat: &T::Category) -> Vec<T> {
+		let removed = self.table.remove_cat(cat);
+		if !removed.is_empty() {
+			self.bump();
+			for val in removed.iter() {
+				self.changed(val.key(), false);
+			}
+		}
+		removed
+	}
+
+	/// Empties the table. Deltas since before now are full.
+	pub fn clear(&mut self) {
+		self.table.clear();
+		self.changes.clear();
+		self.horizon = self.bump();
+	}
+}
+
+impl<T: MicroRecord, H> Deref for DeltaTable<T, H> {
+	type Target = MicroTable<T, H>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.table
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Applies a delta of a [`DeltaTable`], replacing the records it has and removing the keys it removed.
+	/// Fails if a record breaks a uniqueness constraint of this table; the records before it are applied.
+	pub fn apply_delta(&mut self, delta: Delta<T, T::Key>) -> Result<(), KeyError<T::Key>> {
+		if delta.full {
+			self.clear();
+		}
+		for key in delta.removed.iter() {
+			self.remove(key);
+		}
+		for val in delta.upserted {
+			self.remove(&val.key());
+			self.insert(val)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Player {
+		name: String,
+		team: String,
+		score: u32,
+	}
+
+	impl MicroRecord for Player {
+		type Key = String;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.team.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.name.clone()
+		}
+	}
+
+	fn player(name: &str, team: &str) -> Player {
+		Player { name: name.into(), team: team.into(), score: 0 }
+	}
+
+	/// Sends the delta through JSON, as to a client.
+	fn sync(server: &DeltaTable<Player>, client: &mut MicroTable<Player>, since: Version) -> Version {
+		let json = serde_json::to_vec(&server.delta(since)).unwrap();
+		let delta: Delta<Player, String> = serde_json::from_slice(&json).unwrap();
+		let version = delta.version;
+		client.apply_delta(delta).unwrap();
+		version
+	}
+
+	#[test]
+	fn replicate_by_deltas() {
+		let mut server = DeltaTable::new([player("ann", "red"), player("bob", "red")].into_iter().collect());
+		let mut client = MicroTable::default();
+		let v1 = sync(&server, &mut client, Version(0));
+		assert_eq!(client.len(), 2);
+
+		server.update_with("ann".into(), &|p| p.score = 3).unwrap();
+		server.insert(player("cid", "blue")).unwrap();
+		server.remove(&"bob".into());
+		let delta = server.delta(v1);
+		assert_eq!((delta.upserted.len(), delta.removed.len(), delta.full), (2, 1, false));
+		let v2 = sync(&server, &mut client, v1);
+		assert_eq!(client.get(&"ann".into()).map(|p| p.score), Some(3));
+		assert_eq!((client.len(), client.find(&"blue".into()).len()), (2, 1));
+		assert!(server.delta(v2).upserted.is_empty());
+
+		server.upsert("cid".into(), player("dan", "blue")).unwrap();
+		server.forget_before(server.version());
+		let stale = server.delta(v2);
+		assert!(stale.full && stale.removed.is_empty());
+		sync(&server, &mut client, v2);
+		assert_eq!(client.iter_keys().collect::<std::collections::HashSet<_>>(), server.iter_keys().collect::<std::collections::HashSet<_>>());
+	}
+}
@@ -0,0 +1,143 @@
+//! Rendering the records as aligned text tables, for logs and command line tools.
+
+use core::{fmt::Display, hash::BuildHasher};
+use alloc::{boxed::Box, string::{String, ToString}, vec::Vec};
+use crate::{MicroRecord, MicroTable};
+
+/// Column of a rendered table: a header and the text of a cell for each record.
+pub struct Column<'a, T> {
+	header: String,
+	cell: Box<dyn Fn(&T) -> String + 'a>,
+	right: bool,
+}
+
+impl<'a, T> Column<'a, T> {
+	pub fn new<D: Display>(header: impl Into<String>, cell: impl Fn(&T) -> D + 'a) -> Self {
+		Self { header: header.into(), cell: Box::new(move |val| cell(val).to_string()), right: false }
+	}
+
+	/// Aligns the cells to the right, as for numbers.
+	pub fn right(mut self) -> Self {
+		self.right = true;
+		self
+	}
+}
+
+/// Text of a cell on one line. Markdown cells also have their pipes escaped.
+fn clean(text: String, markdown: bool) -> String {
+	let text = if text.contains(['\n', '\r']) { text.replace(['\n', '\r'], " ") } else { text };
+	if markdown && text.contains('|') { text.replace('|', "\\|") } else { text }
+}
+
+fn pad(out: &mut String, text: &str, width: usize, right: bool) {
+	let fill = width - text.chars().count();
+	if right {
+		out.extend(core::iter::repeat_n(' ', fill));
+	}
+	out.push_str(text);
+	if !right {
+		out.extend(core::iter::repeat_n(' ', fill));
+	}
+}
+
+fn render<'v, T: 'v>(values: impl Iterator<Item = &'v T>, columns: &[Column<'_, T>], markdown: bool) -> String {
+	let header: Vec<String> = columns.iter().map(|c| clean(c.header.clone(), markdown)).collect();
+	let rows: Vec<Vec<String>> = values.map(|val| columns.iter().map(|c| clean((c.cell)(val), markdown)).collect()).collect();
+	let min = if markdown { 3 } else { 0 }; // a markdown rule needs `---`
+	let widths: Vec<usize> = (0..columns.len())
+		.map(|i| core::iter::once(&header).chain(&rows).map(|row| row[i].chars().count()).max().unwrap_or(0).max(min))
+		.collect();
+
+	let mut out = String::new();
+	let line = |out: &mut String, cells: &[String]| {
+		out.push('|');
+		for ((cell, width), column) in cells.iter().zip(&widths).zip(columns) {
+			out.push(' ');
+			pad(out, cell, *width, column.right);
+			out.push_str(" |");
+		}
+		out.push('\n');
+	};
+	let rule = |out: &mut String| {
+		out.push('+');
+		for width in &widths {
+			out.extend(core::iter::repeat_n('-', width + 2));
+			out.push('+');
+		}
+		out.push('\n');
+	};
+	if !markdown {
+		rule(&mut out);
+	}
+	line(&mut out, &header);
+	if markdown {
+		out.push('|');
+		for (width, column) in widths.iter().zip(columns) {
+			out.extend(core::iter::repeat_n('-', width + 1));
+			out.push(if column.right { ':' } else { '-' });
+			out.push('|');
+		}
+		out.push('\n');
+	} else {
+		rule(&mut out);
+	}
+	for row in &rows {
+		line(&mut out, row);
+	}
+	if !markdown && !rows.is_empty() {
+		rule(&mut out);
+	}
+	out
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// The records as an ASCII table with a row each, in the table's order, with the cells padded to line up.
+	pub fn render(&self, columns: &[Column<'_, T>]) -> String {
+		render(self.values(), columns, false)
+	}
+
+	/// Like [`MicroTable::render`], but as a Markdown table.
+	pub fn render_markdown(&self, columns: &[Column<'_, T>]) -> String {
+		render(self.values(), columns, true)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct Planet {
+		name: &'static str,
+		moons: u32,
+	}
+
+	impl MicroRecord for Planet {
+		type Key = &'static str;
+		type Category = u32;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.moons]
+		}
+		fn key(&self) -> Self::Key {
+			self.name
+		}
+	}
+
+	#[test]
+	fn render_tables() {
+		let planets: MicroTable<Planet> = [Planet { name: "Mars", moons: 2 }].into_iter().collect();
+		let columns = [Column::new("planet", |p: &Planet| p.name), Column::new("moons", |p: &Planet| p.moons).right()];
+		assert_eq!(planets.render(&columns), "\
++--------+-------+
+| planet | moons |
++--------+-------+
+| Mars   |     2 |
++--------+-------+
+");
+		let columns = [Column::new("a|b", |p: &Planet| p.name), Column::new("n", |p: &Planet| p.moons).right()];
+		assert_eq!(planets.render_markdown(&columns), "\
+| a\\|b |   n |
+|------|----:|
+| Mars |   2 |
+");
+	}
+}
@@ -0,0 +1,71 @@
+//! Graphviz export of the records and their categories.
+
+use core::{fmt::{Display, Write}, hash::BuildHasher};
+use alloc::string::{String, ToString};
+use crate::{MicroRecord, MicroTable};
+
+/// Label in double quotes, escaped for DOT.
+fn quoted(label: impl Display) -> String {
+	let mut out = String::from("\"");
+	for c in label.to_string().chars() {
+		match c {
+			'"' | '\\' => { out.push('\\'); out.push(c) },
+			'\n' => out.push_str("\\n"),
+			'\r' => {},
+			_ => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// The table as an undirected bipartite graph in the Graphviz DOT language: records are boxes, categories are ellipses,
+	/// and an edge joins a record to each of its categories in the index. The labels are made by `record_label` and `cat_label`.
+	pub fn to_dot<R: Display, C: Display>(&self, record_label: impl Fn(&T) -> R, cat_label: impl Fn(&T::Category) -> C) -> String {
+		let mut out = String::from("graph microtable {\n");
+		// writing to a String doesn't fail
+		for (slot, _, val) in self.data.iter_slots() {
+			let _ = writeln!(out, "\tr{slot} [shape=box, label={}];", quoted(record_label(val)));
+		}
+		for (i, (cat, slots)) in self.index.iter().enumerate() {
+			let _ = writeln!(out, "\tc{i} [shape=ellipse, label={}];", quoted(cat_label(cat)));
+			for slot in slots.iter() {
+				let _ = writeln!(out, "\tr{slot} -- c{i};");
+			}
+		}
+		out.push_str("}\n");
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct Book {
+		title: &'static str,
+		genres: [&'static str; 2],
+	}
+
+	impl MicroRecord for Book {
+		type Key = &'static str;
+		type Category = &'static str;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			self.genres
+		}
+		fn key(&self) -> Self::Key {
+			self.title
+		}
+	}
+
+	#[test]
+	fn dot_graph() {
+		let books: MicroTable<Book> = [Book { title: "The \"Hobbit\"", genres: ["fantasy", "children"] }].into_iter().collect();
+		let dot = books.to_dot(|b| b.title, |g| *g);
+		assert!(dot.starts_with("graph microtable {\n\tr0 [shape=box, label=\"The \\\"Hobbit\\\"\"];\n"), "{dot}");
+		assert!(dot.contains("label=\"fantasy\"") && dot.contains("label=\"children\""));
+		assert_eq!(dot.matches(" -- ").count(), 2);
+		assert!(dot.ends_with("}\n"));
+	}
+}
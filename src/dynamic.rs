@@ -0,0 +1,162 @@
+//! Records of JSON documents whose schema is only known at run time. A [`DynSpec`] names the key and the categories
+//! by [JSON pointers](https://www.rfc-editor.org/rfc/rfc6901) into the documents, and makes [`DynRecord`]s of them.
+//!
+//! ```
+//! # use microtable::{MicroTable, dynamic::{DynCategory, DynSpec}};
+//! # use serde_json::json;
+//! let spec = DynSpec::new("/id").category("/lang").category("/tags");
+//! let docs: MicroTable<_> = spec.records([json!({"id": 1, "lang": "en", "tags": ["a", "b"]}), json!({"id": 2, "lang": "fr", "tags": ["b"]})]).collect();
+//! assert_eq!(docs.find(&DynCategory::new("/tags", "b")).len(), 2);
+//! assert_eq!(docs.get(&"2".into()).unwrap()["lang"], "fr");
+//! ```
+
+use core::ops::Deref;
+use alloc::{string::{String, ToString}, sync::Arc, vec::Vec};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use crate::MicroRecord;
+
+/// Paths of the key and of the categories of [`DynRecord`]s, shared by the records it makes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynSpec {
+	key: String,
+	categories: Vec<Arc<str>>,
+}
+
+impl DynSpec {
+	/// Spec of documents keyed by the value at the pointer `key`, e.g. `"/id"` or `"/meta/uuid"`.
+	pub fn new(key: impl Into<String>) -> Self {
+		Self { key: key.into(), categories: vec![] }
+	}
+
+	/// Adds a category path: documents are in the category of the value there, or of every item if it's an array.
+	pub fn category(mut self, path: impl Into<String>) -> Self {
+		self.categories.push(path.into().into());
+		self
+	}
+
+	/// The document as a record, or `None` if it has no key, or a null one.
+	pub fn record(&self, value: Value) -> Option<DynRecord> {
+		value.pointer(&self.key).and_then(text)?;
+		Some(DynRecord { value, spec: Arc::new(self.clone()) })
+	}
+
+	/// Records of the documents, sharing one copy of the spec. Documents without a key are skipped.
+	pub fn records(&self, values: impl IntoIterator<Item = Value>) -> impl Iterator<Item = DynRecord> {
+		let spec = Arc::new(self.clone());
+		values.into_iter().filter_map(move |value| {
+			value.pointer(&spec.key).and_then(text)?;
+			Some(DynRecord { value, spec: Arc::clone(&spec) })
+		})
+	}
+}
+
+/// Text of a value, as keys and categories compare it: strings as they are, other values as JSON, null as none.
+fn text(value: &Value) -> Option<String> {
+	match value {
+		Value::Null => None,
+		Value::String(s) => Some(s.clone()),
+		other => Some(other.to_string()),
+	}
+}
+
+/// A category of [`DynRecord`]s: a value at a path of their spec. Values compare as text, see [`DynCategory::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DynCategory {
+	pub path: Arc<str>,
+	pub value: String,
+}
+
+impl DynCategory {
+	/// Category of the value at `path`. A string value is given as it is, other values as JSON: `"42"`, `"true"`.
+	pub fn new(path: &str, value: impl Into<String>) -> Self {
+		Self { path: path.into(), value: value.into() }
+	}
+}
+
+/// A JSON document with the key and categories of its [`DynSpec`]. The key is the text of the value at the
+/// key path (see [`DynCategory::new`]). It derefs to the document, and serializes as it.
+#[derive(Debug, Clone)]
+pub struct DynRecord {
+	value: Value,
+	spec: Arc<DynSpec>,
+}
+
+impl DynRecord {
+	/// The document, to change in [`crate::MicroTable::update_with`]. A document whose key is removed gets the empty key.
+	pub fn value_mut(&mut self) -> &mut Value {
+		&mut self.value
+	}
+
+	pub fn into_value(self) -> Value {
+		self.value
+	}
+}
+
+impl Deref for DynRecord {
+	type Target = Value;
+
+	fn deref(&self) -> &Value {
+		&self.value
+	}
+}
+
+impl PartialEq for DynRecord {
+	fn eq(&self, other: &Self) -> bool {
+		self.value == other.value
+	}
+}
+
+impl Serialize for DynRecord {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.value.serialize(serializer)
+	}
+}
+
+impl MicroRecord for DynRecord {
+	type Key = String;
+	type Category = DynCategory;
+
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.spec.categories.iter().flat_map(|path| {
+			let values = match self.value.pointer(path) {
+				Some(Value::Array(items)) => items.iter().collect(),
+				Some(value) => vec![value],
+				None => vec![],
+			};
+			values.into_iter().filter_map(text).map(|value| DynCategory { path: Arc::clone(path), value })
+		})
+	}
+
+	fn key(&self) -> Self::Key {
+		self.value.pointer(&self.spec.key).and_then(text).unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+	use crate::MicroTable;
+
+	#[test]
+	fn json_documents() {
+		let spec = DynSpec::new("/meta/id").category("/kind").category("/labels");
+		let docs = [
+			json!({"meta": {"id": "a"}, "kind": "bug", "labels": ["ui", 3]}),
+			json!({"meta": {"id": "b"}, "kind": "bug", "labels": []}),
+			json!({"meta": {"id": 7}, "kind": null}),
+			json!({"kind": "no key"}),
+		];
+		let mut table: MicroTable<DynRecord> = spec.records(docs).collect();
+		assert_eq!(table.len(), 3);
+		assert_eq!(table.find(&DynCategory::new("/kind", "bug")).len(), 2);
+		assert_eq!(table.find(&DynCategory::new("/labels", "3")).len(), 1);
+		assert!(table.find(&DynCategory::new("/kind", "ui")).is_empty());
+		assert_eq!(table.get(&"7".into()).map(|d| d["meta"]["id"].clone()), Some(json!(7)));
+
+		table.update_with("b".into(), &|d| { d.value_mut()["labels"] = json!(["ui"]); }).unwrap();
+		assert_eq!(table.find(&DynCategory::new("/labels", "ui")).len(), 2);
+		assert_eq!(serde_json::to_value(&table).unwrap().as_array().map(Vec::len), Some(3));
+	}
+}
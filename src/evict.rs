@@ -0,0 +1,78 @@
+use core::hash::{BuildHasher, Hash};
+use alloc::collections::BTreeMap;
+use crate::map::{Map, map_swap_remove};
+
+/// Bookkeeping of a bounded table: the estimated size of the records, and their order of eviction, least recently used first.
+/// Records are stamped with a counter on insert, update and touch; the stamps survive slot changes, unlike slot numbers.
+#[derive(Clone)]
+pub(crate) struct Eviction<K, H> {
+	pub(crate) max_bytes: Option<usize>,
+	pub(crate) max_records: Option<usize>,
+	pub(crate) bytes: usize,
+	stamps: Map<K, u64, H>,
+	order: BTreeMap<u64, K>,
+	next: u64,
+}
+
+impl<K: Hash + Eq + Clone, H: BuildHasher + Clone> Eviction<K, H> {
+	pub(crate) fn with_hasher(hasher: H) -> Self {
+		Self { max_bytes: None, max_records: None, bytes: 0, stamps: Map::with_hasher(hasher), order: BTreeMap::new(), next: 0 }
+	}
+
+	pub(crate) fn add(&mut self, key: &K, size: usize) {
+		self.bytes += size;
+		self.stamps.insert(key.clone(), self.next);
+		self.order.insert(self.next, key.clone());
+		self.next += 1;
+	}
+
+	pub(crate) fn remove(&mut self, key: &K, size: usize) {
+		self.bytes -= size;
+		if let Some(stamp) = map_swap_remove(&mut self.stamps, key) {
+			self.order.remove(&stamp);
+		}
+	}
+
+	/// Counts the new size of an updated record, and moves it to the end of the order.
+	pub(crate) fn update(&mut self, key: &K, old: usize, new: usize) {
+		self.remove(key, old);
+		self.add(key, new);
+	}
+
+	/// Moves the record to the end of the order.
+	pub(crate) fn touch(&mut self, key: &K) {
+		if let Some(stamp) = self.stamps.get_mut(key) {
+			let key = self.order.remove(stamp).expect("stamps and order match");
+			*stamp = self.next;
+			self.order.insert(self.next, key);
+			self.next += 1;
+		}
+	}
+
+	/// Whether there are no limits left, so the bookkeeping can go.
+	pub(crate) fn is_unlimited(&self) -> bool {
+		self.max_bytes.is_none() && self.max_records.is_none()
+	}
+
+	/// Whether records must be evicted to fit the limits. The byte budget never evicts the last record, while a record
+	/// limit of 0 evicts them all.
+	pub(crate) fn over(&self, len: usize) -> bool {
+		self.max_records.is_some_and(|max| len > max) || (len > 1 && self.max_bytes.is_some_and(|max| self.bytes > max))
+	}
+
+	pub(crate) fn oldest(&self) -> Option<&K> {
+		self.order.values().next()
+	}
+
+	pub(crate) fn clear(&mut self) {
+		self.bytes = 0;
+		self.stamps.clear();
+		self.order.clear();
+	}
+}
+
+impl<K, H> core::fmt::Debug for Eviction<K, H> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("Eviction").field("max_bytes", &self.max_bytes).field("max_records", &self.max_records).field("bytes", &self.bytes).finish_non_exhaustive()
+	}
+}
@@ -0,0 +1,126 @@
+//! Records shared by the tests of the modules.
+
+use alloc::string::String;
+use crate::MicroRecord;
+
+/// A record in one `&'static str` category, for tests that don't serialize.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Item {
+	pub id: u32,
+	pub kind: &'static str,
+}
+
+impl MicroRecord for Item {
+	type Key = u32;
+	type Category = &'static str;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		[self.kind]
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
+
+/// A record in one `String` category, with the derives of the serialization and schema features. It isn't `Clone`,
+/// so the tests using it show that serializing doesn't clone.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature="utoipa", derive(utoipa::ToSchema))]
+pub(crate) struct Note {
+	pub id: u32,
+	pub tag: String,
+}
+
+impl MicroRecord for Note {
+	type Key = u32;
+	type Category = String;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		[self.tag.clone()]
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
+
+/// An edge of a graph, in the categories of both of its vertices.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Edge {
+	pub id: u32,
+	pub v1: u32,
+	pub v2: u32,
+}
+
+impl MicroRecord for Edge {
+	type Key = u32;
+	type Category = u32;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		[self.v1, self.v2]
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
+
+/// A bank account, in the category of its owner.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Account {
+	pub id: u32,
+	pub owner: u32,
+	pub balance: i64,
+}
+
+impl MicroRecord for Account {
+	type Key = u32;
+	type Category = u32;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		[self.owner]
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
+
+/// A sensor reading, in the category of its sensor.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Reading {
+	pub id: u32,
+	pub sensor: u16,
+	pub value: f32,
+}
+
+impl MicroRecord for Reading {
+	type Key = u32;
+	type Category = u16;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		[self.sensor]
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
+
+/// A city, in the category of its country.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct City {
+	pub id: u32,
+	pub country: String,
+	pub population: u64,
+	#[cfg_attr(feature="serde", serde(default))]
+	pub capital: bool,
+}
+
+impl MicroRecord for City {
+	type Key = u32;
+	type Category = String;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		[self.country.clone()]
+	}
+	fn key(&self) -> Self::Key {
+		self.id
+	}
+}
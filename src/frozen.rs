@@ -0,0 +1,97 @@
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, vec::Vec};
+use crate::{MicroRecord, RandomState, SlotSet, map::Map, posting::intersect_sorted, slab::Slab};
+#[cfg(feature="imbl")]
+use crate::map::NoCapacity;
+
+/// Read-only table made by [`crate::MicroTable::freeze`], for tables built once and queried a lot.
+/// The records are packed into one array, and all the posting sets into another, each category's slots sorted,
+/// so there are no empty slots, spare capacity, or per-category allocations, and [`FrozenTable::find_all`] intersects
+/// in one pass.
+#[derive(Clone)]
+pub struct FrozenTable<T: MicroRecord, H = RandomState> {
+	keys: Map<T::Key, usize, H>,
+	records: Box<[T]>,
+	cats: Map<T::Category, (usize, usize), H>,
+	postings: Box<[u32]>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> FrozenTable<T, H> {
+	/// Packs the records and the index. The slots must be dense.
+	pub(crate) fn build(data: Slab<T::Key, T, H>, index: Map<T::Category, SlotSet<H>, H>) -> Self {
+		let mut cats = Map::with_capacity_and_hasher(index.len(), index.hasher().clone());
+		let mut postings = Vec::with_capacity(index.values().map(|slots| slots.len()).sum());
+		for (cat, slots) in index.iter() {
+			let start = postings.len();
+			postings.extend(slots.iter());
+			postings[start..].sort_unstable();
+			cats.insert(cat.clone(), (start, postings.len()));
+		}
+		let (keys, records) = data.into_dense();
+		Self { keys, records: records.into_boxed_slice(), cats, postings: postings.into_boxed_slice() }
+	}
+
+	pub fn len(&self) -> usize {
+		self.records.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.records.is_empty()
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.keys.contains_key(key)
+	}
+
+	pub fn contains_cat(&self, cat: &T::Category) -> bool {
+		self.cats.contains_key(cat)
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<&T> {
+		self.keys.get(key).map(|s| &self.records[*s])
+	}
+
+	fn slots(&self, cat: &T::Category) -> &[u32] {
+		self.cats.get(cat).map_or(&[], |(start, end)| &self.postings[*start..*end])
+	}
+
+	/// Records of the category, in the order they were stored, without allocating.
+	pub fn find_iter(&self, cat: &T::Category) -> impl Iterator<Item = &T> {
+		self.slots(cat).iter().map(|s| &self.records[*s as usize])
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<&T> {
+		self.find_iter(cat).collect()
+	}
+
+	/// Records that are in all of the categories (none if `cats` is empty).
+	pub fn find_all(&self, cats: &[T::Category]) -> Vec<&T> {
+		let mut lists: Vec<&[u32]> = cats.iter().map(|c| self.slots(c)).collect();
+		lists.sort_by_key(|l| l.len());
+		let Some((smallest, others)) = lists.split_first() else { return Vec::new() };
+		intersect_sorted(smallest, others).into_iter().map(|s| &self.records[s as usize]).collect()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&T::Key, &T)> {
+		self.keys.iter().map(|(k, s)| (k, &self.records[*s]))
+	}
+
+	/// The records, in the order they were stored.
+	pub fn values(&self) -> impl Iterator<Item = &T> {
+		self.records.iter()
+	}
+
+	pub fn iter_keys(&self) -> impl Iterator<Item = &T::Key> {
+		self.keys.keys()
+	}
+
+	pub fn iter_cats(&self) -> impl Iterator<Item = &T::Category> {
+		self.cats.keys()
+	}
+}
+
+impl<T: MicroRecord + core::fmt::Debug, H> core::fmt::Debug for FrozenTable<T, H> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("FrozenTable").field("records", &self.records).field("postings", &self.postings.len()).finish_non_exhaustive()
+	}
+}
@@ -0,0 +1,320 @@
+//! Saving tables to files and loading them back, in the format the file's extension names:
+//!
+//! | extension | format | feature |
+//! |---|---|---|
+//! | `.json` | one JSON array of the records | |
+//! | `.jsonl`, `.ndjson` | [JSON Lines](MicroTable::write_jsonl) | |
+//! | `.csv` | [CSV](MicroTable::to_csv) | `csv` |
+//! | `.bin`, `.snap` | [binary snapshots](crate::binary) | `binary` |
+//!
+//! JSON comes with the `fs` feature. Other extensions, or ones whose feature is off, fail with [`FileError::UnknownFormat`].
+//!
+//! With the `compression` feature, a `.zst` after the extension (`table.json.zst`) compresses the file with zstd,
+//! and [`MicroTable::save_to_compressed`] sets the level. [`MicroTable::load_from`] decompresses any file that is zstd data.
+
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, format, string::String};
+use std::{fs::File, io::{self, Write}, path::{Path, PathBuf}};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{MicroRecord, MicroTable};
+
+/// Error of [`MicroTable::save_to`] and [`MicroTable::load_from`], with the path of the file.
+#[derive(Debug)]
+pub enum FileError {
+	/// The extension names no format, or its feature is off.
+	UnknownFormat(PathBuf),
+	/// Reading or writing the file failed.
+	Io { path: PathBuf, source: io::Error },
+	/// The file doesn't hold a valid table, or a record couldn't be encoded.
+	Format { path: PathBuf, source: Box<dyn std::error::Error + Send + Sync> },
+}
+
+impl core::fmt::Display for FileError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::UnknownFormat(path) => write!(f, "no table format for the extension of {}", path.display()),
+			Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+			Self::Format { path, source } => write!(f, "{}: bad table data: {source}", path.display()),
+		}
+	}
+}
+
+impl std::error::Error for FileError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::UnknownFormat(_) => None,
+			Self::Io { source, .. } => Some(source),
+			Self::Format { source, .. } => Some(&**source),
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+enum Codec {
+	Json,
+	Jsonl,
+	#[cfg(feature="csv")]
+	Csv,
+	#[cfg(feature="binary")]
+	Binary,
+}
+
+/// zstd level of [`MicroTable::save_to`] for `.zst` paths, zstd's default.
+#[cfg(feature="compression")]
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// First bytes of a zstd frame.
+#[cfg(feature="compression")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+fn extension(path: &Path) -> Option<String> {
+	path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase())
+}
+
+impl Codec {
+	/// The format of the path's extension, and the zstd level if a `.zst` follows it.
+	fn of(path: &Path) -> Result<(Self, Option<i32>), FileError> {
+		#[cfg(feature="compression")]
+		if extension(path).as_deref() == Some("zst") {
+			let inner = Path::new(path.file_stem().unwrap_or_default());
+			return Self::named(extension(inner), path).map(|codec| (codec, Some(DEFAULT_LEVEL)));
+		}
+		Self::named(extension(path), path).map(|codec| (codec, None))
+	}
+
+	fn named(ext: Option<String>, path: &Path) -> Result<Self, FileError> {
+		match ext.as_deref() {
+			Some("json") => Ok(Self::Json),
+			Some("jsonl" | "ndjson") => Ok(Self::Jsonl),
+			#[cfg(feature="csv")]
+			Some("csv") => Ok(Self::Csv),
+			#[cfg(feature="binary")]
+			Some("bin" | "snap") => Ok(Self::Binary),
+			_ => Err(FileError::UnknownFormat(path.to_path_buf())),
+		}
+	}
+}
+
+/// Sorts an error of a codec into a failed read or write, or bad data.
+fn classify(path: &Path, source: Box<dyn std::error::Error + Send + Sync>) -> FileError {
+	match source.downcast::<io::Error>() {
+		Ok(e) if e.kind() != io::ErrorKind::InvalidData && e.kind() != io::ErrorKind::UnexpectedEof && e.kind() != io::ErrorKind::InvalidInput => FileError::Io { path: path.to_path_buf(), source: *e },
+		Ok(e) => FileError::Format { path: path.to_path_buf(), source: e },
+		Err(source) => FileError::Format { path: path.to_path_buf(), source },
+	}
+}
+
+fn json_error(path: &Path, e: serde_json::Error) -> FileError {
+	match e.io_error_kind() {
+		Some(_) => classify(path, Box::new(io::Error::from(e))),
+		None => FileError::Format { path: path.to_path_buf(), source: Box::new(e) },
+	}
+}
+
+/// Writes a temporary file next to `path` with `write`, which gets the file and its path, then syncs it and renames it
+/// over `path`, and syncs the directory after the rename. The temporary file is removed if any step fails.
+fn replace_file(path: &Path, write: impl FnOnce(&mut File, &Path) -> Result<(), FileError>) -> Result<(), FileError> {
+	let name = path.file_name().ok_or_else(|| FileError::UnknownFormat(path.to_path_buf()))?;
+	let tmp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+	let io_error = |source| FileError::Io { path: tmp.clone(), source };
+	let mut file = File::create(&tmp).map_err(io_error)?;
+	let written = write(&mut file, &tmp)
+		.and_then(|()| file.sync_all().map_err(io_error))
+		.and_then(|()| std::fs::rename(&tmp, path).map_err(|source| FileError::Io { path: path.to_path_buf(), source }))
+		.and_then(|()| crate::sync_parent(path).map_err(|source| FileError::Io { path: path.to_path_buf(), source }));
+	if written.is_err() {
+		let _ = std::fs::remove_file(&tmp);
+	}
+	written
+}
+
+#[cfg(feature="csv")]
+fn csv_error(path: &Path, e: csv::Error) -> FileError {
+	if !e.is_io_error() {
+		return FileError::Format { path: path.to_path_buf(), source: Box::new(e) };
+	}
+	let csv::ErrorKind::Io(e) = e.into_kind() else { unreachable!() };
+	classify(path, Box::new(e))
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Writes the table to `path`, in the format of its extension. The records go to a temporary file
+	/// next to it, which is synced and then renamed over `path`, and the directory is synced after the rename, so a crash
+	/// leaves either the old file or the new one.
+	pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), FileError>
+	where T: Serialize {
+		let path = path.as_ref();
+		let (codec, level) = Codec::of(path)?;
+		self.write_file(path, codec, level)
+	}
+
+	/// Like [`MicroTable::save_to`], but compressed with zstd at `level` (1 to 22, or negative for faster levels),
+	/// with or without a `.zst` after the extension.
+	#[cfg(feature="compression")]
+	pub fn save_to_compressed(&self, path: impl AsRef<Path>, level: i32) -> Result<(), FileError>
+	where T: Serialize {
+		let path = path.as_ref();
+		self.write_file(path, Codec::of(path)?.0, Some(level))
+	}
+
+	#[cfg_attr(not(feature="compression"), allow(unused_variables))]
+	fn write_file(&self, path: &Path, codec: Codec, level: Option<i32>) -> Result<(), FileError>
+	where T: Serialize {
+		replace_file(path, |file, tmp| {
+			#[cfg(feature="compression")]
+			if let Some(level) = level {
+				let io_error = |source| FileError::Io { path: tmp.to_path_buf(), source };
+				let mut z = zstd::Encoder::new(file, level).map_err(io_error)?;
+				self.encode(codec, &mut z, tmp)?;
+				return z.finish().map(drop).map_err(io_error);
+			}
+			self.encode(codec, file, tmp)
+		})
+	}
+
+	fn encode(&self, codec: Codec, w: &mut dyn io::Write, path: &Path) -> Result<(), FileError>
+	where T: Serialize {
+		match codec {
+			Codec::Json => {
+				// flushed here rather than on drop, which would ignore an error writing the end of the file
+				let mut w = io::BufWriter::new(w);
+				serde_json::to_writer(&mut w, self).and_then(|()| w.flush().map_err(serde_json::Error::io)).map_err(|e| json_error(path, e))
+			}
+			Codec::Jsonl => self.write_jsonl(w).map_err(|e| json_error(path, e)),
+			#[cfg(feature="csv")]
+			Codec::Csv => self.to_csv(w).map_err(|e| csv_error(path, e)),
+			#[cfg(feature="binary")]
+			Codec::Binary => self.save_snapshot(w).map_err(|e| classify(path, Box::new(e))),
+		}
+	}
+
+	/// Reads a table from `path`, in the format of its extension. Repeated keys are an error in every format.
+	pub fn load_from(path: impl AsRef<Path>) -> Result<Self, FileError>
+	where T: DeserializeOwned, H: Default {
+		let path = path.as_ref();
+		let (codec, _) = Codec::of(path)?;
+		let file = File::open(path).map_err(|source| FileError::Io { path: path.to_path_buf(), source })?;
+		let mut r = io::BufReader::new(file);
+		#[cfg(feature="compression")]
+		{
+			use io::BufRead;
+			if r.fill_buf().map_err(|source| FileError::Io { path: path.to_path_buf(), source })?.starts_with(&ZSTD_MAGIC) {
+				let z = zstd::Decoder::with_buffer(r).map_err(|source| FileError::Io { path: path.to_path_buf(), source })?;
+				return Self::decode(codec, &mut io::BufReader::new(z), path);
+			}
+		}
+		Self::decode(codec, &mut r, path)
+	}
+
+	fn decode(codec: Codec, r: &mut dyn io::Read, path: &Path) -> Result<Self, FileError>
+	where T: DeserializeOwned, H: Default {
+		match codec {
+			Codec::Json => serde_json::from_reader(r).map_err(|e| json_error(path, e)),
+			Codec::Jsonl => Self::read_jsonl(r).map_err(|e| json_error(path, e)),
+			#[cfg(feature="csv")]
+			Codec::Csv => {
+				let (table, rejected) = Self::from_csv(r, crate::OnDuplicate::Error).map_err(|e| csv_error(path, e))?;
+				match rejected.first() {
+					None => Ok(table),
+					Some(first) => Err(FileError::Format { path: path.to_path_buf(), source: format!("{} lines rejected, the first is line {}", rejected.len(), first.line).into() }),
+				}
+			}
+			#[cfg(feature="binary")]
+			Codec::Binary => Self::load_snapshot(r).map_err(|e| classify(path, Box::new(e))),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+	use serde::Deserialize;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Setting {
+		name: String,
+		group: String,
+	}
+
+	impl MicroRecord for Setting {
+		type Key = String;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.group.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.name.clone()
+		}
+	}
+
+	#[test]
+	fn save_and_load() {
+		let settings: MicroTable<Setting> = [("width", "ui"), ("height", "ui"), ("proxy", "net")].into_iter()
+			.map(|(name, group)| Setting { name: name.into(), group: group.into() }).collect();
+		let dir = std::env::temp_dir();
+		assert!(matches!(settings.save_to(dir.join("settings.xyz")), Err(FileError::UnknownFormat(_))));
+		assert!(matches!(MicroTable::<Setting>::load_from(dir.join("missing-microtable-file.json")), Err(FileError::Io { .. })));
+		#[allow(unused_mut)]
+		let mut extensions = vec!["json", "jsonl"];
+		#[cfg(feature="csv")]
+		extensions.push("csv");
+		#[cfg(feature="binary")]
+		extensions.push("bin");
+		for ext in extensions {
+			let path = dir.join(format!("microtable-fs-{}.{ext}", std::process::id()));
+			settings.save_to(&path).unwrap();
+			let loaded = MicroTable::<Setting>::load_from(&path).unwrap();
+			assert_eq!((loaded.len(), loaded.find(&"ui".into()).len()), (3, 2), "{ext}");
+			std::fs::write(&path, b"{ not a table\n1,2,3\n").unwrap();
+			assert!(matches!(MicroTable::<Setting>::load_from(&path), Err(FileError::Format { .. })), "{ext}");
+			std::fs::remove_file(&path).unwrap();
+		}
+	}
+
+	/// Passes the writes on, but fails to flush, like a disk that fills up on the last write.
+	struct FailingFlush<'a>(&'a mut File);
+
+	impl io::Write for FailingFlush<'_> {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Err(io::Error::other("disk full"))
+		}
+	}
+
+	#[test]
+	fn failed_flush_keeps_the_file() {
+		let settings: MicroTable<Setting> = [Setting { name: "width".into(), group: "ui".into() }].into_iter().collect();
+		let path = std::env::temp_dir().join(format!("microtable-fs-flush-{}.json", std::process::id()));
+		settings.save_to(&path).unwrap();
+		let saved = std::fs::read(&path).unwrap();
+		let failed = replace_file(&path, |file, tmp| MicroTable::<Setting>::default().encode(Codec::Json, &mut FailingFlush(file), tmp));
+		assert!(matches!(failed, Err(FileError::Io { .. })));
+		assert_eq!(std::fs::read(&path).unwrap(), saved);
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	#[cfg(feature="compression")]
+	fn compressed_files() {
+		let settings: MicroTable<Setting> = (0..500).map(|i| Setting { name: format!("setting {i}"), group: format!("group {}", i % 7) }).collect();
+		let dir = std::env::temp_dir();
+		let zst = dir.join(format!("microtable-fs-{}.jsonl.zst", std::process::id()));
+		settings.save_to(&zst).unwrap();
+		let plain = dir.join(format!("microtable-fs-{}.jsonl", std::process::id()));
+		settings.save_to(&plain).unwrap();
+		let bytes = std::fs::read(&zst).unwrap();
+		assert!(bytes.starts_with(&ZSTD_MAGIC) && bytes.len() * 5 < std::fs::metadata(&plain).unwrap().len() as usize);
+		assert_eq!(MicroTable::<Setting>::load_from(&zst).unwrap().len(), 500);
+
+		// compressed without the suffix, found by the zstd magic bytes
+		settings.save_to_compressed(&plain, 19).unwrap();
+		assert!(std::fs::read(&plain).unwrap().starts_with(&ZSTD_MAGIC));
+		assert_eq!(MicroTable::<Setting>::load_from(&plain).unwrap().find(&"group 3".into()).len(), 71);
+		std::fs::remove_file(&zst).unwrap();
+		std::fs::remove_file(&plain).unwrap();
+	}
+}
@@ -0,0 +1,116 @@
+use core::{hash::BuildHasher, ops::{Deref, DerefMut}};
+use crate::{KeyError, MicroRecord, MicroTable};
+
+/// Key assigned by the table to a record without a natural key: the storage slot and its generation.
+/// The slot is reused after removal, but with a new generation, so a stale handle finds nothing.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Handle {
+	slot: u32,
+	generation: u32,
+}
+
+/// Record stored with [`MicroTable::insert_anon`], with the handle it got. `T` implements `MicroRecord<Key = ()>`,
+/// only for its categories.
+#[derive(Debug, Clone)]
+pub struct Anon<T> {
+	handle: Handle,
+	value: T,
+}
+
+impl<T> Anon<T> {
+	pub fn handle(&self) -> Handle {
+		self.handle
+	}
+
+	pub fn into_inner(self) -> T {
+		self.value
+	}
+}
+
+impl<T> Deref for Anon<T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		&self.value
+	}
+}
+
+impl<T> DerefMut for Anon<T> {
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.value
+	}
+}
+
+impl<T: MicroRecord<Key = ()>> MicroRecord for Anon<T> {
+	type Key = Handle;
+	type Category = T::Category;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.value.categories()
+	}
+	fn key(&self) -> Self::Key {
+		self.handle
+	}
+	fn buckets(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.value.buckets()
+	}
+	fn heap_size(&self) -> usize {
+		self.value.heap_size()
+	}
+}
+
+impl<T: MicroRecord<Key = ()>, H: BuildHasher + Clone> MicroTable<Anon<T>, H> {
+	/// Stores a record that has no key of its own, and returns the handle to get it by.
+	/// Fails only if a uniqueness constraint is violated.
+	pub fn insert_anon(&mut self, value: T) -> Result<Handle, KeyError<Handle>> {
+		let (slot, generation) = self.data.next_slot();
+		let handle = Handle { slot: slot as u32, generation };
+		self.insert(Anon { handle, value })?;
+		Ok(handle)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Particle {
+		zone: u8,
+		x: f32,
+	}
+
+	impl MicroRecord for Particle {
+		type Key = ();
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.zone]
+		}
+		fn key(&self) -> Self::Key {}
+	}
+
+	#[test]
+	fn stale_handles() {
+		let mut it: MicroTable<Anon<Particle>> = MicroTable::new();
+		let a = it.insert_anon(Particle { zone: 1, x: 0.0 }).unwrap();
+		let b = it.insert_anon(Particle { zone: 1, x: 1.0 }).unwrap();
+		assert_ne!(a, b);
+		assert_eq!(it.find(&1).len(), 2);
+
+		assert!(it.remove(&a).is_some());
+		let c = it.insert_anon(Particle { zone: 2, x: 2.0 }).unwrap();
+		#[cfg(not(feature="indexmap"))]
+		assert_eq!(c.slot, a.slot);
+		assert!(it.get(&a).is_none());
+		assert_eq!(it.get(&c).unwrap().x, 2.0);
+		it.modify(&c, |p| p.zone = 1).unwrap();
+		assert_eq!(it.find(&1).len(), 2);
+		it.modify(&c, |p| p.zone = 2).unwrap();
+
+		it.remove(&b);
+		it.shrink_to_fit();
+		let d = it.insert_anon(Particle { zone: 2, x: 3.0 }).unwrap();
+		assert!(it.get(&b).is_none());
+		assert_eq!(it.find(&2).len(), 2);
+		assert_eq!(it.get(&d).unwrap().handle(), d);
+	}
+}
@@ -0,0 +1,294 @@
+//! Callbacks on the changes to a table: a [`HookedTable`] passes each change that succeeds to its [`Observer`]s, to
+//! keep an index, a cache or metrics outside the table in step with it. Observers are registered and unregistered
+//! at any time, and called in the order they were registered. A panic in an observer (with the `std` feature)
+//! doesn't reach the caller nor the other observers: it unregisters the observer, see
+//! [`HookedTable::take_panicked`]. The table reads like the [`MicroTable`] it derefs to, and changes go through its
+//! own methods.
+
+use core::{hash::BuildHasher, ops::Deref};
+use alloc::{boxed::Box, vec::Vec};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Listener of the changes to a [`HookedTable`], called after each change. A change of key is an update.
+pub trait Observer<T>: Send + Sync {
+	fn inserted(&mut self, _new: &T) {}
+	fn updated(&mut self, _old: &T, _new: &T) {}
+	fn removed(&mut self, _old: &T) {}
+}
+
+/// Observer of [`HookedTable::on_insert`].
+struct OnInsert<F>(F);
+impl<T, F: FnMut(&T) + Send + Sync> Observer<T> for OnInsert<F> {
+	fn inserted(&mut self, new: &T) {
+		(self.0)(new)
+	}
+}
+
+/// Observer of [`HookedTable::on_update`].
+struct OnUpdate<F>(F);
+impl<T, F: FnMut(&T, &T) + Send + Sync> Observer<T> for OnUpdate<F> {
+	fn updated(&mut self, old: &T, new: &T) {
+		(self.0)(old, new)
+	}
+}
+
+/// Observer of [`HookedTable::on_remove`].
+struct OnRemove<F>(F);
+impl<T, F: FnMut(&T) + Send + Sync> Observer<T> for OnRemove<F> {
+	fn removed(&mut self, old: &T) {
+		(self.0)(old)
+	}
+}
+
+/// Registration of an [`Observer`], to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+/// Observers in the order they were registered.
+struct Observers<T> {
+	list: Vec<(ObserverId, Box<dyn Observer<T>>)>,
+	next: u64,
+	/// Observers unregistered by a panic, since the last [`HookedTable::take_panicked`].
+	panicked: Vec<ObserverId>,
+}
+
+impl<T> Observers<T> {
+	/// Calls `event` on each observer, and drops the ones that panic.
+	fn notify(&mut self, event: impl Fn(&mut dyn Observer<T>)) {
+		#[cfg(feature = "std")]
+		self.list.retain_mut(|(id, observer)| {
+			let called = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| event(&mut **observer))).is_ok();
+			if !called {
+				self.panicked.push(*id);
+			}
+			called
+		});
+		#[cfg(not(feature = "std"))]
+		self.list.iter_mut().for_each(|(_, observer)| event(&mut **observer));
+	}
+}
+
+/// Table that passes its changes to observers, see the [module docs](crate::hooks). Records that the table drops by
+/// itself by its size limit are not passed to them; expired ones are, if expired by [`HookedTable::expire`].
+pub struct HookedTable<T: MicroRecord, H = RandomState> {
+	table: MicroTable<T, H>,
+	observers: Observers<T>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for HookedTable<T, H> {
+	fn default() -> Self {
+		Self::new(MicroTable::default())
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> HookedTable<T, H> {
+	/// Wraps the table. The records it has already aren't passed to the observers.
+	pub fn new(table: MicroTable<T, H>) -> Self {
+		Self { table, observers: Observers { list: Vec::new(), next: 0, panicked: Vec::new() } }
+	}
+
+	pub fn into_inner(self) -> MicroTable<T, H> {
+		self.table
+	}
+
+	/// Adds the observer after the ones registered before it.
+	pub fn register(&mut self, observer: impl Observer<T> + 'static) -> ObserverId {
+		let id = ObserverId(self.observers.next);
+		self.observers.next += 1;
+		self.observers.list.push((id, Box::new(observer)));
+		id
+	}
+
+	/// Removes the observer. False if it isn't registered, or was unregistered by a panic.
+	pub fn unregister(&mut self, id: ObserverId) -> bool {
+		let before = self.observers.list.len();
+		self.observers.list.retain(|(registered, _)| *registered != id);
+		self.observers.list.len() < before
+	}
+
+	/// The observers unregistered because they panicked, since the last call.
+	pub fn take_panicked(&mut self) -> Vec<ObserverId> {
+		core::mem::take(&mut self.observers.panicked)
+	}
+
+	/// Registers an observer that calls `f` with each new record.
+	pub fn on_insert(&mut self, f: impl FnMut(&T) + Send + Sync + 'static) -> ObserverId {
+		self.register(OnInsert(f))
+	}
+
+	/// Registers an observer that calls `f` with the old and the new record of each update, including a change of key.
+	pub fn on_update(&mut self, f: impl FnMut(&T, &T) + Send + Sync + 'static) -> ObserverId {
+		self.register(OnUpdate(f))
+	}
+
+	/// Registers an observer that calls `f` with each removed record.
+	pub fn on_remove(&mut self, f: impl FnMut(&T) + Send + Sync + 'static) -> ObserverId {
+		self.register(OnRemove(f))
+	}
+
+	fn inserted(&mut self, key: &T::Key) {
+		let val = self.table.get(key).expect("inserted");
+		self.observers.notify(|o| o.inserted(val));
+	}
+
+	fn removed(&mut self, val: &T) {
+		self.observers.notify(|o| o.removed(val));
+	}
+
+	/// Passes the old record as updated, or the new one as inserted if there was none.
+	fn replaced(&mut self, old: Option<T>, new_key: &T::Key) {
+		let Some(old) = old else { return self.inserted(new_key) };
+		let new = self.table.get(new_key).expect("updated");
+		self.observers.notify(|o| o.updated(&old, new));
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		let key = val.key();
+		self.table.insert(val)?;
+		self.inserted(&key);
+		Ok(())
+	}
+
+	/// [`MicroTable::upsert`]. The observers get the old record, so it's copied first.
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let new_key = new_val.key();
+		let old = self.table.get(&key).cloned();
+		self.table.upsert(key, new_val)?;
+		self.replaced(old, &new_key);
+		Ok(())
+	}
+
+	/// [`MicroTable::update_with`]. The observers get the old record, so it's copied first.
+	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let old = self.table.get(&old_key).ok_or(KeyError::NotFound)?.clone();
+		let mut val = old.clone();
+		cb(&mut val);
+		let new_key = val.key();
+		self.table.upsert(old_key, val)?;
+		self.replaced(Some(old), &new_key);
+		Ok(())
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+		let val = self.table.remove(key)?;
+		self.removed(&val);
+		Some(val)
+	}
+
+	pub fn remove_cat(&mut self, cat: &T::Category) -> Vec<T> {
+		let removed = self.table.remove_cat(cat);
+		removed.iter().for_each(|val| self.removed(val));
+		removed
+	}
+
+	/// [`MicroTable::expire`], with the expired records passed to the observers as removed.
+	#[cfg(feature = "std")]
+	pub fn expire(&mut self, now: std::time::Instant) -> Vec<T> {
+		let expired = self.table.expire(now);
+		expired.iter().for_each(|val| self.removed(val));
+		expired
+	}
+
+	/// Empties the table, passing each record to the observers as removed first.
+	pub fn clear(&mut self) {
+		for val in self.table.values() {
+			self.observers.notify(|o| o.removed(val));
+		}
+		self.table.clear();
+	}
+}
+
+impl<T: MicroRecord, H> Deref for HookedTable<T, H> {
+	type Target = MicroTable<T, H>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.table
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{string::String, sync::{Arc, Mutex}};
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Doc {
+		id: u32,
+		folder: u8,
+		title: &'static str,
+	}
+
+	impl MicroRecord for Doc {
+		type Key = u32;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.folder]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn hooks_see_changes() {
+		let log = Arc::new(Mutex::new(Vec::<String>::new()));
+		let mut docs = HookedTable::<Doc>::default();
+		let l = Arc::clone(&log);
+		docs.on_insert(move |d| l.lock().unwrap().push(format!("+{}", d.title)));
+		let l = Arc::clone(&log);
+		docs.on_update(move |old, new| l.lock().unwrap().push(format!("{}>{}", old.title, new.title)));
+		let l = Arc::clone(&log);
+		docs.on_remove(move |d| l.lock().unwrap().push(format!("-{}", d.title)));
+
+		docs.insert(Doc { id: 1, folder: 0, title: "a" }).unwrap();
+		docs.upsert(2, Doc { id: 2, folder: 0, title: "b" }).unwrap();
+		docs.update_with(1, &|d| d.title = "c").unwrap();
+		docs.upsert(2, Doc { id: 3, folder: 1, title: "d" }).unwrap();
+		// failed changes don't call the hooks
+		assert_eq!(docs.insert(Doc { id: 1, folder: 0, title: "e" }), Err(KeyError::Collision));
+		assert_eq!(docs.update_with(5, &|_| unreachable!()), Err(KeyError::NotFound));
+		assert!(docs.remove(&9).is_none());
+		assert_eq!(docs.remove_cat(&1).len(), 1);
+		docs.clear();
+		assert_eq!(*log.lock().unwrap(), ["+a", "+b", "a>c", "b>d", "-d", "-c"]);
+		assert!(docs.is_empty());
+	}
+
+	struct Audit(Arc<Mutex<Vec<String>>>, &'static str);
+
+	impl Observer<Doc> for Audit {
+		fn inserted(&mut self, new: &Doc) {
+			if self.1 == "audit" && new.title == "boom" {
+				panic!("audit failed");
+			}
+			self.0.lock().unwrap().push(format!("{}+{}", self.1, new.title));
+		}
+		fn removed(&mut self, old: &Doc) {
+			self.0.lock().unwrap().push(format!("{}-{}", self.1, old.title));
+		}
+	}
+
+	#[test]
+	fn observers_in_order() {
+		let log = Arc::new(Mutex::new(Vec::<String>::new()));
+		let mut docs = HookedTable::<Doc>::default();
+		let audit = docs.register(Audit(Arc::clone(&log), "audit"));
+		let l = Arc::clone(&log);
+		let cache = docs.on_remove(move |d| l.lock().unwrap().push(format!("cache-{}", d.title)));
+		docs.insert(Doc { id: 1, folder: 0, title: "a" }).unwrap();
+		docs.remove(&1);
+		assert_eq!(*log.lock().unwrap(), ["audit+a", "audit-a", "cache-a"]);
+
+		// the panic unregisters the observer, and the change is made and passed to the others
+		docs.register(Audit(Arc::clone(&log), "ui"));
+		log.lock().unwrap().clear();
+		docs.insert(Doc { id: 2, folder: 0, title: "boom" }).unwrap();
+		assert_eq!(docs.take_panicked(), [audit]);
+		assert!(docs.contains_key(&2) && !docs.unregister(audit) && docs.take_panicked().is_empty());
+		assert!(docs.unregister(cache));
+		docs.remove(&2);
+		assert_eq!(*log.lock().unwrap(), ["ui+boom", "ui-boom"]);
+	}
+}
@@ -0,0 +1,148 @@
+//! Serde format that stores the category index next to the records, so loading doesn't call
+//! `categories()` for every record. Use it with `#[serde(with = "microtable::indexed")]`:
+//!
+//! ```
+//! # use microtable::{MicroTable, MicroRecord};
+//! # use serde::{Serialize, Deserialize};
+//! # #[derive(Clone, Serialize, Deserialize)]
+//! # struct Edge { id: u32, v1: u32, v2: u32 }
+//! # impl MicroRecord for Edge {
+//! #     type Key = u32;
+//! #     type Category = u32;
+//! #     fn categories(&self) -> impl IntoIterator<Item = u32> { [self.v1, self.v2] }
+//! #     fn key(&self) -> u32 { self.id }
+//! # }
+//! #[derive(Serialize, Deserialize)]
+//! struct Graph {
+//!     #[serde(with = "microtable::indexed")]
+//!     edges: MicroTable<Edge>,
+//! }
+//! ```
+//!
+//! On load the index is only checked for consistency with the keys (every indexed key exists, no empty
+//! categories); it's trusted to match what `categories()` would return. Buckets are not stored and are rebuilt.
+
+use core::hash::{BuildHasher, Hash};
+use alloc::{sync::Arc, vec::Vec};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error, ser::SerializeStruct};
+use crate::{MicroRecord, MicroTable, map::Map, posting::Posting};
+#[cfg(feature="imbl")]
+use crate::map::NoCapacity;
+
+struct Values<'a, K, T, H>(&'a crate::Slab<K, T, H>);
+
+impl<K: Hash + Eq + Clone, T: Serialize, H: BuildHasher + Clone> Serialize for Values<'_, K, T, H> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.0.values())
+	}
+}
+
+struct Postings<'a, C, K, T, H>(&'a Map<C, crate::SlotSet<H>, H>, &'a crate::Slab<K, T, H>);
+
+impl<C: Serialize, K: Serialize + Hash + Eq + Clone, T, H: BuildHasher + Clone> Serialize for Postings<'_, C, K, T, H> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.0.iter().map(|(cat, slots)| (cat, Keys(slots, self.1))))
+	}
+}
+
+// the keys of the records in the posting, since slot numbers don't survive loading
+struct Keys<'a, K, T, H>(&'a Posting<H>, &'a crate::Slab<K, T, H>);
+
+impl<K: Serialize + Hash + Eq + Clone, T, H: BuildHasher + Clone> Serialize for Keys<'_, K, T, H> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.0.iter().map(|s| self.1.at(s as usize).0))
+	}
+}
+
+pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	T: MicroRecord + Serialize,
+	T::Key: Serialize,
+	T::Category: Serialize,
+	H: BuildHasher + Clone,
+	S: Serializer,
+{
+	let mut st = serializer.serialize_struct("MicroTable", 2)?;
+	st.serialize_field("data", &Values(&table.data))?;
+	st.serialize_field("index", &Postings(&table.index, &table.data))?;
+	st.end()
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MicroTable", bound(deserialize = "T: Deserialize<'de>, C: Deserialize<'de>, K: Deserialize<'de>"))]
+struct Repr<T, C, K> {
+	data: Vec<T>,
+	index: Vec<(C, Vec<K>)>,
+}
+
+pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+where
+	T: MicroRecord + Deserialize<'de>,
+	T::Key: Deserialize<'de>,
+	T::Category: Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+	D: Deserializer<'de>,
+{
+	let repr: Repr<T, T::Category, T::Key> = Repr::deserialize(deserializer)?;
+	let mut table: MicroTable<T, H> = MicroTable::default();
+	table.data.reserve(repr.data.len());
+	for val in repr.data {
+		let Some(vacant) = table.data.vacant(val.key()) else {
+			return Err(D::Error::custom("duplicate key in table data"));
+		};
+		vacant.insert(val);
+	}
+	for (slot, _, val) in table.data.iter_slots() {
+		crate::index_add(&mut table.buckets, table.postings, crate::slot_id(slot), val.buckets());
+	}
+	table.index.reserve(repr.index.len());
+	for (cat, keys) in repr.index {
+		if keys.is_empty() {
+			return Err(D::Error::custom("empty category in table index"));
+		}
+		let mut posting = Posting::empty();
+		for k in keys {
+			let Some(slot) = table.data.slot_of(&k) else {
+				return Err(D::Error::custom("table index refers to a missing key"));
+			};
+			posting.insert(crate::slot_id(slot), table.postings, table.hasher());
+		}
+		if table.index.insert(cat, Arc::new(posting)).is_some() {
+			return Err(D::Error::custom("duplicate category in table index"));
+		}
+	}
+	Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Edge;
+
+	#[derive(Serialize, Deserialize)]
+	struct Graph {
+		#[serde(with = "crate::indexed")]
+		edges: MicroTable<Edge>,
+	}
+
+	#[test]
+	fn roundtrip() {
+		let mut edges = MicroTable::new();
+		edges.insert(Edge { id: 1, v1: 10, v2: 20 }).unwrap();
+		edges.insert(Edge { id: 2, v1: 20, v2: 30 }).unwrap();
+		let s = serde_json::to_string(&Graph { edges }).unwrap();
+
+		let g: Graph = serde_json::from_str(&s).unwrap();
+		assert_eq!(g.edges.len(), 2);
+		assert_eq!(g.edges.find(&20).len(), 2);
+		assert_eq!(g.edges.find(&30), vec![&Edge { id: 2, v1: 20, v2: 30 }]);
+	}
+
+	#[test]
+	fn inconsistent_index() {
+		let s = r#"{"edges": {"data": [{"id": 1, "v1": 10, "v2": 20}], "index": [[10, [1]], [20, [2]]]}}"#;
+		assert!(serde_json::from_str::<Graph>(s).is_err());
+		let s = r#"{"edges": {"data": [{"id": 1, "v1": 10, "v2": 20}, {"id": 1, "v1": 10, "v2": 20}], "index": []}}"#;
+		assert!(serde_json::from_str::<Graph>(s).is_err());
+	}
+}
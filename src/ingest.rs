@@ -0,0 +1,145 @@
+//! Intake for high-throughput appends: producers [`Intake::push`] new records into a lock-free queue, without
+//! taking any lock of the table, and a single consumer folds them into the table now and then, with a lock
+//! taken once per fold rather than once per record. Records are found by the table's queries only once folded.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use crossbeam_queue::SegQueue;
+use crate::{KeyError, MicroRecord, MicroTable, Rejected};
+
+/// Queue of records to insert into a table, see the [module docs](crate::ingest).
+pub struct Intake<T> {
+	queue: SegQueue<T>,
+}
+
+impl<T> Default for Intake<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T> Intake<T> {
+	pub fn new() -> Self {
+		Self { queue: SegQueue::new() }
+	}
+
+	/// Appends a record, from any thread. It doesn't check the key: a key that's taken fails in the fold.
+	pub fn push(&self, val: T) {
+		self.queue.push(val);
+	}
+
+	/// Records waiting to be folded.
+	pub fn len(&self) -> usize {
+		self.queue.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.queue.is_empty()
+	}
+
+	/// Takes out the records pushed so far, in the order they were pushed. Later pushes wait for the next fold,
+	/// so a fold ends even while producers keep pushing.
+	fn take(&self) -> impl Iterator<Item = T> + '_ {
+		(0..self.queue.len()).map_while(|_| self.queue.pop())
+	}
+}
+
+impl<T: MicroRecord> Intake<T> {
+	/// Inserts the pushed records into the table, and returns the ones it rejected, with their errors.
+	pub fn fold_into<H: BuildHasher + Clone>(&self, table: &mut MicroTable<T, H>) -> Vec<Rejected<T>> {
+		table.reserve(self.len());
+		self.take().filter_map(|val| insert(table, val)).collect()
+	}
+
+	/// [`Intake::fold_into`] a [`crate::ConcurrentTable`], with each shard locked once.
+	#[cfg(feature = "concurrent")]
+	pub fn fold_into_concurrent<H: BuildHasher + Clone>(&self, table: &crate::ConcurrentTable<T, H>) -> Vec<Rejected<T>> {
+		let mut by_shard: Vec<Vec<T>> = (0..table.shard_count()).map(|_| Vec::new()).collect();
+		for val in self.take() {
+			by_shard[table.shard_of(&val.key())].push(val);
+		}
+		let mut rejected = Vec::new();
+		for (shard, vals) in by_shard.into_iter().enumerate().filter(|(_, vals)| !vals.is_empty()) {
+			let mut shard = table.write_shard(shard);
+			rejected.extend(vals.into_iter().filter_map(|val| insert(&mut shard, val)));
+		}
+		rejected
+	}
+}
+
+/// Inserts the record, or gives it back with the error.
+fn insert<T: MicroRecord, H: BuildHasher + Clone>(table: &mut MicroTable<T, H>, val: T) -> Option<Rejected<T>> {
+	let key = val.key();
+	let checked = match table.contains_key(&key) {
+		true => Err(KeyError::Collision),
+		false => table.check_unique(&val, &|_| false),
+	};
+	if let Err(error) = checked {
+		return Some(Rejected { error, record: Some(val) });
+	}
+	// checked above, so it can't fail
+	let _ = table.insert_keyed(key, val);
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Reading {
+		sensor: u32,
+		seq: u32,
+	}
+
+	impl MicroRecord for Reading {
+		type Key = (u32, u32);
+		type Category = u32;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.sensor]
+		}
+		fn key(&self) -> Self::Key {
+			(self.sensor, self.seq)
+		}
+	}
+
+	#[test]
+	fn producers_push_consumer_folds() {
+		let intake = Intake::new();
+		let mut readings = MicroTable::<Reading>::new();
+		std::thread::scope(|s| {
+			for sensor in 0..4 {
+				let intake = &intake;
+				s.spawn(move || {
+					for seq in 0..1000 {
+						intake.push(Reading { sensor, seq });
+					}
+				});
+			}
+			while readings.len() < 4000 {
+				assert!(intake.fold_into(&mut readings).is_empty());
+			}
+		});
+		assert_eq!((readings.len(), readings.find(&2).len()), (4000, 1000));
+
+		intake.push(Reading { sensor: 9, seq: 0 });
+		intake.push(Reading { sensor: 1, seq: 5 });
+		let rejected = intake.fold_into(&mut readings);
+		assert_eq!(rejected.len(), 1);
+		assert_eq!((&rejected[0].error, &rejected[0].record), (&KeyError::Collision, &Some(Reading { sensor: 1, seq: 5 })));
+		assert!(intake.is_empty() && readings.contains_key(&(9, 0)));
+	}
+
+	#[cfg(feature = "concurrent")]
+	#[test]
+	fn fold_into_concurrent_table() {
+		let intake = Intake::new();
+		let readings = crate::ConcurrentTable::<Reading>::with_shards(4);
+		for seq in 0..100 {
+			intake.push(Reading { sensor: seq % 3, seq });
+		}
+		intake.push(Reading { sensor: 0, seq: 0 });
+		assert_eq!(intake.fold_into_concurrent(&readings).len(), 1);
+		assert_eq!((readings.len(), readings.find(&1).len()), (100, 33));
+	}
+}
@@ -0,0 +1,102 @@
+use alloc::{sync::Arc, vec::Vec};
+use crate::map::HashMap;
+
+/// Compact handle of a string stored in an [`Interner`]. It's `Copy` and hashes as a `u32`,
+/// so it's a cheap `MicroRecord::Category` in place of `String`.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+	pub fn id(&self) -> u32 {
+		self.0
+	}
+}
+
+/// Stores each distinct string once and hands out [`Symbol`]s for them.
+///
+/// Records keep symbols in their category fields, and queries by string go through [`Interner::get`]:
+/// ```
+/// # use microtable::Interner;
+/// let mut authors = Interner::new();
+/// let a = authors.intern("Tolstoy");
+/// assert_eq!(authors.intern("Tolstoy"), a);
+/// assert_eq!(authors.get("Tolstoy"), Some(a));
+/// assert_eq!(authors.resolve(a), Some("Tolstoy"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+	ids: HashMap<Arc<str>, Symbol>,
+	strings: Vec<Arc<str>>,
+}
+
+impl Interner {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the symbol of the string, storing the string if it's new. Panics past `u32::MAX` strings.
+	pub fn intern(&mut self, s: &str) -> Symbol {
+		if let Some(sym) = self.ids.get(s) {
+			return *sym;
+		}
+		let sym = Symbol(u32::try_from(self.strings.len()).expect("more than u32::MAX interned strings"));
+		let s: Arc<str> = s.into();
+		self.strings.push(s.clone());
+		self.ids.insert(s, sym);
+		sym
+	}
+
+	/// Looks up the symbol without interning, for queries: a string that was never interned can't be a category.
+	pub fn get(&self, s: &str) -> Option<Symbol> {
+		self.ids.get(s).copied()
+	}
+
+	pub fn resolve(&self, sym: Symbol) -> Option<&str> {
+		self.strings.get(sym.0 as usize).map(|s| s.as_ref())
+	}
+
+	pub fn len(&self) -> usize {
+		self.strings.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.strings.is_empty()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{MicroRecord, MicroTable};
+
+	#[derive(Debug, Clone)]
+	struct Post {
+		id: usize,
+		tags: Vec<Symbol>,
+	}
+
+	impl MicroRecord for Post {
+		type Key = usize;
+		type Category = Symbol;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			self.tags.iter().copied()
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn interned_categories() {
+		let mut tags = Interner::new();
+		let mut it: MicroTable<Post> = MicroTable::new();
+		it.insert(Post { id: 1, tags: vec![tags.intern("rust"), tags.intern("db")] }).unwrap();
+		it.insert(Post { id: 2, tags: vec![tags.intern("rust")] }).unwrap();
+		assert_eq!(tags.len(), 2);
+
+		let rust = tags.get("rust").unwrap();
+		assert_eq!(it.find(&rust).len(), 2);
+		assert_eq!(tags.get("python"), None);
+		assert_eq!(tags.resolve(rust), Some("rust"));
+	}
+}
@@ -0,0 +1,90 @@
+//! JSON Lines: one record per line, read and written as a stream, so big tables don't go through
+//! one JSON array in memory.
+
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, vec::Vec};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use serde::{Serialize, de::{DeserializeOwned, Error}};
+use crate::{MicroRecord, MicroTable, RecordError, SkippedRecord};
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Writes the records, each as JSON on its own line.
+	pub fn write_jsonl(&self, writer: impl Write) -> serde_json::Result<()>
+	where T: Serialize {
+		let mut w = BufWriter::new(writer);
+		for val in self.values() {
+			serde_json::to_writer(&mut w, val)?;
+			w.write_all(b"\n").map_err(serde_json::Error::io)?;
+		}
+		w.flush().map_err(serde_json::Error::io)
+	}
+
+	/// Reads records written one per line (any whitespace between them works). Fails at the first record
+	/// that doesn't parse, telling its line, or that the table rejects, e.g. for a repeated key.
+	pub fn read_jsonl(reader: impl Read) -> serde_json::Result<Self>
+	where T: DeserializeOwned, H: Default {
+		let mut table = Self::default();
+		let mut stream = serde_json::Deserializer::from_reader(reader).into_iter::<T>();
+		while let Some(val) = stream.next().transpose()? {
+			if let Err(e) = table.insert(val) {
+				let reason = if matches!(e, crate::KeyError::Collision) { "duplicate key" } else { "repeated unique value" };
+				return Err(serde_json::Error::custom(format_args!("{reason} in the record ending at byte {}", stream.byte_offset())));
+			}
+		}
+		Ok(table)
+	}
+
+	/// Reads records written one per line, skipping the lines that don't parse or whose records the table rejects,
+	/// and returns them with the table. Unlike [`MicroTable::read_jsonl`], a record must be on one line.
+	/// Fails only if the input can't be read.
+	pub fn read_jsonl_lenient(reader: impl Read) -> io::Result<(Self, Vec<SkippedRecord<T::Key>>)>
+	where T: DeserializeOwned, H: Default {
+		let (mut table, mut skipped) = (Self::default(), vec![]);
+		let (mut r, mut buf, mut line) = (BufReader::new(reader), vec![], 0);
+		while r.read_until(b'\n', &mut buf)? > 0 {
+			line += 1;
+			if !buf.trim_ascii().is_empty() {
+				let error = match serde_json::from_slice::<T>(&buf) {
+					Ok(val) => table.insert(val).err().map(RecordError::Rejected),
+					Err(e) => Some(RecordError::Decode(Box::new(e))),
+				};
+				skipped.extend(error.map(|error| SkippedRecord { position: line, error }));
+			}
+			buf.clear();
+		}
+		Ok((table, skipped))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Note;
+
+	#[test]
+	fn jsonl_roundtrip() {
+		let notes: MicroTable<Note> = (0..3).map(|id| Note { id, tag: if id == 1 { "stop".into() } else { "go".into() } }).collect();
+		let mut out = vec![];
+		notes.write_jsonl(&mut out).unwrap();
+		let text = String::from_utf8(out).unwrap();
+		assert_eq!(text.lines().count(), 3);
+		assert!(text.lines().all(|l| l.starts_with("{\"id\":")));
+		let back = MicroTable::<Note>::read_jsonl(text.as_bytes()).unwrap();
+		assert_eq!(back.find(&"go".into()).len(), 2);
+
+		assert!(MicroTable::<Note>::read_jsonl(format!("{text}{{\"id\": 1, \"tag\": \"go\"}}\n").as_bytes()).is_err());
+		let broken = MicroTable::<Note>::read_jsonl("{\"id\": 1, \"tag\": \"go\"}\n{\"id\": 2}\n".as_bytes()).unwrap_err();
+		assert_eq!(broken.line(), 2);
+	}
+
+	#[test]
+	fn lenient_jsonl() {
+		let text = "{\"id\": 1, \"tag\": \"go\"}\n{\"id\": 2}\n\n{\"id\": 1, \"tag\": \"stop\"}\n{\"id\": 3, \"tag\": \"go\"}\n";
+		let (notes, skipped) = MicroTable::<Note>::read_jsonl_lenient(text.as_bytes()).unwrap();
+		assert_eq!(notes.find(&"go".into()).len(), 2);
+		assert!(matches!(skipped[..], [
+			SkippedRecord { position: 2, error: RecordError::Decode(_) },
+			SkippedRecord { position: 4, error: RecordError::Rejected(crate::KeyError::Collision) },
+		]));
+	}
+}
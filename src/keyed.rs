@@ -0,0 +1,127 @@
+//! Serde format that writes the table as a map from keys to records, e.g. `{"1": {...}, "2": {...}}` in JSON,
+//! which is easier to read and to diff than the default list. Use it with `#[serde(with = "microtable::keyed")]`:
+//!
+//! ```
+//! # use microtable::{MicroTable, MicroRecord};
+//! # use serde::{Serialize, Deserialize};
+//! # #[derive(Serialize, Deserialize)]
+//! # struct User { id: u32, team: u32 }
+//! # impl MicroRecord for User {
+//! #     type Key = u32;
+//! #     type Category = u32;
+//! #     fn categories(&self) -> impl IntoIterator<Item = u32> { [self.team] }
+//! #     fn key(&self) -> u32 { self.id }
+//! # }
+//! #[derive(Serialize, Deserialize)]
+//! struct Org {
+//!     #[serde(with = "microtable::keyed")]
+//!     users: MicroTable<User>,
+//! }
+//! ```
+//!
+//! On load every map key must be the key of its record, and appear once.
+
+use core::{hash::BuildHasher, marker::PhantomData};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{Error, MapAccess, Visitor}};
+use crate::{IndexPresize, MicroRecord, MicroTable, OnDuplicate, seed::MAX_PRESIZE};
+
+pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	T: MicroRecord + Serialize,
+	T::Key: Serialize,
+	H: BuildHasher + Clone,
+	S: Serializer,
+{
+	serializer.collect_map(table.iter())
+}
+
+struct KeyedVisitor<T, H>(OnDuplicate, PhantomData<fn() -> (T, H)>);
+
+impl<'de, T, H> Visitor<'de> for KeyedVisitor<T, H>
+where
+	T: MicroRecord + Deserialize<'de>,
+	T::Key: Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+{
+	type Value = MicroTable<T, H>;
+
+	fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		f.write_str("a map of keys to records")
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+		let expected = map.size_hint().unwrap_or(0).min(MAX_PRESIZE);
+		let mut table = MicroTable::with_capacity_and_hasher(expected, 0, H::default());
+		let mut presize = IndexPresize::new(&table, expected);
+		for loaded in 0.. {
+			presize.reserve(&mut table, loaded);
+			let Some((key, val)) = map.next_entry::<T::Key, T>()? else { break };
+			if key != val.key() {
+				return Err(A::Error::custom("map key differs from the record's key"));
+			}
+			crate::seed::add(&mut table, val, self.0)?;
+		}
+		Ok(table)
+	}
+}
+
+pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+where
+	T: MicroRecord + Deserialize<'de>,
+	T::Key: Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+	D: Deserializer<'de>,
+{
+	deserialize_with(deserializer, OnDuplicate::Error)
+}
+
+/// Loads the map, handling repeated keys by the policy.
+pub(crate) fn deserialize_with<'de, T, H, D>(deserializer: D, on_duplicate: OnDuplicate) -> Result<MicroTable<T, H>, D::Error>
+where
+	T: MicroRecord + Deserialize<'de>,
+	T::Key: Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+	D: Deserializer<'de>,
+{
+	deserializer.deserialize_map(KeyedVisitor(on_duplicate, PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, PartialEq, Serialize, Deserialize)]
+	struct User {
+		id: u32,
+		team: u32,
+	}
+
+	impl MicroRecord for User {
+		type Key = u32;
+		type Category = u32;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.team]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[derive(Serialize, Deserialize)]
+	struct Org {
+		#[serde(with = "crate::keyed")]
+		users: MicroTable<User>,
+	}
+
+	#[test]
+	fn roundtrip() {
+		let users = [User { id: 1, team: 7 }, User { id: 2, team: 7 }].into_iter().collect();
+		let s = serde_json::to_string(&Org { users }).unwrap();
+		assert!(s.contains(r#""1":{"id":1,"team":7}"#));
+		let org: Org = serde_json::from_str(&s).unwrap();
+		assert_eq!(org.users.find(&7).len(), 2);
+
+		assert!(serde_json::from_str::<Org>(r#"{"users": {"1": {"id": 2, "team": 7}}}"#).is_err());
+		assert!(serde_json::from_str::<Org>(r#"{"users": {"1": {"id": 1, "team": 7}, "1": {"id": 1, "team": 8}}}"#).is_err());
+	}
+}
@@ -0,0 +1,103 @@
+use std::sync::OnceLock;
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, vec::Vec};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{MicroRecord, MmapTable, RandomState, map::Map};
+
+/// Table over a file written by [`crate::MicroTable::freeze_to_file`] that loads the keys and the category index
+/// on open, but leaves the records encoded in the mapped file. A record is decoded the first time it's read,
+/// and kept, so later reads return the same reference. For big snapshots of which a run reads a small part.
+pub struct LazyTable<T: MicroRecord, H = RandomState> {
+	file: MmapTable<T>,
+	keys: Map<T::Key, usize, H>,
+	index: Map<T::Category, Box<[u32]>, H>,
+	decoded: Box<[OnceLock<T>]>,
+}
+
+impl<T> LazyTable<T>
+where
+	T: MicroRecord + DeserializeOwned,
+	T::Key: Serialize + DeserializeOwned,
+	T::Category: Serialize + DeserializeOwned,
+{
+	pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+		Self::open_with_hasher(path, RandomState::default())
+	}
+}
+
+impl<T, H> LazyTable<T, H>
+where
+	T: MicroRecord + DeserializeOwned,
+	T::Key: Serialize + DeserializeOwned,
+	T::Category: Serialize + DeserializeOwned,
+	H: BuildHasher + Clone,
+{
+	/// Opens the file, with `hasher` for the key and category maps.
+	pub fn open_with_hasher(path: impl AsRef<std::path::Path>, hasher: H) -> std::io::Result<Self> {
+		let file = MmapTable::open(path)?;
+		let mut keys = Map::with_hasher(hasher.clone());
+		keys.extend(file.iter_keys().enumerate().map(|(i, k)| (k, i)));
+		let mut index = Map::with_hasher(hasher);
+		for (c, cat) in file.iter_cats().enumerate() {
+			index.insert(cat, file.posting(c).map(|i| i as u32).collect());
+		}
+		let decoded = (0..file.len()).map(|_| OnceLock::new()).collect();
+		Ok(Self { file, keys, index, decoded })
+	}
+
+	pub fn len(&self) -> usize {
+		self.decoded.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.decoded.is_empty()
+	}
+
+	/// How many records have been decoded so far.
+	pub fn decoded_len(&self) -> usize {
+		self.decoded.iter().filter(|d| d.get().is_some()).count()
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.keys.contains_key(key)
+	}
+
+	pub fn contains_cat(&self, cat: &T::Category) -> bool {
+		self.index.contains_key(cat)
+	}
+
+	fn record(&self, i: usize) -> &T {
+		self.decoded[i].get_or_init(|| self.file.record(i))
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<&T> {
+		self.keys.get(key).map(|i| self.record(*i))
+	}
+
+	pub fn find_iter(&self, cat: &T::Category) -> impl Iterator<Item = &T> {
+		self.index.get(cat).into_iter().flat_map(|slots| slots.iter()).map(|i| self.record(*i as usize))
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<&T> {
+		self.find_iter(cat).collect()
+	}
+
+	/// All the records, decoding the ones that weren't yet.
+	pub fn values(&self) -> impl Iterator<Item = &T> {
+		(0..self.len()).map(|i| self.record(i))
+	}
+
+	pub fn iter_keys(&self) -> impl Iterator<Item = &T::Key> {
+		self.keys.keys()
+	}
+
+	pub fn iter_cats(&self) -> impl Iterator<Item = &T::Category> {
+		self.index.keys()
+	}
+}
+
+impl<T: MicroRecord, H> core::fmt::Debug for LazyTable<T, H> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("LazyTable").field("len", &self.decoded.len()).finish_non_exhaustive()
+	}
+}
@@ -0,0 +1,32 @@
+//! Reports of lenient loads, which skip the records they can't load instead of failing.
+
+use alloc::boxed::Box;
+use crate::KeyError;
+
+/// Why a lenient load skipped a record.
+#[derive(Debug)]
+pub enum RecordError<K> {
+	/// The record doesn't decode, e.g. it was written by an older version of the record type.
+	Decode(Box<dyn std::error::Error + Send + Sync>),
+	/// The table rejected the record, for a repeated key or unique value.
+	Rejected(KeyError<K>),
+}
+
+impl<K: core::fmt::Debug> core::fmt::Display for RecordError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Decode(e) => write!(f, "record doesn't decode: {e}"),
+			Self::Rejected(e) => e.fmt(f),
+		}
+	}
+}
+
+impl<K: core::fmt::Debug> std::error::Error for RecordError<K> {}
+
+/// A record that a lenient load skipped, at `position`: the number of the record from 0 in a binary snapshot,
+/// the line from 1 in JSON Lines.
+#[derive(Debug)]
+pub struct SkippedRecord<K> {
+	pub position: u64,
+	pub error: RecordError<K>,
+}
@@ -1,50 +1,594 @@
-use std::{hash::Hash, collections::{HashMap, HashSet}};
+#![cfg_attr(not(any(feature="std", test)), no_std)]
+extern crate alloc;
+
+#[cfg(not(any(feature="std", feature="hashbrown")))]
+compile_error!("microtable needs either the `std` or the `hashbrown` feature");
+
+use core::hash::{BuildHasher, Hash};
+use alloc::{sync::Arc, vec, vec::Vec};
+#[cfg(feature="std")]
+use std::{hash::RandomState, time::{Duration, Instant}};
+/// Default hasher of the tables: SipHash, or foldhash from `hashbrown` without `std`.
+#[cfg(not(feature="std"))]
+use hashbrown::DefaultHashBuilder as RandomState;
+/// Without `std` there's no clock: records can't get a TTL, and the deadlines map stays empty.
+#[cfg(not(feature="std"))]
+type Instant = core::convert::Infallible;
 #[cfg(feature="serde")]
-use serde::{Serialize, Deserialize};
-
-pub trait MicroRecord: Clone {
+use ::serde::{Serialize, Deserialize};
+
+mod intern;
+pub use intern::{Interner, Symbol};
+mod unique;
+pub use unique::UniqueId;
+use unique::Constraints;
+mod map;
+use map::{HashSet, Map, Set, map_remove, map_swap_remove, set_insert};
+#[cfg(feature="imbl")]
+use map::NoCapacity;
+#[cfg(feature="imbl")]
+mod chunks;
+mod slab;
+use slab::Slab;
+#[cfg(test)]
+#[allow(dead_code)] // each record is used only by the tests of some features
+mod fixtures;
+mod handle;
+pub use handle::{Anon, Handle};
+mod snapshot;
+pub use snapshot::TableSnapshot;
+mod view;
+pub use view::MapView;
+mod columns;
+pub use columns::ColumnId;
+mod evict;
+use evict::Eviction;
+mod posting;
+pub use posting::PostingKind;
+use posting::Posting;
+mod small;
+mod frozen;
+pub use frozen::FrozenTable;
+pub use small::{INLINE, SmallTable};
+#[cfg(feature="serde")]
+pub mod indexed;
+#[cfg(feature="serde")]
+pub mod keyed;
+#[cfg(feature="serde")]
+pub mod versioned;
+#[cfg(feature="serde")]
+mod seed;
+#[cfg(feature="serde")]
+pub mod serde;
+#[cfg(feature="serde")]
+mod op;
+#[cfg(feature="serde")]
+pub use op::TableOp;
+#[cfg(feature="csv")]
+mod csv_io;
+#[cfg(feature="csv")]
+pub use csv_io::{LineError, RejectedLine};
+#[cfg(feature="jsonl")]
+mod jsonl;
+#[cfg(any(feature="binary", feature="jsonl"))]
+mod lenient;
+#[cfg(any(feature="binary", feature="jsonl"))]
+pub use lenient::{RecordError, SkippedRecord};
+#[cfg(feature="binary")]
+pub mod binary;
+#[cfg(feature="encryption")]
+pub mod sealed;
+#[cfg(any(feature="mmap", feature="rkyv"))]
+mod probe;
+#[cfg(feature="rkyv")]
+mod archive;
+#[cfg(feature="rkyv")]
+pub use archive::RkyvTable;
+#[cfg(feature="fs")]
+pub mod fs;
+#[cfg(feature="fs")]
+pub use fs::FileError;
+#[cfg(feature="wal")]
+pub mod wal;
+#[cfg(feature="wal")]
+pub use wal::{CheckpointPolicy, WalError, WalTable};
+#[cfg(feature="sled")]
+mod sled_store;
+#[cfg(feature="sled")]
+pub use sled_store::{SledError, SledTable};
+#[cfg(feature="sqlite")]
+mod sqlite;
+#[cfg(feature="sqlite")]
+pub use sqlite::SqliteError;
+#[cfg(feature="arrow")]
+mod arrow;
+#[cfg(feature="parquet")]
+mod parquet_io;
+#[cfg(feature="parquet")]
+pub use parquet_io::ParquetError;
+#[cfg(feature="polars")]
+mod polars_io;
+#[cfg(feature="schemars")]
+mod schema;
+#[cfg(feature="utoipa")]
+mod openapi;
+#[cfg(feature="patch")]
+mod patch;
+#[cfg(feature="patch")]
+pub use patch::PatchError;
+#[cfg(feature="dynamic")]
+pub mod dynamic;
+#[cfg(feature="display")]
+mod display;
+#[cfg(feature="display")]
+pub use display::Column;
+#[cfg(feature="dot")]
+mod dot;
+#[cfg(feature="delta")]
+pub mod delta;
+#[cfg(feature="hooks")]
+pub mod hooks;
+#[cfg(feature="crdt")]
+pub mod crdt;
+#[cfg(feature="serde")]
+pub use seed::{OnDuplicate, TableSeed};
+#[cfg(feature="nohash")]
+pub mod nohash;
+#[cfg(feature="mmap")]
+mod mmap;
+#[cfg(feature="mmap")]
+pub use mmap::MmapTable;
+#[cfg(feature="mmap")]
+mod lazy;
+#[cfg(feature="mmap")]
+pub use lazy::LazyTable;
+#[cfg(feature="concurrent")]
+pub mod concurrent;
+#[cfg(feature="concurrent")]
+pub use concurrent::{ArcConcurrentTable, ConcurrentTable};
+#[cfg(feature="dashmap")]
+pub mod dash;
+#[cfg(feature="rayon")]
+mod par;
+#[cfg(feature="arc-swap")]
+pub mod swap;
+#[cfg(feature="mvcc")]
+pub mod mvcc;
+#[cfg(feature="tokio")]
+pub mod async_table;
+#[cfg(feature="actor")]
+pub mod actor;
+#[cfg(feature="ingest")]
+pub mod ingest;
+#[cfg(feature="concurrent")]
+pub mod buffer;
+#[cfg(feature="metrics")]
+pub mod metrics;
+#[cfg(feature="bumpalo")]
+pub mod arena;
+#[cfg(feature="tiered")]
+mod tiered;
+#[cfg(feature="tiered")]
+pub use tiered::TieredTable;
+
+pub trait MicroRecord {
 	type Key: Hash + Eq + Clone;
 	type Category: Hash + Eq + Clone;
-	fn categories(&self) -> Vec<Self::Category>;
+	/// Categories the record is indexed by. Any iterable works: a `Vec`, a fixed-size array, or an iterator
+	/// borrowing from `self`, so records with a few fixed categories don't have to allocate.
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category>;
+	/// Key of the record. The table stores it next to the record, so it's called once per insert or update
+	/// and may be costly to compute.
 	fn key(&self) -> Self::Key;
+	/// Coarse buckets of the record (e.g. timestamp to day, price to band), kept in a separate index
+	/// and queried with [`MicroTable::find_bucket`], so they don't mix with `categories()` results. None by default.
+	fn buckets(&self) -> impl IntoIterator<Item = Self::Category> {
+		[]
+	}
+	/// Bytes the record owns on the heap (strings, vectors...), for [`MicroTable::memory_usage`]. 0 by default.
+	fn heap_size(&self) -> usize {
+		0
+	}
+}
+
+/// Records shared via `Arc` are indexed as the records themselves.
+impl<T: MicroRecord> MicroRecord for Arc<T> {
+	type Key = T::Key;
+	type Category = T::Category;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		(**self).categories()
+	}
+	fn key(&self) -> Self::Key {
+		(**self).key()
+	}
+	fn buckets(&self) -> impl IntoIterator<Item = Self::Category> {
+		(**self).buckets()
+	}
+	fn heap_size(&self) -> usize {
+		core::mem::size_of::<T>() + (**self).heap_size()
+	}
 }
 
-#[derive(Debug, Clone)]
-pub struct MicroTable<T: MicroRecord> {
-	data: HashMap<T::Key, T>,
-	index: HashMap<T::Category, HashSet<T::Key>>
+/// Table of `Arc`-wrapped records: `find` and `get` results can be cloned cheaply and sent to other threads.
+/// Updates are copy-on-write with `Arc::make_mut` in the callback, e.g. `table.modify(&key, |b| Arc::make_mut(b).year += 1)`:
+/// the record is copied only if someone else still holds the old version.
+pub type ArcTable<T, H = RandomState> = MicroTable<Arc<T>, H>;
+
+/// Table hashing with ahash instead of SipHash. Create it with `AHashTable::default()`.
+#[cfg(feature="ahash")]
+pub type AHashTable<T> = MicroTable<T, ahash::RandomState>;
+
+/// Table hashing with FxHash, fast for integer and short string keys but not DoS-resistant.
+/// Create it with `FxTable::default()`.
+#[cfg(feature="fxhash")]
+pub type FxTable<T> = MicroTable<T, rustc_hash::FxBuildHasher>;
+
+/// Records of one category by storage slot, shared with snapshots until the table changes them.
+type SlotSet<H> = Arc<Posting<H>>;
+
+/// Callback of [`MicroTable::on_evict`], shared by the clones of the table.
+type EvictFn<T> = Arc<dyn Fn(T) + Send + Sync>;
+
+/// Records by key, indexed by categories. `H` is the hasher of all internal maps and sets.
+#[derive(Clone)]
+pub struct MicroTable<T: MicroRecord, H = RandomState> {
+	data: Slab<T::Key, T, H>,
+	index: Map<T::Category, SlotSet<H>, H>,
+	buckets: Map<T::Category, SlotSet<H>, H>,
+	partial: Vec<PartialIndex<T, H>>,
+	expiry: Map<T::Key, Instant, H>,
+	unique: Constraints<T>,
+	eviction: Option<Eviction<T::Key, H>>,
+	on_evict: Option<EvictFn<T>>,
+	postings: PostingKind,
 }
 
-#[derive(Debug)]
-pub enum KeyError {
+// Debug is written out, since persistent sets need `H: BuildHasher` for it
+impl<T: MicroRecord + core::fmt::Debug, H: BuildHasher + core::fmt::Debug> core::fmt::Debug for MicroTable<T, H>
+where T::Key: core::fmt::Debug, T::Category: core::fmt::Debug {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("MicroTable")
+			.field("data", &self.data)
+			.field("index", &self.index)
+			.field("buckets", &self.buckets)
+			.field("partial", &self.partial)
+			.field("expiry", &self.expiry)
+			.field("unique", &self.unique)
+			.field("eviction", &self.eviction)
+			.field("on_evict", &self.on_evict.is_some())
+			.field("postings", &self.postings)
+			.finish()
+	}
+}
+
+/// Category index that covers only the records passing `filter`.
+#[derive(Clone)]
+struct PartialIndex<T: MicroRecord, H> {
+	filter: fn(&T) -> bool,
+	index: Map<T::Category, SlotSet<H>, H>,
+}
+
+impl<T: MicroRecord, H: BuildHasher> core::fmt::Debug for PartialIndex<T, H>
+where T::Key: core::fmt::Debug, T::Category: core::fmt::Debug {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("PartialIndex").field("index", &self.index).finish_non_exhaustive()
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> PartialIndex<T, H> {
+	fn add(&mut self, kind: PostingKind, slot: u32, val: &T) {
+		if (self.filter)(val) {
+			index_add(&mut self.index, kind, slot, val.categories());
+		}
+	}
+
+	fn remove(&mut self, slot: u32, val: &T) {
+		if (self.filter)(val) {
+			index_remove(&mut self.index, slot, val.categories());
+		}
+	}
+
+	/// Moves the slot of a replaced record between the categories that changed, and in or out of the index if the
+	/// filter result changed. Records that fail the filter before and after aren't touched.
+	fn replace(&mut self, kind: PostingKind, slot: u32, old: &T, new: &T) {
+		match ((self.filter)(old), (self.filter)(new)) {
+			(true, true) => index_diff(&mut self.index, kind, slot, old.categories(), new.categories()),
+			(true, false) => index_remove(&mut self.index, slot, old.categories()),
+			(false, true) => index_add(&mut self.index, kind, slot, new.categories()),
+			(false, false) => {},
+		}
+	}
+}
+
+fn index_add<C: Hash + Eq + Clone, H: BuildHasher + Clone>(index: &mut Map<C, SlotSet<H>, H>, kind: PostingKind, slot: u32, cats: impl IntoIterator<Item = C>) {
+	let hasher = index.hasher().clone();
+	for cat in cats {
+		let keys = index.entry(cat).or_insert_with(|| Arc::new(Posting::empty()));
+		Arc::make_mut(keys).insert(slot, kind, &hasher);
+	}
+}
+
+fn index_remove<C: Hash + Eq + Clone, H: BuildHasher + Clone>(index: &mut Map<C, SlotSet<H>, H>, slot: u32, cats: impl IntoIterator<Item = C>) {
+	for cat in cats {
+		if let Some(keys) = index.get_mut(&cat) {
+			let keys = Arc::make_mut(keys);
+			keys.remove(slot);
+			if keys.is_empty() {
+				map_remove(index, &cat);
+			} else if keys.capacity() > SHRINK_MIN && keys.len() * 4 < keys.capacity() {
+				// after heavy removal, give memory back, but leave room to grow without reallocating right away
+				keys.shrink_to(keys.len() * 2);
+			}
+		}
+	}
+}
+
+/// Slot number of a record in the indexes. Slots are dense, so they outgrow `u32` only with as many records.
+fn slot_id(slot: usize) -> u32 {
+	u32::try_from(slot).expect("more than u32::MAX records")
+}
+
+/// Posting sets smaller than this aren't shrunk on removal, it's not worth reallocating them.
+const SHRINK_MIN: usize = 64;
+
+/// Start of an FNV-1a hash, see [`fnv1a`].
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// Continues an FNV-1a hash of bytes from `hash`. Used in files: it's stable across platforms and versions, unlike the std hashers.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+	bytes.iter().fold(hash, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Syncs the directory of `path`, so that a file renamed into it is still there after a crash. Windows can't open
+/// directories as files, so there it does nothing.
+#[cfg(any(feature="fs", feature="wal"))]
+fn sync_parent(path: &std::path::Path) -> std::io::Result<()> {
+	#[cfg(unix)]
+	{
+		let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+		std::fs::File::open(dir)?.sync_all()?;
+	}
+	#[cfg(not(unix))]
+	let _ = path;
+	Ok(())
+}
+
+/// [`fnv1a`] as a `Hasher`, for hashes that are the same in every process.
+struct Fnv(u64);
+
+impl Default for Fnv {
+	fn default() -> Self {
+		Self(FNV_OFFSET)
+	}
+}
+
+impl core::hash::Hasher for Fnv {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		self.0 = fnv1a(self.0, bytes);
+	}
+}
+
+/// Finalizer of SplitMix64, spreading every bit of the hash over all of the bits, so summed hashes don't cancel out.
+fn mix(mut h: u64) -> u64 {
+	h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+	h ^ (h >> 31)
+}
+
+/// Lists of categories up to this long are diffed by scanning rather than with hash sets.
+const DIFF_SCAN: usize = 16;
+
+/// Records inserted by [`MicroTable::try_extend`] before it sizes the index by their categories per record.
+const INDEX_SAMPLE: usize = 64;
+
+/// Sizing of the index during a bulk load of `expected` records: once the table holds [`INDEX_SAMPLE`] records,
+/// the index reserves room for the categories of the rest, at the categories per record so far.
+struct IndexPresize {
+	expected: usize,
+	sized_at: usize,
+	sized: bool,
+}
+
+impl IndexPresize {
+	fn new<T: MicroRecord, H: BuildHasher + Clone>(table: &MicroTable<T, H>, expected: usize) -> Self {
+		Self { expected, sized_at: table.len().max(INDEX_SAMPLE), sized: false }
+	}
+
+	/// Called before each record of the load is inserted, with the number of records loaded so far.
+	fn reserve<T: MicroRecord, H: BuildHasher + Clone>(&mut self, table: &mut MicroTable<T, H>, loaded: usize) {
+		if !self.sized && table.len() >= self.sized_at && loaded < self.expected {
+			table.index.reserve((self.expected - loaded) * table.index.len() / table.len());
+			self.sized = true;
+		}
+	}
+}
+
+/// Moves the slot between the categories that changed. Each side's categories are collected once, and an update
+/// that keeps them leaves the index as it is.
+fn index_diff<C: Hash + Eq + Clone, H: BuildHasher + Clone>(index: &mut Map<C, SlotSet<H>, H>, kind: PostingKind, slot: u32, old: impl IntoIterator<Item = C>, new: impl IntoIterator<Item = C>) {
+	let (old, new): (Vec<C>, Vec<C>) = (old.into_iter().collect(), new.into_iter().collect());
+	if old == new {
+		return;
+	}
+	if old.len().max(new.len()) <= DIFF_SCAN {
+		index_remove(index, slot, old.iter().filter(|c| !new.contains(c)).cloned());
+		index_add(index, kind, slot, new.iter().filter(|c| !old.contains(c)).cloned());
+		return;
+	}
+	// sets for lookups, but the lists keep the order of categories() for the index
+	let (old_set, new_set) = (vec2hashset(old.iter()), vec2hashset(new.iter()));
+	index_remove(index, slot, old.iter().filter(|c| !new_set.contains(c)).cloned());
+	index_add(index, kind, slot, new.iter().filter(|c| !old_set.contains(c)).cloned());
+}
+
+/// Handle of a partial index, returned by [`MicroTable::add_partial_index`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PartialIndexId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyError<K> {
 	Collision,
 	NotFound,
+	/// A uniqueness constraint is violated, `existing` is the key of the record that holds the value.
+	Unique { constraint: UniqueId, existing: K },
 }
-impl std::error::Error for KeyError {}
-impl std::fmt::Display for KeyError {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let s = match self {
-			Self::Collision => "key is busy",
-			Self::NotFound => "key not found"
-		};
-		f.write_fmt(format_args!("{}", s))
+#[cfg(feature="std")]
+impl<K: core::fmt::Debug> std::error::Error for KeyError<K> {}
+
+/// Error of [`MicroTable::modify`], and a record rejected by a fold of an `ingest::Intake`.
+#[derive(Debug)]
+pub struct Rejected<T: MicroRecord> {
+	pub error: KeyError<T::Key>,
+	/// The modified record, no longer in the table. `None` if the key wasn't found.
+	pub record: Option<T>,
+}
+impl<K: core::fmt::Debug> core::fmt::Display for KeyError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Collision => f.write_str("key is busy"),
+			Self::NotFound => f.write_str("key not found"),
+			Self::Unique { existing, .. } => f.write_fmt(format_args!("value is taken by key {:?}", existing)),
+		}
     }
 }
 
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for MicroTable<T, H> {
+	fn default() -> Self {
+		Self::with_hasher(H::default())
+	}
+}
+
 impl<T: MicroRecord> MicroTable<T> {
 	pub fn new() -> Self {
-		Self { data: HashMap::new(), index: HashMap::new() }
+		Self::default()
+	}
+
+	/// Empty table that keeps at most `max_records` records, evicting the least recently used ones: an indexed LRU cache.
+	/// See [`MicroTable::set_max_records`].
+	pub fn bounded(max_records: usize) -> Self {
+		let mut t = Self::new();
+		t.set_max_records(Some(max_records));
+		t
+	}
+
+	/// Empty table with room for `records` records in `categories` categories.
+	pub fn with_capacity(records: usize, categories: usize) -> Self {
+		Self::with_capacity_and_hasher(records, categories, RandomState::default())
 	}
+}
 
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Empty table whose maps and posting sets use `hasher`.
+	pub fn with_hasher(hasher: H) -> Self {
+		Self {
+			data: Slab::with_hasher(hasher.clone()),
+			index: Map::with_hasher(hasher.clone()),
+			buckets: Map::with_hasher(hasher.clone()),
+			partial: vec![],
+			expiry: Map::with_hasher(hasher),
+			unique: Constraints::new(),
+			eviction: None,
+			on_evict: None,
+			postings: PostingKind::Hashed,
+		}
+	}
+
+	pub fn with_capacity_and_hasher(records: usize, categories: usize, hasher: H) -> Self {
+		let mut t = Self::with_hasher(hasher);
+		t.data.reserve(records);
+		t.index.reserve(categories);
+		t
+	}
+
+	/// Reserves room for `additional` more records. Categories are allocated as they appear.
+	pub fn reserve(&mut self, additional: usize) {
+		self.data.reserve(additional);
+	}
+
+	/// Shrinks the data, the indexes and every category's posting set as much as possible.
+	pub fn shrink_to_fit(&mut self) {
+		if let Some(remap) = self.data.shrink_to_fit() {
+			self.renumber(&remap);
+		}
+		self.expiry.shrink_to_fit();
+		let indexes = core::iter::once(&mut self.index)
+			.chain(core::iter::once(&mut self.buckets))
+			.chain(self.partial.iter_mut().map(|p| &mut p.index));
+		for index in indexes {
+			index.shrink_to_fit();
+			// sets shared with snapshots are left as they are, rather than copied
+			for (_, keys) in index.iter_mut() {
+				if let Some(keys) = Arc::get_mut(keys) {
+					keys.shrink_to_fit();
+				}
+			}
+		}
+	}
+
+	/// Puts the posting sets through the new slot numbers, after the records were moved together.
+	fn renumber(&mut self, remap: &[u32]) {
+		let hasher = self.hasher().clone();
+		let indexes = core::iter::once(&mut self.index)
+			.chain(core::iter::once(&mut self.buckets))
+			.chain(self.partial.iter_mut().map(|p| &mut p.index));
+		for index in indexes {
+			for (_, keys) in index.iter_mut() {
+				*keys = Arc::new(keys.renumber(hasher.clone(), |slot| remap[slot as usize]));
+			}
+		}
+	}
+
+	/// Changes how the records of each category are kept, converting the current ones. See [`PostingKind`].
+	pub fn set_postings(&mut self, kind: PostingKind) {
+		self.postings = kind;
+		let hasher = self.hasher().clone();
+		let indexes = core::iter::once(&mut self.index)
+			.chain(core::iter::once(&mut self.buckets))
+			.chain(self.partial.iter_mut().map(|p| &mut p.index));
+		for index in indexes {
+			for (_, keys) in index.iter_mut() {
+				if keys.kind().is_some_and(|k| k != kind) {
+					*keys = Arc::new(keys.convert(kind, hasher.clone(), |slot| slot));
+				}
+			}
+		}
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.data.capacity()
+	}
+
+	pub fn hasher(&self) -> &H {
+		self.data.hasher()
+	}
+
+	/// Removes all records. Partial indexes stay declared.
 	pub fn clear(&mut self) {
 		self.data.clear();
 		self.index.clear();
+		self.buckets.clear();
+		self.expiry.clear();
+		self.unique.clear();
+		for p in self.partial.iter_mut() {
+			p.index.clear();
+		}
+		if let Some(e) = &mut self.eviction {
+			e.clear();
+		}
 	}
 
 	pub fn len(&self) -> usize {
 		self.data.len()
 	}
 
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
 	pub fn contains_key(&self, key: &T::Key) -> bool {
 		self.data.contains_key(key)
 	}
@@ -57,113 +601,505 @@ impl<T: MicroRecord> MicroTable<T> {
 		self.index.contains_key(cat)
 	}
 
-	pub fn insert(&mut self, val: T) -> Result<(), KeyError> {
-		let key = val.key();
-		if self.data.contains_key(&key) {
-			return Err(KeyError::Collision);
-		}
-		for cat in val.categories() {
-			self.index.entry(cat).or_insert_with(|| HashSet::new()).insert(key.clone());
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		self.insert_keyed(val.key(), val)
+	}
+
+	/// [`MicroTable::insert`] of a record whose key is computed already.
+	fn insert_keyed(&mut self, key: T::Key, val: T) -> Result<(), KeyError<T::Key>> {
+		self.insert_slot(key, val)?;
+		self.enforce_budget();
+		Ok(())
+	}
+
+	/// Inserts the records, stopping at the first that fails; the ones before it stay in the table.
+	/// The records storage is sized by the iterator's `size_hint`, and the index by the categories per record
+	/// of the first records, so a bulk load doesn't rehash as it grows.
+	pub fn try_extend(&mut self, vals: impl IntoIterator<Item = T>) -> Result<(), KeyError<T::Key>> {
+		self.extend_pairs(vals.into_iter().map(|val| (val.key(), val)), Self::insert_keyed)
+	}
+
+	/// Bulk load where a record replaces the one with its key. Only for tables without constraints or limits, as it
+	/// doesn't check them.
+	fn extend_last_wins(&mut self, pairs: impl IntoIterator<Item = (T::Key, T)>) {
+		let Ok(()) = self.extend_pairs(pairs, |t, key, val| {
+			t.remove(&key);
+			t.insert_unchecked(key, val);
+			Ok::<_, core::convert::Infallible>(())
+		});
+	}
+
+	/// Bulk load of records whose keys are computed already, sized as in [`MicroTable::try_extend`]. `insert` adds each
+	/// record, and the load stops at the first error it returns.
+	fn extend_pairs<E>(&mut self, pairs: impl IntoIterator<Item = (T::Key, T)>, mut insert: impl FnMut(&mut Self, T::Key, T) -> Result<(), E>) -> Result<(), E> {
+		let pairs = pairs.into_iter();
+		let expected = pairs.size_hint().0;
+		self.data.reserve(expected);
+		let mut presize = IndexPresize::new(self, expected);
+		for (i, (key, val)) in pairs.enumerate() {
+			presize.reserve(self, i);
+			insert(self, key, val)?;
 		}
-		self.data.insert(key, val);
 		Ok(())
 	}
 
+	/// Splits the records into `n` tables by `shard_fn` (taken modulo `n`), e.g. by a hash of a category, so each
+	/// can be worked on by its own thread. The shards have the table's hasher, and neither its constraints, nor
+	/// columns, partial indexes, expiry or budget. Panics if `n` is 0.
+	pub fn split_shards(self, n: usize, shard_fn: impl Fn(&T) -> usize) -> Vec<Self> {
+		assert!(n > 0, "a table splits into a shard at least");
+		let hasher = self.hasher().clone();
+		let mut shards: Vec<Self> = (0..n).map(|_| Self::with_hasher(hasher.clone())).collect();
+		for (key, val) in self.data.into_pairs() {
+			shards[shard_fn(&val) % n].insert_unchecked(key, val);
+		}
+		shards
+	}
+
+	/// Joins tables, e.g. the ones of [`MicroTable::split_shards`], into the first one, which keeps its constraints
+	/// and columns. Fails if a key is in two of them, or a constraint of the first is broken.
+	pub fn merge_shards(shards: impl IntoIterator<Item = Self>) -> Result<Self, KeyError<T::Key>>
+	where H: Default {
+		let mut shards = shards.into_iter();
+		let Some(mut table) = shards.next() else { return Ok(Self::default()) };
+		for shard in shards {
+			table.try_extend(shard.data.into_pairs().map(|(_, val)| val))?;
+		}
+		Ok(table)
+	}
+
+	/// Inserts the record under its key and returns its slot in `data`.
+	fn insert_slot(&mut self, key: T::Key, val: T) -> Result<usize, KeyError<T::Key>> {
+		// the same lookup checks the key and stores the record
+		let Err(vacant) = self.data.entry(key) else { return Err(KeyError::Collision) };
+		if let Some((constraint, existing)) = self.unique.check(&val, &|_| false) {
+			return Err(KeyError::Unique { constraint, existing });
+		}
+		let slot = vacant.insert(val);
+		self.index_slot(slot);
+		Ok(slot)
+	}
+
+	/// Stores and indexes the record, without checking the constraints, and returns its slot. The key must be free.
+	fn insert_unchecked(&mut self, key: T::Key, val: T) -> usize {
+		let slot = self.data.vacant(key).expect("key is taken").insert(val);
+		self.index_slot(slot);
+		slot
+	}
+
+	/// Gives the record in the slot the TTL deadline of the one it replaced. The key is cloned only if there's a deadline.
+	fn carry_expiry(&mut self, slot: usize, expires: Option<Instant>) {
+		if let Some(t) = expires {
+			self.expiry.insert(self.data.at(slot).0.clone(), t);
+		}
+	}
+
+	/// Adds the record in the slot to the indexes.
+	fn index_slot(&mut self, slot: usize) {
+		let (key, val) = self.data.at(slot);
+		let id = slot_id(slot);
+		index_add(&mut self.index, self.postings, id, val.categories());
+		index_add(&mut self.buckets, self.postings, id, val.buckets());
+		for p in self.partial.iter_mut() {
+			p.add(self.postings, id, val);
+		}
+		self.unique.add(key, val);
+		if let Some(e) = &mut self.eviction {
+			e.add(key, record_size(val));
+		}
+	}
+
+	fn check_unique(&self, val: &T, skip: &dyn Fn(&T::Key) -> bool) -> Result<(), KeyError<T::Key>> {
+		match self.unique.check(val, skip) {
+			Some((constraint, existing)) => Err(KeyError::Unique { constraint, existing }),
+			None => Ok(()),
+		}
+	}
+
+	/// Declares that `field` of the records must be unique, e.g. `|b| (b.author, b.title.clone())`.
+	/// Inserts and updates that would repeat a value fail with [`KeyError::Unique`].
+	/// If the current records already repeat a value, the constraint isn't added and the error names one of them.
+	pub fn add_unique<U: Hash + Eq + Clone + Send + Sync + 'static>(&mut self, field: fn(&T) -> U) -> Result<UniqueId, KeyError<T::Key>>
+	where T: 'static, T::Key: Send + Sync {
+		self.unique.push(field, self.data.iter()).map_err(|(constraint, existing)| KeyError::Unique { constraint, existing })
+	}
+
 	/// Finds the object by old key, updates it. The key in the table is not updated.
-	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), KeyError> {
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
 		let new_key = new_val.key();
-		if new_key != key && self.data.contains_key(&new_key) {
-			return Err(KeyError::Collision);
+		if new_key != key {
+			// the new key is checked before the old record goes, so that a failed upsert changes nothing
+			if self.data.contains_key(&new_key) {
+				return Err(KeyError::Collision);
+			}
+			self.check_unique(&new_val, &|k| *k == key)?;
+			let expires = if self.expiry.is_empty() { None } else { map_swap_remove(&mut self.expiry, &key) };
+			self.remove(&key);
+			let slot = self.insert_unchecked(new_key, new_val);
+			self.carry_expiry(slot, expires);
+			self.enforce_budget();
+			return Ok(());
 		}
-		if self.contains_key(&key) {
-			self.update_with(key, &|old_val| *old_val = new_val.clone()).unwrap(); // unwrap because checked in .contains_key
-		} else {
-			self.insert(new_val).unwrap(); // unwrap because checked
+		// the same lookup finds the record, or the place to insert it
+		let slot = match self.data.entry(key) {
+			Ok(slot) => slot,
+			Err(vacant) => {
+				if let Some((constraint, existing)) = self.unique.check(&new_val, &|_| false) {
+					return Err(KeyError::Unique { constraint, existing });
+				}
+				let slot = vacant.insert(new_val);
+				self.index_slot(slot);
+				self.enforce_budget();
+				return Ok(());
+			},
+		};
+		self.check_unique(&new_val, &|k| k == self.data.at(slot).0)?;
+		let old_val = self.replace_in_slot(slot, new_val);
+		let (key, val) = self.data.at(slot);
+		self.unique.replace(key, &old_val, val);
+		self.enforce_budget();
+		Ok(())
+	}
+
+	/// Updates the record in place without cloning it, so it works for records that aren't `Clone`.
+	/// The record is taken out of the table, passed to `cb` and put back. If the new key or a unique value is taken
+	/// by another record, the modified record can't be restored, so it stays out of the table and is returned in the error.
+	pub fn modify(&mut self, key: &T::Key, cb: impl FnOnce(&mut T)) -> Result<(), Rejected<T>> {
+		let expires = map_swap_remove(&mut self.expiry, key);
+		let Some(mut val) = self.remove(key) else { return Err(Rejected { error: KeyError::NotFound, record: None }) };
+		cb(&mut val);
+		let new_key = val.key();
+		if self.data.contains_key(&new_key) {
+			return Err(Rejected { error: KeyError::Collision, record: Some(val) });
+		}
+		if let Err(error) = self.check_unique(&val, &|_| false) {
+			return Err(Rejected { error, record: Some(val) });
 		}
+		let slot = self.insert_unchecked(new_key, val);
+		self.carry_expiry(slot, expires);
+		self.enforce_budget();
 		Ok(())
 	}
 
-	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError>  {
-		let Some(val) = self.data.get(&old_key) else { return Err(KeyError::NotFound); };
-		let mut val = val.clone();
-		let old_cats = vec2hashset(val.categories());
+	/// Runs the callback on a copy of the record, and saves it only if its key and unique values are free,
+	/// leaving the table unchanged otherwise.
+	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), KeyError<T::Key>>
+	where T: Clone {
+		let Some(slot) = self.data.slot_of(&old_key) else { return Err(KeyError::NotFound); };
+		let mut val = self.data.at(slot).1.clone();
 		cb(&mut val);
 		let new_key = val.key();
+		if new_key != old_key && self.data.contains_key(&new_key) {
+			return Err(KeyError::Collision);
+		}
+		self.check_unique(&val, &|k| *k == old_key)?;
 		if new_key != old_key {
-			self.insert(val)?;
+			let expires = map_swap_remove(&mut self.expiry, &old_key);
 			self.remove(&old_key);
+			let slot = self.insert_unchecked(new_key, val);
+			self.carry_expiry(slot, expires);
 		} else {
-			let new_cats = vec2hashset(val.categories());
-
-			for c in old_cats.difference(&new_cats) {
-				self.index.entry(c.clone()).and_modify(|e| { e.remove(&old_key); });
-			}
-			for c in new_cats.difference(&old_cats) {
-				self.index.entry(c.clone()).or_insert_with(|| HashSet::new()).insert(old_key.clone());
-			}
-			self.clear_empty_categories();
+			let old_val = self.replace_in_slot(slot, val);
+			let (key, val) = self.data.at(slot);
+			self.unique.replace(key, &old_val, val);
 		}
+		self.enforce_budget();
 		Ok(())
 	}
 
-	pub fn update_by_cat(&mut self, cat: T::Category, cb: impl Fn(&mut T)) -> Result<usize, KeyError> {
+	/// Replaces the record in the slot with one under the same key, and updates the indexes where its categories changed.
+	/// Uniqueness constraints are left to the caller.
+	fn replace_in_slot(&mut self, slot: usize, val: T) -> T {
+		let old_val = self.data.replace_at(slot, val);
+		let (key, val) = self.data.at(slot);
+		let id = slot_id(slot);
+		index_diff(&mut self.index, self.postings, id, old_val.categories(), val.categories());
+		index_diff(&mut self.buckets, self.postings, id, old_val.buckets(), val.buckets());
+		for p in self.partial.iter_mut() {
+			p.replace(self.postings, id, &old_val, val);
+		}
+		if let Some(e) = &mut self.eviction {
+			e.update(key, record_size(&old_val), record_size(val));
+		}
+		old_val
+	}
+
+	pub fn update_by_cat(&mut self, cat: T::Category, cb: impl Fn(&mut T)) -> Result<usize, KeyError<T::Key>>
+	where T: Clone {
 		// update multiple records found by category
-		let Some(keys) = self.index.get(&cat) else { return Ok(0); };
-		let keys: Vec<T::Key> = keys.into_iter().map(|k| k.clone()).collect(); // ugly but required, because self.index.get borrows self immutably and it's still borrowed, while self.update requires mutable borrow.
-		let update_count = keys.len();
-		// can fail if there's key collision. must run check beforehand
-		// callbacks are run on copies, results are stored, then if all is ok, we can save the data with upsert
-		let mut updates: Vec<(T::Key, T)> = vec![];
-		for old_key in keys.into_iter() {
-			let mut item = self.data.get(&old_key).unwrap().clone();
+		let Some(slots) = self.index.get(&cat) else { return Ok(0); };
+		let old_slots = Arc::clone(slots); // required, because self.index.get borrows self immutably and it's still borrowed, while updates require mutable borrow.
+		// callbacks are run on copies, results are stored, then if all is ok, old records are replaced with them
+		let updates = old_slots.iter().map(|slot| {
+			let (old_key, old_val) = self.data.at(slot as usize);
+			let mut item = old_val.clone();
 			cb(&mut item);
+			(old_key.clone(), item)
+		}).collect();
+		self.apply_cat_updates(&old_slots, updates)
+	}
+
+	/// Replaces the records in `old_slots` with `updates` (their old keys and new values), checking first that
+	/// the new records don't collide with each other or with the rest of the table. Returns the number of updates.
+	fn apply_cat_updates(&mut self, old_slots: &Posting<H>, updates: Vec<(T::Key, T)>) -> Result<usize, KeyError<T::Key>> {
+		let update_count = updates.len();
+		let is_old = |data: &Slab<T::Key, T, H>, k: &T::Key| data.slot_of(k).is_some_and(|s| old_slots.contains(slot_id(s)));
+		// can fail if there's key collision or a constraint violation. must run check beforehand
+		let mut moves: Vec<T::Key> = Vec::with_capacity(update_count); // new keys, in the order of updates
+		let mut new_keys = Set::with_capacity_and_hasher(update_count, self.hasher().clone());
+		for (_, item) in updates.iter() {
 			let new_key = item.key();
-			if new_key != old_key && self.contains_key(&new_key) {
+			// the updated records may swap keys between them, but not take others' or repeat each other's
+			if (self.contains_key(&new_key) && !is_old(&self.data, &new_key)) || !set_insert(&mut new_keys, new_key.clone()) {
 				return Err(KeyError::Collision);
 			}
-			updates.push((old_key, item));
+			moves.push(new_key);
+			self.check_unique(item, &|k| is_old(&self.data, k))?;
+		}
+		if let Some((constraint, existing)) = self.unique.check_batch(&updates) {
+			return Err(KeyError::Unique { constraint, existing });
 		}
-		for (old_key, new_val) in updates.into_iter() {
-			self.upsert(old_key, new_val).unwrap(); // already checked
+		// records that keep their keys are replaced in place, the others are removed first, to free the keys they swap
+		let (in_place, moved): (Vec<_>, Vec<_>) = updates.into_iter().zip(moves).partition(|((old_key, _), new_key)| old_key == new_key);
+		let mut expires = vec![];
+		for ((old_key, _), _) in moved.iter() {
+			expires.push(map_swap_remove(&mut self.expiry, old_key));
+			self.remove(old_key);
 		}
+		// unique values may pass between the records, so all old ones go before any new one is added
+		let slots: Vec<usize> = in_place.iter().map(|((key, _), _)| self.data.slot_of(key).expect("checked above")).collect();
+		for slot in slots.iter() {
+			self.unique.remove(self.data.at(*slot).1);
+		}
+		for (((_, new_val), _), slot) in in_place.into_iter().zip(slots) {
+			self.replace_in_slot(slot, new_val);
+			let (key, val) = self.data.at(slot);
+			self.unique.add(key, val);
+		}
+		for (((_, new_val), new_key), expires) in moved.into_iter().zip(expires) {
+			let slot = self.insert_unchecked(new_key, new_val);
+			self.carry_expiry(slot, expires);
+		}
+		self.enforce_budget();
 		Ok(update_count)
 	}
 
-	fn clear_empty_categories(&mut self) {
-		self.index.retain(|_, keys| keys.len() > 0);
-	}
-
 	pub fn remove(&mut self, key: &T::Key) -> Option<T> {
-		// get categories
-		let value = self.data.remove(key)?;
-		for cat in value.categories() {
-			self.index.entry(cat).and_modify(|c| { c.remove(key); });
-			self.clear_empty_categories();
+		let (slot, value) = self.data.remove(key)?;
+		let id = slot_id(slot);
+		index_remove(&mut self.index, id, value.categories());
+		index_remove(&mut self.buckets, id, value.buckets());
+		for p in self.partial.iter_mut() {
+			p.remove(id, &value);
+		}
+		if self.data.wants_compaction() {
+			let remap = self.data.compact_slots();
+			self.renumber(&remap);
+		}
+		self.unique.remove(&value);
+		if !self.expiry.is_empty() {
+			map_swap_remove(&mut self.expiry, key);
+		}
+		if let Some(e) = &mut self.eviction {
+			e.remove(key, record_size(&value));
 		}
 		Some(value)
 	}
 
+	/// Limits the estimated size of the records to `max_bytes`, so the table works as a bounded cache: inserts and
+	/// updates that go over it evict the least recently used records (inserted, updated or [touched](MicroTable::touch)
+	/// longest ago), and pass them to [`MicroTable::on_evict`], or drop them. The newest record stays even if it alone
+	/// is too big. Indexes aren't counted. `None` lifts the limit.
+	///
+	/// A record's size is `size_of::<T>()` plus [`MicroRecord::heap_size`], the estimate that
+	/// [`MicroTable::memory_usage`] reports too, so records implement one size method rather than one per use.
+	pub fn set_budget(&mut self, max_bytes: Option<usize>) {
+		self.set_limits(|e| e.max_bytes = max_bytes);
+		self.enforce_budget();
+	}
+
+	/// Limits the number of records, evicting the least recently used ones like [`MicroTable::set_budget`]. `Some(0)` evicts
+	/// every record once inserted, and `None` lifts the limit.
+	pub fn set_max_records(&mut self, max_records: Option<usize>) {
+		self.set_limits(|e| e.max_records = max_records);
+		self.enforce_budget();
+	}
+
+	/// Calls `f` with each record the limits evict, least recently used first, e.g. to write it back to a store.
+	/// Without a callback, evicted records are dropped. Clones of the table share the callback.
+	pub fn on_evict(&mut self, f: impl Fn(T) + Send + Sync + 'static) {
+		self.on_evict = Some(Arc::new(f));
+	}
+
+	fn set_limits(&mut self, set: impl FnOnce(&mut Eviction<T::Key, H>)) {
+		let e = self.eviction.get_or_insert_with(|| {
+			let mut e = Eviction::with_hasher(self.data.hasher().clone());
+			for (key, val) in self.data.iter() {
+				e.add(key, record_size(val));
+			}
+			e
+		});
+		set(e);
+		if e.is_unlimited() {
+			self.eviction = None;
+		}
+	}
+
+	/// Marks the record as just used, so a bounded table evicts it last, and returns it.
+	/// Without limits it's the same as [`MicroTable::get`].
+	pub fn touch(&mut self, key: &T::Key) -> Option<&T> {
+		if let Some(e) = &mut self.eviction {
+			e.touch(key);
+		}
+		self.data.get(key)
+	}
+
+	/// Estimated size of the records counted against the budget, `None` if the table has no limits.
+	pub fn budget_used(&self) -> Option<usize> {
+		self.eviction.as_ref().map(|e| e.bytes)
+	}
+
+	/// Evicts records while the table is over its limits, passing them to the [`MicroTable::on_evict`] callback.
+	fn enforce_budget(&mut self) {
+		while let Some(val) = self.evict_one() {
+			if let Some(f) = &self.on_evict {
+				f(val);
+			}
+		}
+	}
+
+	/// Removes the least recently used record if the table is over its limits, and returns it.
+	fn evict_one(&mut self) -> Option<T> {
+		let e = self.eviction.as_ref()?;
+		if !e.over(self.len()) {
+			return None;
+		}
+		let key = e.oldest().expect("over budget without records").clone();
+		self.remove(&key)
+	}
+
+	/// Inserts a record that [`MicroTable::expire`] will remove once `ttl` passes. Updates keep the deadline,
+	/// also when they change the key.
+	#[cfg(feature="std")]
+	pub fn insert_with_ttl(&mut self, val: T, ttl: Duration) -> Result<(), KeyError<T::Key>> {
+		self.insert_with_deadline(val, Some(Instant::now() + ttl))
+	}
+
+	/// [`MicroTable::insert`] with the TTL deadline of a record that moves in from another table.
+	#[cfg(feature="std")]
+	pub(crate) fn insert_with_deadline(&mut self, val: T, expires: Option<Instant>) -> Result<(), KeyError<T::Key>> {
+		let slot = self.insert_slot(val.key(), val)?;
+		self.carry_expiry(slot, expires);
+		self.enforce_budget();
+		Ok(())
+	}
+
+	/// The TTL deadline of the record, to carry over with [`MicroTable::insert_with_deadline`].
+	#[cfg(feature="concurrent")]
+	pub(crate) fn deadline(&self, key: &T::Key) -> Option<Instant> {
+		self.expiry.get(key).copied()
+	}
+
+	/// Removes the records whose TTL has run out by `now`, and returns them.
+	#[cfg(feature="std")]
+	pub fn expire(&mut self, now: Instant) -> Vec<T> {
+		let expired: Vec<T::Key> = self.expiry.iter().filter(|(_, t)| **t <= now).map(|(k, _)| k.clone()).collect();
+		expired.iter().filter_map(|k| self.remove(k)).collect()
+	}
+
+	/// Removes all records of the category, also from their other categories.
 	pub fn remove_cat(&mut self, cat: &T::Category) -> Vec<T> {
-		let Some(keys) = self.index.remove(cat) else { return vec![] };
-		keys.iter().filter_map(|k| self.data.remove(k)).collect()
+		let Some(slots) = self.index.get(cat) else { return vec![] };
+		let keys: Vec<T::Key> = slots.iter().map(|s| self.data.at(s as usize).0.clone()).collect();
+		keys.iter().filter_map(|k| self.remove(k)).collect()
+	}
+
+	/// Finds records by a bucket from [`MicroRecord::buckets`].
+	pub fn find_bucket(&self, bucket: &T::Category) -> Vec<&T> {
+		let Some(hs) = self.buckets.get(bucket) else { return vec![] };
+		hs.iter().map(|s| self.data.at(s as usize).1).collect()
+	}
+
+	/// Adds a column: `field` of every record, kept in an array next to each other (e.g. `|b| b.price`),
+	/// so scans and aggregations over it don't load whole records. It's filled from the current records and kept up to date.
+	pub fn add_column<U: Clone + Default + Send + Sync + 'static>(&mut self, field: fn(&T) -> U) -> ColumnId<U>
+	where T: 'static {
+		self.data.add_column(field)
+	}
+
+	/// Values of the column, by storage slot, in the order of [`MicroTable::values`]. Slots left empty by removals
+	/// hold `U::default()` (0 for numbers, so sums don't need to skip them) until [`MicroTable::shrink_to_fit`].
+	///
+	/// `id` must come from this table's [`MicroTable::add_column`]. An id of another table panics with "column of
+	/// another table", unless this table has a column of the same type at its place, which is then what's read.
+	pub fn column<U: 'static>(&self, id: ColumnId<U>) -> &[U]
+	where T: 'static {
+		self.data.column(id)
+	}
+
+	/// Declares an index by the same categories, but only of the records for which `filter` returns true
+	/// (e.g. only active ones). It's queried separately with [`MicroTable::find_partial`], so that
+	/// [`MicroTable::find`] still returns all records. The index is built from the current records and kept up to date.
+	pub fn add_partial_index(&mut self, filter: fn(&T) -> bool) -> PartialIndexId {
+		let mut p = PartialIndex { filter, index: Map::with_hasher(self.hasher().clone()) };
+		for (slot, _, val) in self.data.iter_slots() {
+			p.add(self.postings, slot_id(slot), val);
+		}
+		self.partial.push(p);
+		PartialIndexId(self.partial.len() - 1)
+	}
+
+	/// Like [`MicroTable::find`], but only among the records covered by the partial index.
+	/// Panics if `id` was issued by another table.
+	pub fn find_partial(&self, id: PartialIndexId, cat: &T::Category) -> Vec<&T> {
+		let Some(hs) = self.partial[id.0].index.get(cat) else { return vec![] };
+		hs.iter().map(|s| self.data.at(s as usize).1).collect()
 	}
 
 	pub fn get(&self, key: &T::Key) -> Option<&T> {
 		self.data.get(key)
 	}
 
+	/// Like [`MicroTable::find`], but without collecting the records, so it doesn't allocate.
+	/// Categories of one record keep it inline, so `find_iter(cat).next()` works as a lookup by a secondary key.
+	pub fn find_iter(&self, cat: &T::Category) -> impl Iterator<Item = &T> {
+		self.index.get(cat).into_iter().flat_map(|slots| slots.iter()).map(|s| self.data.at(s as usize).1)
+	}
+
 	pub fn find(&self, cat: &T::Category) -> Vec<&T> { // TODO: replace with iterator struct?
 		let Some(hs) = self.index.get(cat) else { return vec![] };
-		hs.iter().filter_map(|k| self.data.get(k)).collect()
+		hs.iter().map(|s| self.data.at(s as usize).1).collect()
 	}
 
 	pub fn find_many(&self, cats: &[T::Category]) -> Vec<&T> { // TODO: replace with iterator struct?
-		let keys: HashSet<&T::Key> = cats.iter()
+		let slots: Set<u32, H> = cats.iter()
 			.filter_map(|c| self.index.get(c))
-			.flatten().collect();
+			.fold(Set::with_hasher(self.hasher().clone()), |mut acc, slots| { acc.extend(slots.iter()); acc });
 
 		// Vec<T>s into T-s
-		keys.iter().filter_map(|k| self.data.get(k)).collect()
+		slots.iter().map(|s| self.data.at(*s as usize).1).collect()
+	}
+
+	/// Appends the records of the category to `out`, so a loop can reuse one buffer instead of allocating for every query.
+	pub fn find_into<'a>(&'a self, cat: &T::Category, out: &mut Vec<&'a T>) {
+		out.extend(self.find_iter(cat));
+	}
+
+	/// Appends the records of any of the categories to `out`, each once, without allocating anything but `out`'s growth:
+	/// a record is skipped if an earlier category has it.
+	pub fn find_many_into<'a>(&'a self, cats: &[T::Category], out: &mut Vec<&'a T>) {
+		let postings = || cats.iter().filter_map(|c| self.index.get(c));
+		for (i, slots) in postings().enumerate() {
+			let fresh = slots.iter().filter(|s| !postings().take(i).any(|seen| seen.contains(*s)));
+			out.extend(fresh.map(|s| self.data.at(s as usize).1));
+		}
+	}
+
+	/// Records that are in all of the categories (none if `cats` is empty).
+	/// With [`PostingKind::Sorted`] the categories are intersected in one pass.
+	pub fn find_all(&self, cats: &[T::Category]) -> Vec<&T> {
+		let Some(mut postings) = cats.iter().map(|c| self.index.get(c).map(|slots| &**slots)).collect::<Option<Vec<_>>>() else { return vec![] };
+		posting::intersect(&mut postings).into_iter().map(|s| self.data.at(s as usize).1).collect()
 	}
 
 	pub fn iter(&self) -> impl Iterator<Item = (&T::Key, &T)> {
@@ -174,6 +1110,13 @@ impl<T: MicroRecord> MicroTable<T> {
 		self.data.values()
 	}
 
+	/// Copies of the records, taken now, in an iterator that doesn't borrow the table, to move into another thread
+	/// or task. With records in an `Arc`, as in an [`ArcTable`], the copies are pointers.
+	pub fn iter_snapshot(&self) -> impl ExactSizeIterator<Item = T> + Send + 'static
+	where T: Clone + Send + 'static {
+		self.values().cloned().collect::<Vec<_>>().into_iter()
+	}
+
 	pub fn iter_keys(&self) -> impl Iterator<Item = &T::Key> {
 		self.data.keys()
 	}
@@ -181,38 +1124,197 @@ impl<T: MicroRecord> MicroTable<T> {
 	pub fn iter_cats(&self) -> impl Iterator<Item = &T::Category> {
 		self.index.keys()
 	}
+
+	/// Sizes of the category index, to spot skewed categories. See [`IndexStats`].
+	pub fn index_stats(&self) -> IndexStats {
+		let mut stats = IndexStats { categories: self.index.len(), ..Default::default() };
+		let mut min = usize::MAX;
+		let mut bytes = map_bytes(&self.index);
+		for keys in self.index.values() {
+			min = min.min(keys.len());
+			stats.max_posting = stats.max_posting.max(keys.len());
+			stats.entries += keys.len();
+			bytes += keys.bytes();
+		}
+		if stats.categories > 0 {
+			stats.min_posting = min;
+			stats.mean_posting = stats.entries as f64 / stats.categories as f64;
+		}
+		stats.estimated_bytes = bytes;
+		stats
+	}
+
+	/// Rebuilds the table into a read-only [`FrozenTable`], with the records and posting sets packed into arrays.
+	/// Buckets, partial indexes, columns, TTLs and limits are dropped.
+	pub fn freeze(mut self) -> FrozenTable<T, H> {
+		if let Some(remap) = self.data.shrink_to_fit() {
+			self.renumber(&remap);
+		}
+		FrozenTable::build(self.data, self.index)
+	}
+
+	/// Writes the records and the category index to a file, to be opened read-only with [`MmapTable::open`].
+	#[cfg(feature="mmap")]
+	pub fn freeze_to_file(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()>
+	where T: Serialize, T::Key: Serialize, T::Category: Serialize {
+		mmap::write(self, path.as_ref())
+	}
+
+	/// Hash of the records that doesn't depend on their order, to tell whether two tables (say, replicas in different
+	/// processes) hold the same records without comparing them. It is the same wherever the records hash to the same bytes:
+	/// on machines of the same word size and byte order, for `Hash` impls that don't depend on the process.
+	pub fn digest(&self) -> u64
+	where T: Hash {
+		let sum = self.data.values().fold(0u64, |sum, val| {
+			let mut h = Fnv::default();
+			val.hash(&mut h);
+			sum.wrapping_add(mix(h.0))
+		});
+		mix(sum ^ self.len() as u64)
+	}
+
+	/// Estimate of the memory used by the table. See [`MemoryReport`].
+	pub fn memory_usage(&self) -> MemoryReport {
+		let mut report = MemoryReport {
+			data: self.data.bytes() + map_bytes(&self.expiry),
+			records_heap: self.data.values().map(|v| v.heap_size()).sum(),
+			..Default::default()
+		};
+		let indexes = core::iter::once(&self.index)
+			.chain(core::iter::once(&self.buckets))
+			.chain(self.partial.iter().map(|p| &p.index));
+		for index in indexes {
+			report.index += map_bytes(index);
+			// each posting sits in an `Arc` allocation, with two reference counts
+			let shared = core::mem::size_of::<Posting<H>>() + 2 * core::mem::size_of::<usize>();
+			report.postings += index.values().map(|keys| shared + keys.bytes()).sum::<usize>();
+		}
+		report
+	}
+}
+
+/// Memory estimate returned by [`MicroTable::memory_usage`], in bytes. Hash tables are counted by capacity.
+/// Uniqueness constraints are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+	/// The records storage (records inline, with keys), their columns and TTL deadlines.
+	pub data: usize,
+	/// Category maps of the index, buckets and partial indexes.
+	pub index: usize,
+	/// Posting sets of all categories: the records of each, by internal `u32` id.
+	pub postings: usize,
+	/// Sum of [`MicroRecord::heap_size`] of the records.
+	pub records_heap: usize,
+}
+
+impl MemoryReport {
+	pub fn total(&self) -> usize {
+		self.data + self.index + self.postings + self.records_heap
+	}
+}
+
+// hashbrown keeps roughly one control byte per bucket on top of the slots
+fn map_bytes<K: Hash + Eq + Clone, V: Clone, H: BuildHasher + Clone>(map: &Map<K, V, H>) -> usize {
+	map.capacity() * (core::mem::size_of::<K>() + core::mem::size_of::<V>() + 1)
+}
+
+/// Category index statistics returned by [`MicroTable::index_stats`].
+/// "Posting" is the set of records of one category.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexStats {
+	/// Number of distinct categories.
+	pub categories: usize,
+	pub min_posting: usize,
+	pub max_posting: usize,
+	pub mean_posting: f64,
+	/// Total (category, key) pairs, i.e. sum of posting sizes.
+	pub entries: usize,
+	/// Estimate of the index allocations, by capacity. Heap owned by the categories and keys themselves is not counted.
+	pub estimated_bytes: usize,
+}
+
+
+
+/// Size of a record counted against the budget of [`MicroTable::set_budget`].
+fn record_size<T: MicroRecord>(val: &T) -> usize {
+	core::mem::size_of::<T>() + val.heap_size()
+}
+
+/// Collects the records into a table sized by the iterator, see [`MicroTable::try_extend`]. A record replaces an earlier
+/// one with the same key, as in a `HashMap`; [`MicroTable::try_extend`] fails on it instead.
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> FromIterator<T> for MicroTable<T, H> {
+	fn from_iter<I: IntoIterator<Item = T>>(vals: I) -> Self {
+		let mut t = Self::default();
+		t.extend_last_wins(vals.into_iter().map(|val| (val.key(), val)));
+		t
+	}
 }
 
+/// Error of converting a map of records into a table: `key` maps to a record whose key is `record_key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMismatch<K> {
+	pub key: K,
+	pub record_key: K,
+}
+
+#[cfg(feature="std")]
+impl<K: core::fmt::Debug> std::error::Error for KeyMismatch<K> {}
+
+impl<K: core::fmt::Debug> core::fmt::Display for KeyMismatch<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "map key {:?} holds the record of key {:?}", self.key, self.record_key)
+	}
+}
 
+/// Takes the records of a map that's already keyed, checking that every map key is its record's key.
+/// The keys are known to be distinct, so the records go straight into the storage and the index.
+#[cfg(feature="std")]
+impl<T: MicroRecord, H: BuildHasher + Clone + Default, S> TryFrom<std::collections::HashMap<T::Key, T, S>> for MicroTable<T, H> {
+	type Error = KeyMismatch<T::Key>;
+
+	fn try_from(map: std::collections::HashMap<T::Key, T, S>) -> Result<Self, Self::Error> {
+		let mut t = Self::with_hasher(H::default());
+		t.extend_pairs(map, |t, key, val| {
+			let record_key = val.key();
+			if record_key != key {
+				return Err(KeyMismatch { key, record_key });
+			}
+			t.insert_unchecked(key, val);
+			Ok(())
+		})?;
+		Ok(t)
+	}
+}
 
-fn vec2hashset<T: Hash + Eq>(data: Vec<T>) -> HashSet<T> {
+fn vec2hashset<T: Hash + Eq>(data: impl IntoIterator<Item = T>) -> HashSet<T> {
 	data.into_iter().collect()
 }
 
+
 #[cfg(feature="serde")]
-impl<T: MicroRecord + Serialize> Serialize for MicroTable<T> {
+/// Writes the records as a sequence, straight from the storage.
+impl<T: MicroRecord + Serialize, H: BuildHasher + Clone> Serialize for MicroTable<T, H> {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where S: serde::Serializer {
-		let data: Vec<T> = self.data.clone().into_values().collect();
-		data.serialize(serializer)
+    where S: ::serde::Serializer {
+		serializer.collect_seq(self.data.values())
     }
 }
 
+/// Reads a sequence of records, failing on a repeated key. Records may borrow from the input, e.g. with `&'de str`
+/// fields, keys and categories, so a table loaded from a buffer doesn't copy its strings while the buffer lives.
 #[cfg(feature="serde")]
-impl<'de, T: MicroRecord + Deserialize<'de>> Deserialize<'de> for MicroTable<T> {
+impl<'de, T: MicroRecord + Deserialize<'de>, H: BuildHasher + Clone + Default> Deserialize<'de> for MicroTable<T, H> {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where D: serde::Deserializer<'de> {
-		let mut t: MicroTable<T> = MicroTable::new();
-		for item in Vec::deserialize(deserializer)?.into_iter() {
-			t.insert(item).unwrap();
-		}
-		Ok(t)
+    where D: ::serde::Deserializer<'de> {
+		::serde::de::DeserializeSeed::deserialize(TableSeed::new(OnDuplicate::Error), deserializer)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::clone_on_copy, clippy::map_clone, clippy::map_flatten)] // the tests predate the lints
 pub mod multimap_tests {
 	use super::*;
+	use std::collections::HashSet;
 
 	#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 	struct ScienceId(usize);
@@ -239,7 +1341,7 @@ pub mod multimap_tests {
 	impl MicroRecord for Book {
 		type Key = BookId;
 		type Category = BookCategory;
-		fn categories(&self) -> Vec<Self::Category> {
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
 			vec![BookCategory::Science(self.science.clone()), BookCategory::Author(self.author.clone())]
 		}
 		fn key(&self) -> Self::Key {
@@ -307,7 +1409,7 @@ pub mod multimap_tests {
 		assert!(!it.contains_cat(&BookCategory::Science(s5)));
 
 		let expected_values = vec2hashset(books.clone());
-		let real_values = it.values().map(|b| b.clone()).collect();
+		let real_values: HashSet<_> = it.values().map(|b| b.clone()).collect();
 		assert_eq!(expected_values, real_values);
 	}
 
@@ -346,15 +1448,18 @@ pub mod multimap_tests {
 		let b2 = books[1].clone();
 		// find books by book 2 author (a1)
 		let prev_author_books = it.find(&BookCategory::Author(b2.author)).len();
+		let order: Vec<BookId> = it.iter_keys().copied().collect();
 
 		assert!(it.upsert(BookId(2), Book { id: BookId(2), title: "Book №5".into(), science: s3, author: a0 }).is_ok());
+		// the record is replaced where it is
+		assert_eq!(it.iter_keys().copied().collect::<Vec<_>>(), order);
 		assert!(it.contains_key(&BookId(3)));
 		assert!(!it.contains_key(&BookId(365)));
-		assert!(!it.index.get(&BookCategory::Author(a2)).unwrap().contains(&BookId(365)));
+		assert!(!it.find(&BookCategory::Author(a2)).iter().any(|b| b.id == BookId(365)));
 		assert!(it.upsert(BookId(3), Book { id: BookId(365), title: "Book №365".into(), science: s3, author: a2 }).is_ok());
 		assert!(!it.contains_key(&BookId(3)));
 		assert!(it.contains_key(&BookId(365)));
-		assert!(it.index.get(&BookCategory::Author(a2)).unwrap().contains(&BookId(365)));
+		assert!(it.find(&BookCategory::Author(a2)).iter().any(|b| b.id == BookId(365)));
 		// upserting with key collision must fail
 		assert!(it.upsert(BookId(2), Book { id: BookId(365), title: "Book №365".into(), science: s3, author: a2 }).is_err());
 		// less 1 book by author (a1)
@@ -416,6 +1521,32 @@ pub mod multimap_tests {
 		assert_eq!(real, expected);
 	}
 
+	#[test]
+	fn split_and_merge_shards() {
+		let mut it = table_fixture();
+		it.add_unique(|b| b.title.clone()).unwrap();
+		let shards = it.split_shards(2, |b| b.science.0);
+		assert_eq!(shards.iter().map(MicroTable::len).collect::<Vec<_>>(), [4, 3]);
+		assert_eq!(shards[0].find(&BookCategory::Science(ScienceId(22))).len(), 3);
+		assert!(shards[1].find(&BookCategory::Science(ScienceId(22))).is_empty());
+
+		let shards: Vec<_> = std::thread::scope(|s| {
+			let workers: Vec<_> = shards.into_iter().map(|mut shard| s.spawn(move || {
+				shard.update_by_cat(BookCategory::Author(AuthorId(10)), |b| b.title += " (2nd ed.)").unwrap();
+				shard
+			})).collect();
+			workers.into_iter().map(|w| w.join().unwrap()).collect()
+		});
+		let merged = MicroTable::merge_shards(shards).unwrap();
+		assert_eq!(merged.len(), 7);
+		assert_eq!(merged.get(&BookId(4)).map(|b| b.title.as_str()), Some("Book №4 (2nd ed.)"));
+		assert_eq!(merged.find(&BookCategory::Author(AuthorId(10))).len(), 2);
+
+		let twice = MicroTable::merge_shards([table_fixture(), table_fixture()]);
+		assert!(matches!(twice, Err(KeyError::Collision)));
+		assert!(MicroTable::<Book>::merge_shards([]).unwrap().is_empty());
+	}
+
 	#[test]
 	fn find_many() {
 		let it = table_fixture();
@@ -423,4 +1554,766 @@ pub mod multimap_tests {
 		let expected: HashSet<usize> = HashSet::from([1, 2, 3, 4]);
 		assert_eq!(real, expected);
 	}
+
+	#[test]
+	fn renumbered_slots() {
+		let mut it: MicroTable<Tagged> = MicroTable::new();
+		for id in 0..100 {
+			it.insert(Tagged { id, tags: vec![if id % 2 == 0 { "even" } else { "odd" }, "all"] }).unwrap();
+		}
+		let p = it.add_partial_index(|t| t.id % 10 == 0);
+		for id in 0..80 {
+			it.remove(&id);
+		}
+		it.shrink_to_fit(); // records move together, so the indexes get new slot numbers
+		let ids = |found: Vec<&Tagged>| found.iter().map(|t| t.id).collect::<HashSet<_>>();
+		assert_eq!(ids(it.find(&"even")), (80..100).step_by(2).collect::<HashSet<_>>());
+		assert_eq!(ids(it.find_all(&["odd", "all"])), (81..100).step_by(2).collect::<HashSet<_>>());
+		assert_eq!(ids(it.find_partial(p, &"all")), HashSet::from([80, 90]));
+		it.insert(Tagged { id: 7, tags: vec!["odd"] }).unwrap();
+		assert_eq!(it.find(&"odd").len(), 11);
+	}
+
+	#[test]
+	fn sorted_postings() {
+		let mut it = table_fixture();
+		let ids = |found: Vec<&Book>| found.iter().map(|b| b.id.0).collect::<HashSet<_>>();
+		let (s2, a1) = (BookCategory::Science(ScienceId(22)), BookCategory::Author(AuthorId(11)));
+		assert_eq!(ids(it.find_all(&[s2.clone(), a1.clone()])), HashSet::from([2]));
+		it.set_postings(PostingKind::Sorted);
+		assert_eq!(ids(it.find_all(&[s2.clone(), a1.clone()])), HashSet::from([2]));
+		assert_eq!(ids(it.find(&s2)), HashSet::from([1, 2, 3]));
+		it.update_by_cat(BookCategory::Science(ScienceId(23)), |b| b.science = ScienceId(22)).unwrap();
+		assert_eq!(ids(it.find_all(&[s2.clone(), a1.clone()])), HashSet::from([2, 5]));
+		it.remove(&BookId(2));
+		assert_eq!(ids(it.find_all(&[s2.clone(), a1])), HashSet::from([5]));
+		assert!(it.find_all(&[s2, BookCategory::Author(AuthorId(99))]).is_empty());
+	}
+
+	#[test]
+	fn singleton_postings() {
+		let mut it: MicroTable<Tagged> = MicroTable::new();
+		it.insert(Tagged { id: 1, tags: vec!["one", "shared"] }).unwrap();
+		it.insert(Tagged { id: 2, tags: vec!["two", "shared"] }).unwrap();
+		assert!(matches!(*it.index[&"one"], Posting::One(_)));
+		assert_eq!(it.find_iter(&"two").map(|t| t.id).collect::<Vec<_>>(), vec![2]);
+		assert_eq!(it.find_iter(&"shared").count(), 2);
+		assert_eq!(it.find_iter(&"none").count(), 0);
+		it.update_with(2, &|t| t.tags = vec!["one"]).unwrap();
+		assert_eq!(it.find_iter(&"one").count(), 2);
+		assert_eq!(it.index[&"shared"].len(), 1);
+		assert!(!it.contains_cat(&"two"));
+		it.remove(&1);
+		assert_eq!(it.find_iter(&"one").next().map(|t| t.id), Some(2));
+		assert!(!it.contains_cat(&"shared"));
+	}
+
+	std::thread_local! {
+		static KEY_HASHES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+	}
+
+	/// Key that counts how many times it's hashed.
+	#[derive(Debug, Clone, PartialEq, Eq)]
+	struct CountedKey(u32);
+
+	impl Hash for CountedKey {
+		fn hash<S: core::hash::Hasher>(&self, state: &mut S) {
+			KEY_HASHES.with(|n| n.set(n.get() + 1));
+			self.0.hash(state);
+		}
+	}
+
+	#[derive(Debug, Clone)]
+	struct Wide(u32, u32);
+
+	impl MicroRecord for Wide {
+		type Key = CountedKey;
+		type Category = u32;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			0..self.1
+		}
+		fn key(&self) -> Self::Key {
+			CountedKey(self.0)
+		}
+	}
+
+	#[test]
+	fn keys_hashed_once() {
+		let hashes = |f: &mut dyn FnMut()| {
+			KEY_HASHES.with(|n| n.set(0));
+			f();
+			KEY_HASHES.with(|n| n.get())
+		};
+		let mut it: MicroTable<Wide> = (0..10).map(|id| Wide(id, 50)).collect();
+		// postings hold slot ids, so the number of categories doesn't matter
+		assert_eq!(hashes(&mut || it.insert(Wide(10, 50)).unwrap()), 1);
+		assert_eq!(hashes(&mut || it.update_with(CountedKey(10), &|w| w.1 = 20).unwrap()), 1);
+		assert_eq!((it.find(&5).len(), it.find(&30).len()), (11, 10));
+		assert_eq!(hashes(&mut || it.upsert(CountedKey(10), Wide(10, 40)).unwrap()), 1);
+		assert_eq!(hashes(&mut || it.upsert(CountedKey(11), Wide(11, 40)).unwrap()), 1);
+		// a key change hashes the new key to check it and to store the record, and the old one to remove it
+		assert_eq!(hashes(&mut || it.upsert(CountedKey(11), Wide(12, 40)).unwrap()), 3);
+		assert_eq!((it.find(&45).len(), it.iter_keys().filter(|k| k.0 > 10).count()), (10, 1));
+		assert_eq!(hashes(&mut || { it.remove(&CountedKey(10)); }), 1);
+	}
+
+	#[test]
+	fn find_into_buffer() {
+		let it = table_fixture();
+		let (s2, s3, a1) = (BookCategory::Science(ScienceId(22)), BookCategory::Science(ScienceId(23)), BookCategory::Author(AuthorId(11)));
+		let mut out = Vec::with_capacity(10);
+		it.find_into(&s2, &mut out);
+		assert_eq!(vec2hashset(out.iter().copied()), vec2hashset(it.find(&s2)));
+		out.clear();
+		it.find_many_into(&[s2.clone(), a1.clone(), s3.clone(), BookCategory::Author(AuthorId(99))], &mut out);
+		assert_eq!(out.len(), it.find_many(&[s2, a1, s3]).len());
+		assert_eq!(vec2hashset(out.iter().map(|b| b.id)).len(), out.len());
+		assert!(out.capacity() == 10);
+	}
+
+	#[test]
+	fn frozen() {
+		let mut it = table_fixture();
+		it.remove(&BookId(1));
+		let (s2, a1) = (BookCategory::Science(ScienceId(22)), BookCategory::Author(AuthorId(11)));
+		let (found, all) = (vec2hashset(it.find(&s2).into_iter().cloned()), vec2hashset(it.find_all(&[s2.clone(), a1.clone()]).into_iter().cloned()));
+		let len = it.len();
+		let frozen = it.freeze();
+		assert_eq!(frozen.len(), len);
+		assert_eq!(vec2hashset(frozen.find(&s2).into_iter().cloned()), found);
+		assert_eq!(vec2hashset(frozen.find_all(&[s2, a1]).into_iter().cloned()), all);
+		assert_eq!(frozen.get(&BookId(2)).map(|b| b.id), Some(BookId(2)));
+		assert!(frozen.get(&BookId(1)).is_none() && !frozen.contains_cat(&BookCategory::Author(AuthorId(99))));
+		assert_eq!(frozen.iter().count(), len);
+	}
+
+	#[test]
+	#[cfg(feature="serde")]
+	fn serialize_without_clone() {
+		use crate::fixtures::Note;
+		let notes: MicroTable<Note> = (0..5).map(|id| Note { id, tag: format!("t{}", id % 2) }).collect();
+		let json = serde_json::to_string(&notes).unwrap();
+		let back: MicroTable<Note> = serde_json::from_str(&json).unwrap();
+		assert_eq!((back.len(), back.find(&"t0".into()).len()), (5, 3));
+	}
+
+	#[cfg(feature="serde")]
+	#[derive(Deserialize)]
+	struct Word<'a> {
+		text: &'a str,
+		lang: &'a str,
+	}
+
+	#[cfg(feature="serde")]
+	impl<'a> MicroRecord for Word<'a> {
+		type Key = &'a str;
+		type Category = &'a str;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.lang]
+		}
+		fn key(&self) -> Self::Key {
+			self.text
+		}
+	}
+
+	#[test]
+	#[cfg(feature="serde")]
+	fn borrowed_records() {
+		let input = String::from(r#"[{"text": "chat", "lang": "fr"}, {"text": "cat", "lang": "en"}, {"text": "chien", "lang": "fr"}]"#);
+		let words: MicroTable<Word> = serde_json::from_str(&input).unwrap();
+		assert_eq!(words.find(&"fr").len(), 2);
+		let cat = words.get(&"cat").unwrap();
+		assert!(input.as_bytes().as_ptr_range().contains(&cat.text.as_ptr()), "the text points into the input");
+	}
+
+	#[test]
+	#[cfg(feature="std")]
+	fn from_keyed_map() {
+		let map: std::collections::HashMap<usize, Tagged> = (0..100).map(|id| (id, Tagged { id, tags: vec![if id % 4 == 0 { "fourth" } else { "other" }] })).collect();
+		let it = MicroTable::<Tagged>::try_from(map.clone()).unwrap();
+		assert_eq!((it.len(), it.find(&"fourth").len()), (100, 25));
+		let mut wrong = map;
+		wrong.insert(500, Tagged { id: 5, tags: vec![] });
+		assert_eq!(MicroTable::<Tagged>::try_from(wrong).map(|_| ()), Err(KeyMismatch { key: 500, record_key: 5 }));
+	}
+
+	#[test]
+	fn digest_ignores_order() {
+		let tagged = |id| Tagged { id, tags: vec![if id % 3 == 0 { "third" } else { "other" }] };
+		let mut a: MicroTable<Tagged> = (0..50).map(tagged).collect();
+		let b: MicroTable<Tagged> = (0..50).rev().map(tagged).collect();
+		assert_eq!(a.digest(), b.digest());
+		a.update_with(7, &|t| t.tags.push("new")).unwrap();
+		assert_ne!(a.digest(), b.digest());
+		a.update_with(7, &|t| { t.tags.pop(); }).unwrap();
+		assert_eq!(a.digest(), b.digest());
+		assert_ne!(MicroTable::<Tagged>::default().digest(), b.digest());
+	}
+
+	#[test]
+	fn bulk_load() {
+		let tagged = |id| Tagged { id, tags: vec![if id % 2 == 0 { "even" } else { "odd" }, "all"] };
+		let mut it: MicroTable<Tagged> = (0..100).map(|id| Tagged { id, tags: vec!["all"] }).collect();
+		assert!(it.capacity() >= 100);
+		assert_eq!(it.find(&"all").len(), 100);
+		assert_eq!(it.try_extend((100..300).map(tagged)), Ok(()));
+		assert_eq!((it.len(), it.find(&"even").len()), (300, 100));
+		assert_eq!(it.try_extend([tagged(300), tagged(5), tagged(301)]), Err(KeyError::Collision));
+		assert!(it.contains_key(&300) && !it.contains_key(&301));
+		let it: MicroTable<Tagged> = [tagged(1), tagged(2), Tagged { id: 1, tags: vec!["last"] }].into_iter().collect();
+		assert_eq!((it.len(), it.find(&"odd").len(), it.find(&"last").len()), (2, 0, 1));
+	}
+
+	#[derive(Debug, Clone, Hash)]
+	struct Tagged {
+		id: usize,
+		tags: Vec<&'static str>,
+	}
+
+	impl MicroRecord for Tagged {
+		type Key = usize;
+		type Category = &'static str;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			self.tags.iter().copied()
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+		fn heap_size(&self) -> usize {
+			self.tags.capacity() * std::mem::size_of::<&str>()
+		}
+	}
+
+	#[test]
+	fn borrowed_categories() {
+		let mut it: MicroTable<Tagged> = MicroTable::new();
+		it.insert(Tagged { id: 1, tags: vec!["a", "b"] }).unwrap();
+		it.insert(Tagged { id: 2, tags: vec![] }).unwrap();
+		assert_eq!(it.find(&"a").len(), 1);
+		assert!(it.contains_key(&2));
+		it.update_with(1, &|t| t.tags = vec!["c"]).unwrap();
+		assert!(!it.contains_cat(&"a"));
+		assert!(it.contains_cat(&"c"));
+	}
+
+	#[test]
+	fn index_stats() {
+		let it = table_fixture();
+		let stats = it.index_stats();
+		// 3 sciences and 4 authors
+		assert_eq!(stats.categories, 7);
+		assert_eq!(stats.entries, 14);
+		assert_eq!(stats.min_posting, 1);
+		assert_eq!(stats.max_posting, 3);
+		assert!((stats.mean_posting - 2.0).abs() < f64::EPSILON);
+		assert!(stats.estimated_bytes > 0);
+
+		assert_eq!(MicroTable::<Book>::new().index_stats(), IndexStats::default());
+	}
+
+	#[test]
+	fn shrink_after_churn() {
+		let mut it = table_fixture();
+		for i in 100..1000 {
+			it.insert(Book { id: BookId(i), title: "".into(), science: ScienceId(22), author: AuthorId(10) }).unwrap();
+		}
+		for i in 100..1000 {
+			it.remove(&BookId(i));
+		}
+		let before = it.index_stats().estimated_bytes;
+		it.shrink_to_fit();
+		#[cfg(not(feature="imbl"))]
+		assert!(it.index_stats().estimated_bytes < before);
+		#[cfg(feature="imbl")]
+		let _ = before;
+		assert_eq!(it.len(), 7);
+		assert_eq!(it.find(&BookCategory::Science(ScienceId(22))).len(), 3);
+	}
+
+	#[test]
+	fn partial_index() {
+		let mut it = table_fixture();
+		let s2 = it.add_partial_index(|b| b.science == ScienceId(22));
+		let a0 = BookCategory::Author(AuthorId(10));
+		assert_eq!(it.find(&a0).len(), 2);
+		assert_eq!(it.find_partial(s2, &a0), vec![&books_fixture()[0]]);
+
+		// book 4 moves into science 22 and gets indexed, book 1 leaves it
+		it.update_with(BookId(4), &|b| b.science = ScienceId(22)).unwrap();
+		it.update_with(BookId(1), &|b| b.science = ScienceId(23)).unwrap();
+		let found: Vec<_> = it.find_partial(s2, &a0).iter().map(|b| b.id).collect();
+		assert_eq!(found, vec![BookId(4)]);
+		// book 2 stays in and changes author, book 3 keeps its categories and leaves the postings as they are
+		let before = it.clone();
+		it.update_with(BookId(2), &|b| b.author = AuthorId(12)).unwrap();
+		it.update_with(BookId(3), &|b| b.title = "Renamed".into()).unwrap();
+		assert_eq!(it.find_partial(s2, &BookCategory::Author(AuthorId(12))).len(), 2);
+		assert!(it.find_partial(s2, &BookCategory::Author(AuthorId(11))).is_empty());
+		let science = BookCategory::Science(ScienceId(22));
+		assert!(Arc::ptr_eq(&it.partial[0].index[&science], &before.partial[0].index[&science]));
+
+		it.remove(&BookId(4));
+		assert!(it.find_partial(s2, &a0).is_empty());
+		assert_eq!(it.find_partial(s2, &BookCategory::Science(ScienceId(22))).len(), 2);
+	}
+
+	#[test]
+	fn remove_cat() {
+		let mut it = table_fixture();
+		assert_eq!(it.remove_cat(&BookCategory::Science(ScienceId(24))).len(), 1);
+		// book 7 was the only one by author 13
+		assert!(!it.contains_cat(&BookCategory::Author(AuthorId(13))));
+		assert_eq!(it.len(), 6);
+	}
+
+	#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+	enum EventCategory {
+		User(usize),
+		Day(u64),
+	}
+
+	#[derive(Debug, Clone)]
+	struct Event {
+		id: usize,
+		user: usize,
+		timestamp: u64,
+	}
+
+	impl MicroRecord for Event {
+		type Key = usize;
+		type Category = EventCategory;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[EventCategory::User(self.user)]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+		fn buckets(&self) -> impl IntoIterator<Item = Self::Category> {
+			[EventCategory::Day(self.timestamp / 86400)]
+		}
+	}
+
+	#[test]
+	fn buckets() {
+		let mut it: MicroTable<Event> = MicroTable::new();
+		it.insert(Event { id: 1, user: 1, timestamp: 100 }).unwrap();
+		it.insert(Event { id: 2, user: 1, timestamp: 86500 }).unwrap();
+		it.insert(Event { id: 3, user: 2, timestamp: 200 }).unwrap();
+		assert_eq!(it.find_bucket(&EventCategory::Day(0)).len(), 2);
+		assert!(it.find(&EventCategory::Day(0)).is_empty());
+		assert!(it.find_bucket(&EventCategory::User(1)).is_empty());
+
+		it.update_with(3, &|e| e.timestamp += 86400).unwrap();
+		assert_eq!(it.find_bucket(&EventCategory::Day(1)).len(), 2);
+		it.remove(&1);
+		assert!(it.find_bucket(&EventCategory::Day(0)).is_empty());
+	}
+
+	#[test]
+	fn ttl() {
+		let mut it = table_fixture();
+		let books = books_fixture();
+		it.remove(&BookId(1));
+		it.remove(&BookId(2));
+		it.insert_with_ttl(books[0].clone(), Duration::ZERO).unwrap();
+		it.insert_with_ttl(books[1].clone(), Duration::from_secs(3600)).unwrap();
+		assert!(it.insert_with_ttl(books[2].clone(), Duration::ZERO).is_err());
+
+		// the deadline follows the key change
+		it.update_with(BookId(1), &|b| b.id = BookId(100)).unwrap();
+		assert_eq!(it.expire(Instant::now()).len(), 1);
+		assert!(!it.contains_key(&BookId(100)));
+		assert!(it.contains_key(&BookId(2)));
+		assert_eq!(it.find(&BookCategory::Author(AuthorId(10))).len(), 1);
+
+		assert_eq!(it.expire(Instant::now() + Duration::from_secs(3601)).len(), 1);
+		assert_eq!(it.len(), 5);
+	}
+
+	#[test]
+	fn unique() {
+		let mut it = table_fixture();
+		let (s2, s3, a0) = (ScienceId(22), ScienceId(23), AuthorId(10));
+		assert!(matches!(it.add_unique(|b| b.author), Err(KeyError::Unique { .. })));
+		let pair = it.add_unique(|b| (b.science, b.author)).unwrap();
+
+		let dup = Book { id: BookId(8), title: "".into(), science: s2, author: a0 };
+		assert_eq!(it.insert(dup.clone()), Err(KeyError::Unique { constraint: pair, existing: BookId(1) }));
+		assert_eq!(it.update_with(BookId(1), &|b| b.science = s3), Err(KeyError::Unique { constraint: pair, existing: BookId(4) }));
+		assert!(it.update_with(BookId(1), &|b| b.title = "Renamed".into()).is_ok());
+		// a new value under the same key frees the old one
+		assert!(it.upsert(BookId(5), Book { science: ScienceId(24), ..books_fixture()[4].clone() }).is_ok());
+		assert!(it.insert(Book { id: BookId(9), title: "".into(), science: s3, author: AuthorId(11) }).is_ok());
+		assert!(it.update_with(BookId(9), &|b| b.science = ScienceId(24)).is_err());
+		assert!(it.upsert(BookId(1), Book { id: BookId(100), ..books_fixture()[0].clone() }).is_ok());
+		assert!(it.insert(dup.clone()).is_err());
+
+		// records of the category swap authors between them
+		assert_eq!(it.update_by_cat(BookCategory::Science(s2), |b| b.author = AuthorId(22 - b.author.0)), Ok(3));
+		assert_eq!(it.get(&BookId(100)).unwrap().author, AuthorId(12));
+		// but can't get the same author
+		assert!(it.update_by_cat(BookCategory::Science(s2), |b| b.author = a0).is_err());
+		assert_eq!(it.find(&BookCategory::Author(a0)).len(), 2);
+
+		it.remove(&BookId(3));
+		assert!(it.insert(dup).is_ok());
+	}
+
+	#[derive(Debug)]
+	struct Connection {
+		id: usize,
+		peer: String,
+		socket: std::sync::mpsc::Sender<Vec<u8>>,
+	}
+
+	impl MicroRecord for Connection {
+		type Key = usize;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.peer.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn modify_without_clone() {
+		let (tx, _rx) = std::sync::mpsc::channel();
+		let mut it: MicroTable<Connection> = MicroTable::new();
+		it.insert(Connection { id: 1, peer: "a".into(), socket: tx.clone() }).unwrap();
+		it.insert(Connection { id: 2, peer: "a".into(), socket: tx.clone() }).unwrap();
+		it.modify(&1, |c| c.peer = "b".into()).unwrap();
+		assert_eq!(it.find(&"b".into()).len(), 1);
+		assert!(it.get(&1).unwrap().socket.send(vec![]).is_ok());
+
+		let rejected = it.modify(&1, |c| c.id = 2).unwrap_err();
+		assert!(matches!(rejected.error, KeyError::Collision));
+		assert_eq!(rejected.record.unwrap().id, 2);
+		assert!(!it.contains_key(&1));
+		assert!(it.modify(&1, |_| ()).unwrap_err().record.is_none());
+
+		it.upsert(2, Connection { id: 3, peer: "c".into(), socket: tx }).unwrap();
+		assert!(it.find(&"a".into()).is_empty());
+		assert_eq!(it.len(), 1);
+	}
+
+	#[test]
+	fn arc_table() {
+		let mut it: ArcTable<Book> = MicroTable::new();
+		for b in books_fixture() {
+			it.insert(Arc::new(b)).unwrap();
+		}
+		let found: Vec<Arc<Book>> = it.find(&BookCategory::Science(ScienceId(22))).into_iter().cloned().collect();
+		let handle = std::thread::spawn(move || found.len());
+		assert_eq!(handle.join().unwrap(), 3);
+
+		let old = it.get(&BookId(1)).unwrap().clone();
+		it.update_with(BookId(1), &|b| Arc::make_mut(b).science = ScienceId(23)).unwrap();
+		assert_eq!(old.science, ScienceId(22));
+		assert_eq!(it.find(&BookCategory::Science(ScienceId(23))).len(), 4);
+	}
+
+	#[test]
+	fn iter_snapshot() {
+		let mut it: MicroTable<Book> = books_fixture().into_iter().collect();
+		let snapshot = it.iter_snapshot();
+		it.clear();
+		let handle = std::thread::spawn(move || snapshot.filter(|b| b.science == ScienceId(22)).count());
+		assert_eq!(handle.join().unwrap(), 3);
+		assert_eq!(it.iter_snapshot().len(), 0);
+	}
+
+	#[test]
+	fn custom_hasher() {
+		type Fixed = std::hash::BuildHasherDefault<std::hash::DefaultHasher>;
+		let mut it: MicroTable<Book, Fixed> = MicroTable::default();
+		for b in books_fixture() {
+			it.insert(b).unwrap();
+		}
+		it.update_by_cat(BookCategory::Science(ScienceId(23)), |b| b.science = ScienceId(22)).unwrap();
+		assert_eq!(it.find(&BookCategory::Science(ScienceId(22))).len(), 6);
+		assert_eq!(it.find_many(&[BookCategory::Science(ScienceId(24)), BookCategory::Author(AuthorId(10))]).len(), 3);
+	}
+
+	#[cfg(feature="ahash")]
+	#[test]
+	fn ahash_table() {
+		let mut it: AHashTable<Book> = AHashTable::default();
+		for b in books_fixture() {
+			it.insert(b).unwrap();
+		}
+		assert_eq!(it.find(&BookCategory::Author(AuthorId(10))).len(), 2);
+	}
+
+	#[cfg(feature="fxhash")]
+	#[test]
+	fn fx_table() {
+		let mut it: FxTable<Book> = FxTable::default();
+		for b in books_fixture() {
+			it.insert(b).unwrap();
+		}
+		it.remove(&BookId(1));
+		assert_eq!(it.find(&BookCategory::Author(AuthorId(10))).len(), 1);
+	}
+
+	#[test]
+	#[cfg_attr(feature="imbl", ignore = "persistent maps don't preallocate")]
+	fn capacity() {
+		let mut it: MicroTable<Book> = MicroTable::with_capacity(100, 10);
+		assert!(it.capacity() >= 100);
+		for b in books_fixture() {
+			it.insert(b).unwrap();
+		}
+		it.reserve(1000);
+		assert!(it.capacity() >= 1007);
+		it.shrink_to_fit();
+		assert!(it.capacity() < 100);
+		assert_eq!(it.len(), 7);
+	}
+
+	#[test]
+	fn memory_usage() {
+		let mut it: MicroTable<Tagged> = MicroTable::new();
+		assert_eq!(it.memory_usage().total(), 0);
+		it.insert(Tagged { id: 1, tags: vec!["a", "b"] }).unwrap();
+		let report = it.memory_usage();
+		assert!(report.data >= std::mem::size_of::<Tagged>() + std::mem::size_of::<usize>());
+		assert!(report.index > 0 && report.postings > 0);
+		assert_eq!(report.records_heap, 32);
+		assert_eq!(report.total(), report.data + report.index + report.postings + 32);
+	}
+
+	#[test]
+	fn budget() {
+		let tagged = |id| Tagged { id, tags: vec!["a"] }; // 32 bytes inline + 16 on the heap
+		let mut it: MicroTable<Tagged> = MicroTable::new();
+		let evicted = Arc::new(std::sync::Mutex::new(vec![]));
+		let sink = Arc::clone(&evicted);
+		it.on_evict(move |t: Tagged| sink.lock().unwrap().push(t.id));
+		let take_evicted = || std::mem::take(&mut *evicted.lock().unwrap());
+		it.insert(tagged(1)).unwrap();
+		it.insert(tagged(2)).unwrap();
+		it.set_budget(Some(150));
+		assert_eq!(it.budget_used(), Some(96));
+		it.insert(tagged(3)).unwrap();
+		it.insert(tagged(4)).unwrap();
+		assert_eq!(take_evicted(), vec![1]);
+		// updates count as fresh, and growing records evict too
+		it.update_with(2, &|t| t.tags = vec!["a", "b", "c"]).unwrap();
+		assert_eq!(take_evicted(), vec![3]);
+		assert_eq!(it.budget_used(), Some(80 + 48));
+		assert_eq!(it.find(&"a").len(), 2);
+		it.remove(&4);
+		assert_eq!(it.budget_used(), Some(80));
+		it.set_budget(None);
+		assert_eq!(it.budget_used(), None);
+	}
+
+	#[test]
+	fn bounded() {
+		let mut it = MicroTable::bounded(2);
+		it.insert(Tagged { id: 1, tags: vec!["a"] }).unwrap();
+		it.insert(Tagged { id: 2, tags: vec!["a", "b"] }).unwrap();
+		assert!(it.touch(&1).is_some());
+		// without a callback the evicted record is dropped
+		it.insert(Tagged { id: 3, tags: vec!["b"] }).unwrap();
+		assert!(!it.contains_key(&2) && it.len() == 2);
+		assert_eq!(it.find(&"a").len(), 1);
+		assert_eq!(it.find(&"b").len(), 1);
+		let (sender, evicted) = std::sync::mpsc::channel();
+		let sender = std::sync::Mutex::new(sender);
+		it.on_evict(move |t| sender.lock().unwrap().send(t.id).unwrap());
+		it.set_max_records(Some(1));
+		assert_eq!(evicted.try_iter().collect::<Vec<_>>(), vec![1]);
+		it.set_max_records(Some(0));
+		it.insert(Tagged { id: 4, tags: vec!["c"] }).unwrap();
+		assert!(it.is_empty() && !it.contains_cat(&"c"));
+		assert_eq!(evicted.try_iter().collect::<Vec<_>>(), vec![3, 4]);
+		it.set_max_records(None);
+		assert_eq!(it.budget_used(), None);
+	}
+
+	#[cfg(feature="imbl")]
+	#[test]
+	fn persistent_clone() {
+		let mut it = table_fixture();
+		let snapshot = it.clone();
+		assert!(it.index.ptr_eq(&snapshot.index) && it.data.shares_slot(&snapshot.data, 0));
+		it.remove(&BookId(1));
+		assert!(!it.index.ptr_eq(&snapshot.index) && !it.data.shares_slot(&snapshot.data, 0));
+		assert_eq!(snapshot.find(&BookCategory::Author(AuthorId(10))).len(), 2);
+		assert_eq!(it.find(&BookCategory::Author(AuthorId(10))).len(), 1);
+	}
+
+	#[test]
+	fn snapshot() {
+		let mut it = table_fixture();
+		let snap = it.copy_snapshot();
+		let cat = BookCategory::Author(AuthorId(10));
+		assert!(Arc::ptr_eq(&it.index[&cat], &snap.index[&cat]));
+		#[cfg(feature="imbl")]
+		assert!(it.data.shares_slot(&snap.data, 0));
+		it.remove(&BookId(1));
+		it.insert(Book { id: BookId(8), title: "Book №8".into(), science: ScienceId(25), author: AuthorId(11) }).unwrap();
+		assert!(!Arc::ptr_eq(&it.index[&cat], &snap.index[&cat]));
+		let science = BookCategory::Science(ScienceId(24));
+		assert!(Arc::ptr_eq(&it.index[&science], &snap.index[&science]));
+
+		assert_eq!(snap.len(), 7);
+		assert_eq!(snap.find(&cat).len(), 2);
+		assert!(!snap.contains_key(&BookId(8)));
+		assert_eq!(it.find(&cat).len(), 1);
+		assert_eq!(snap.clone().get(&BookId(1)).unwrap().title, "Book №1");
+	}
+
+	#[test]
+	fn column() {
+		let mut it = table_fixture();
+		let authors = it.add_column(|b| b.author.0);
+		let sum = |it: &MicroTable<Book>| it.column(authors).iter().sum::<usize>();
+		assert_eq!(sum(&it), 10 + 11 + 12 + 10 + 11 + 12 + 13);
+		it.remove(&BookId(7));
+		it.remove(&BookId(1));
+		assert_eq!(sum(&it), 56);
+		it.update_with(BookId(2), &|b| b.author = AuthorId(20)).unwrap();
+		it.insert(Book { id: BookId(8), title: "".into(), science: ScienceId(22), author: AuthorId(1) }).unwrap();
+		assert_eq!(sum(&it), 66);
+		it.shrink_to_fit();
+		assert_eq!(it.column(authors).len(), 6);
+		let by_values: Vec<usize> = it.values().map(|b| b.author.0).collect();
+		assert_eq!(it.column(authors), by_values);
+		it.clear();
+		assert!(it.column(authors).is_empty());
+	}
+
+	#[test]
+	#[should_panic(expected = "column of another table")]
+	fn column_of_another_table() {
+		let mut other = table_fixture();
+		let titles = other.add_column(|b| b.title.clone());
+		table_fixture().column(titles);
+	}
+
+	#[test]
+	#[cfg_attr(feature="imbl", ignore = "persistent maps don't preallocate")]
+	fn shrink_on_removal() {
+		let mut it = MicroTable::new();
+		let books = (0..1000).map(|i| Book { id: BookId(i), title: "".into(), science: ScienceId(22), author: AuthorId(i) });
+		it.try_extend(books).unwrap();
+		let cat = BookCategory::Science(ScienceId(22));
+		assert!(it.index[&cat].capacity() >= 1000);
+		for i in 0..990 {
+			it.remove(&BookId(i));
+		}
+		assert!(it.index[&cat].capacity() < 100);
+		assert_eq!(it.find(&cat).len(), 10);
+	}
+
+	#[test]
+	fn unchanged_categories() {
+		let mut it = table_fixture();
+		let snap = it.copy_snapshot();
+		let (s2, a0) = (BookCategory::Science(ScienceId(22)), BookCategory::Author(AuthorId(10)));
+		assert_eq!(it.update_by_cat(s2.clone(), |b| b.title.push('!')).unwrap(), 3);
+		it.update_with(BookId(4), &|b| b.title.clear()).unwrap();
+		// the posting sets weren't touched, so they're still shared with the snapshot
+		assert!(Arc::ptr_eq(&it.index[&s2], &snap.index[&s2]));
+		assert!(Arc::ptr_eq(&it.index[&a0], &snap.index[&a0]));
+		assert_eq!(it.get(&BookId(1)).unwrap().title, "Book №1!");
+		let ids: Vec<usize> = it.values().map(|b| b.id.0).collect();
+		assert_eq!(ids, vec![1, 2, 3, 4, 5, 6, 7]);
+
+		it.update_by_cat(s2.clone(), |b| if b.id == BookId(1) { b.author = AuthorId(11) }).unwrap();
+		assert!(!Arc::ptr_eq(&it.index[&a0], &snap.index[&a0]));
+		assert_eq!(it.find(&a0).len(), 1);
+		assert_eq!(it.find(&s2).len(), 3);
+	}
+
+	static CLONES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+	#[derive(Debug)]
+	struct Counted(u32, u8);
+
+	impl Clone for Counted {
+		fn clone(&self) -> Self {
+			CLONES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+			Self(self.0, self.1)
+		}
+	}
+
+	impl MicroRecord for Counted {
+		type Key = u32;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.1]
+		}
+		fn key(&self) -> Self::Key {
+			self.0
+		}
+	}
+
+	#[test]
+	fn update_clones() {
+		let clones = || CLONES.load(std::sync::atomic::Ordering::Relaxed);
+		let mut it = MicroTable::new();
+		it.insert_with_ttl(Counted(1, 1), Duration::from_secs(60)).unwrap();
+		it.upsert(1, Counted(2, 1)).unwrap();
+		assert_eq!(clones(), 0);
+		it.update_with(2, &|c| c.0 = 3).unwrap();
+		it.update_with(3, &|c| c.1 = 2).unwrap();
+		assert_eq!(clones(), 2);
+		assert_eq!(it.find(&2).len(), 1);
+		assert_eq!(it.expire(Instant::now() + Duration::from_secs(61)).len(), 1);
+	}
+
+	static KEY_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+	#[derive(Debug, Clone)]
+	struct Hashed {
+		name: &'static str,
+		group: u8,
+	}
+
+	impl MicroRecord for Hashed {
+		type Key = u64;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.group]
+		}
+		fn key(&self) -> Self::Key {
+			KEY_CALLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+			self.name.len() as u64
+		}
+	}
+
+	#[test]
+	fn key_computed_once() {
+		let calls = || KEY_CALLS.load(std::sync::atomic::Ordering::Relaxed);
+		let mut it: MicroTable<Hashed> = MicroTable::new();
+		it.insert(Hashed { name: "a", group: 1 }).unwrap();
+		it.insert_with_ttl(Hashed { name: "bb", group: 1 }, Duration::from_secs(60)).unwrap();
+		assert_eq!(calls(), 2);
+		it.update_by_cat(1, |h| h.group = 2).unwrap();
+		assert_eq!(calls(), 4);
+		it.update_with(1, &|h| h.group = 3).unwrap();
+		assert_eq!(calls(), 5);
+		assert_eq!(it.find(&3).len(), 1);
+	}
+
+	#[cfg(feature="indexmap")]
+	#[test]
+	fn insertion_order() {
+		let mut it = table_fixture();
+		let ids = |it: &MicroTable<Book>| it.values().map(|b| b.id.0).collect::<Vec<_>>();
+		assert_eq!(ids(&it), vec![1, 2, 3, 4, 5, 6, 7]);
+		it.remove(&BookId(2));
+		it.insert(books_fixture()[1].clone()).unwrap();
+		assert_eq!(ids(&it), vec![1, 3, 4, 5, 6, 7, 2]);
+
+		let cats: Vec<_> = it.iter_cats().take(3).cloned().collect();
+		assert_eq!(cats, vec![BookCategory::Science(ScienceId(22)), BookCategory::Author(AuthorId(10)), BookCategory::Author(AuthorId(11))]);
+		let found: Vec<_> = it.find(&BookCategory::Science(ScienceId(23))).iter().map(|b| b.id.0).collect();
+		assert_eq!(found, vec![4, 5, 6]);
+	}
 }
@@ -0,0 +1,105 @@
+//! Maps and sets used inside the table. With the `indexmap` feature they are `IndexMap` and `IndexSet`,
+//! so that categories and their records are iterated in a deterministic order.
+//! Without `std` they come from `hashbrown`, as do the plain `HashMap` and `HashSet` used for smaller lookups.
+//! With the `imbl` feature they are persistent maps, which share their structure between clones. They have no order
+//! to keep, so `imbl` excludes `indexmap`.
+
+use core::hash::{BuildHasher, Hash};
+
+#[cfg(feature="std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+#[cfg(not(feature="std"))]
+pub(crate) use hashbrown::{HashMap, HashSet};
+
+#[cfg(not(any(feature="indexmap", feature="imbl")))]
+pub(crate) type Map<K, V, H> = HashMap<K, V, H>;
+#[cfg(not(any(feature="indexmap", feature="imbl")))]
+pub(crate) type Set<K, H> = HashSet<K, H>;
+#[cfg(all(not(any(feature="indexmap", feature="imbl")), feature="std"))]
+pub(crate) use std::collections::hash_map::{Entry, VacantEntry};
+#[cfg(all(not(any(feature="indexmap", feature="imbl")), not(feature="std")))]
+pub(crate) use hashbrown::hash_map::{Entry, VacantEntry};
+
+#[cfg(all(feature="indexmap", not(feature="imbl")))]
+pub(crate) type Map<K, V, H> = indexmap::IndexMap<K, V, H>;
+#[cfg(all(feature="indexmap", not(feature="imbl")))]
+pub(crate) type Set<K, H> = indexmap::IndexSet<K, H>;
+#[cfg(all(feature="indexmap", not(feature="imbl")))]
+pub(crate) use indexmap::map::{Entry, VacantEntry};
+
+#[cfg(all(feature="imbl", feature="indexmap"))]
+compile_error!("the `imbl` and `indexmap` features can't be enabled together: persistent maps keep no insertion order");
+
+#[cfg(feature="imbl")]
+pub(crate) type Map<K, V, H> = imbl::GenericHashMap<K, V, H, imbl::shared_ptr::DefaultSharedPtr>;
+#[cfg(feature="imbl")]
+pub(crate) type Set<K, H> = imbl::GenericHashSet<K, H, imbl::shared_ptr::DefaultSharedPtr>;
+#[cfg(feature="imbl")]
+pub(crate) type Entry<'a, K, V, H> = imbl::hashmap::Entry<'a, K, V, H, imbl::shared_ptr::DefaultSharedPtr>;
+#[cfg(feature="imbl")]
+pub(crate) type VacantEntry<'a, K, V, H> = imbl::hashmap::VacantEntry<'a, K, V, H, imbl::shared_ptr::DefaultSharedPtr>;
+
+/// Persistent maps and sets don't preallocate. These stand in for the capacity methods of the others,
+/// so the table code is the same for all of them.
+#[cfg(feature="imbl")]
+pub(crate) trait NoCapacity<H> {
+	fn with_capacity_and_hasher(_capacity: usize, hasher: H) -> Self;
+	fn capacity(&self) -> usize;
+	fn reserve(&mut self, _additional: usize) {}
+	fn shrink_to_fit(&mut self) {}
+	fn shrink_to(&mut self, _min_capacity: usize) {}
+}
+
+#[cfg(feature="imbl")]
+impl<K: Hash + Eq + Clone, V: Clone, H: BuildHasher + Clone> NoCapacity<H> for Map<K, V, H> {
+	fn with_capacity_and_hasher(_capacity: usize, hasher: H) -> Self {
+		Self::with_hasher(hasher)
+	}
+	fn capacity(&self) -> usize {
+		self.len()
+	}
+}
+
+#[cfg(feature="imbl")]
+impl<K: Hash + Eq + Clone, H: BuildHasher + Clone> NoCapacity<H> for Set<K, H> {
+	fn with_capacity_and_hasher(_capacity: usize, hasher: H) -> Self {
+		Self::with_hasher(hasher)
+	}
+	fn capacity(&self) -> usize {
+		self.len()
+	}
+}
+
+/// Removes the entry keeping the order of the rest (an O(n) shift with `indexmap`, since categories are removed rarely).
+pub(crate) fn map_remove<K: Hash + Eq + Clone, V: Clone, H: BuildHasher + Clone>(map: &mut Map<K, V, H>, key: &K) -> Option<V> {
+	#[cfg(all(feature="indexmap", not(feature="imbl")))]
+	return map.shift_remove(key);
+	#[cfg(not(all(feature="indexmap", not(feature="imbl"))))]
+	return map.remove(key);
+}
+
+/// Removes the entry in O(1), moving the last entry into its place with `indexmap`. For maps whose order isn't used.
+pub(crate) fn map_swap_remove<K: Hash + Eq + Clone, V: Clone, H: BuildHasher + Clone>(map: &mut Map<K, V, H>, key: &K) -> Option<V> {
+	#[cfg(all(feature="indexmap", not(feature="imbl")))]
+	return map.swap_remove(key);
+	#[cfg(not(all(feature="indexmap", not(feature="imbl"))))]
+	return map.remove(key);
+}
+
+/// Removes the key, moving the last key into its place with `indexmap`: the order stays deterministic and removal O(1).
+pub(crate) fn set_remove<K: Hash + Eq + Clone, H: BuildHasher + Clone>(set: &mut Set<K, H>, key: &K) -> bool {
+	#[cfg(feature="imbl")]
+	return set.remove(key).is_some();
+	#[cfg(all(feature="indexmap", not(feature="imbl")))]
+	return set.swap_remove(key);
+	#[cfg(not(any(feature="indexmap", feature="imbl")))]
+	return set.remove(key);
+}
+
+/// Adds the key, telling if it's new.
+pub(crate) fn set_insert<K: Hash + Eq + Clone, H: BuildHasher + Clone>(set: &mut Set<K, H>, key: K) -> bool {
+	#[cfg(feature="imbl")]
+	return set.insert(key).is_none();
+	#[cfg(not(feature="imbl"))]
+	return set.insert(key);
+}
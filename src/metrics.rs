@@ -0,0 +1,154 @@
+//! Lock metrics of a [`ConcurrentTable`](crate::ConcurrentTable), to tell whether threads wait for the table:
+//! acquisitions of its locks, the ones that had to wait for another thread, how long they waited, and how many
+//! threads wait at once. A lock taken without waiting is only counted, so the metrics cost little when there's no
+//! contention.
+
+use core::{sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use std::{sync::{LockResult, PoisonError, TryLockError, TryLockResult}, time::Instant};
+
+/// Upper bounds of the buckets of [`LockStats::wait_histogram`], but the last bucket, which has the longer waits.
+pub const WAIT_BOUNDS: [Duration; 7] = [
+	Duration::from_micros(4),
+	Duration::from_micros(16),
+	Duration::from_micros(64),
+	Duration::from_micros(256),
+	Duration::from_millis(1),
+	Duration::from_millis(4),
+	Duration::from_millis(16),
+];
+
+/// Metrics of the locks of a table, or of one shard, from [`ConcurrentTable::lock_stats`](crate::ConcurrentTable::lock_stats).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LockStats {
+	pub reads: u64,
+	pub writes: u64,
+	/// Read locks that had to wait for a writer.
+	pub contended_reads: u64,
+	/// Write locks that had to wait for a reader or a writer.
+	pub contended_writes: u64,
+	/// Total time the read locks waited.
+	pub read_wait: Duration,
+	pub write_wait: Duration,
+	/// Contended locks by how long they waited, in the buckets of [`WAIT_BOUNDS`] and one for the longer waits.
+	pub wait_histogram: [u64; WAIT_BOUNDS.len() + 1],
+	/// Threads waiting for a lock now.
+	pub waiting: u64,
+	/// The most threads waiting for a lock of a shard at once.
+	pub max_waiting: u64,
+}
+
+impl LockStats {
+	/// Sums the metrics of shards. `max_waiting` is the highest of the shards.
+	pub fn merge(mut self, other: &Self) -> Self {
+		self.reads += other.reads;
+		self.writes += other.writes;
+		self.contended_reads += other.contended_reads;
+		self.contended_writes += other.contended_writes;
+		self.read_wait += other.read_wait;
+		self.write_wait += other.write_wait;
+		for (count, other) in self.wait_histogram.iter_mut().zip(other.wait_histogram) {
+			*count += other;
+		}
+		self.waiting += other.waiting;
+		self.max_waiting = self.max_waiting.max(other.max_waiting);
+		self
+	}
+}
+
+/// Counters of the lock of one shard.
+#[derive(Default)]
+pub(crate) struct LockMetrics {
+	/// Reads and writes, by whether they waited.
+	acquired: [[AtomicU64; 2]; 2],
+	/// Nanoseconds waited by reads and writes.
+	waited: [AtomicU64; 2],
+	histogram: [AtomicU64; WAIT_BOUNDS.len() + 1],
+	waiting: AtomicU64,
+	max_waiting: AtomicU64,
+}
+
+impl LockMetrics {
+	/// Takes the lock with `try_lock`, or else waits for it with `lock` and records the wait.
+	pub(crate) fn acquire<G>(&self, write: bool, try_lock: impl FnOnce() -> TryLockResult<G>, lock: impl FnOnce() -> LockResult<G>) -> G {
+		let guard = match try_lock() {
+			Ok(guard) => Some(guard),
+			Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+			Err(TryLockError::WouldBlock) => None,
+		};
+		let kind = write as usize;
+		if let Some(guard) = guard {
+			self.acquired[kind][0].fetch_add(1, Ordering::Relaxed);
+			return guard;
+		}
+		let waiting = self.waiting.fetch_add(1, Ordering::Relaxed) + 1;
+		self.max_waiting.fetch_max(waiting, Ordering::Relaxed);
+		let start = Instant::now();
+		let guard = lock().unwrap_or_else(PoisonError::into_inner);
+		let waited = start.elapsed();
+		self.waiting.fetch_sub(1, Ordering::Relaxed);
+		self.acquired[kind][1].fetch_add(1, Ordering::Relaxed);
+		self.waited[kind].fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+		let bucket = WAIT_BOUNDS.iter().position(|bound| waited < *bound).unwrap_or(WAIT_BOUNDS.len());
+		self.histogram[bucket].fetch_add(1, Ordering::Relaxed);
+		guard
+	}
+
+	/// The counters, each read on its own, so they may be a moment apart.
+	pub(crate) fn stats(&self) -> LockStats {
+		let count = |n: &AtomicU64| n.load(Ordering::Relaxed);
+		let [reads, writes] = &self.acquired;
+		LockStats {
+			reads: count(&reads[0]) + count(&reads[1]),
+			writes: count(&writes[0]) + count(&writes[1]),
+			contended_reads: count(&reads[1]),
+			contended_writes: count(&writes[1]),
+			read_wait: Duration::from_nanos(count(&self.waited[0])),
+			write_wait: Duration::from_nanos(count(&self.waited[1])),
+			wait_histogram: self.histogram.each_ref().map(count),
+			waiting: count(&self.waiting),
+			max_waiting: count(&self.max_waiting),
+		}
+	}
+
+	/// Zeroes the counters, but the threads waiting now.
+	pub(crate) fn reset(&self) {
+		let counters = self.acquired.iter().flatten().chain(&self.waited).chain(&self.histogram).chain([&self.max_waiting]);
+		for n in counters {
+			n.store(0, Ordering::Relaxed);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{ConcurrentTable, fixtures::Account};
+
+	#[test]
+	fn count_waits_for_locks() {
+		let sessions = ConcurrentTable::<Account>::with_shards(2);
+		for id in 0..10 {
+			sessions.insert(Account { id, owner: id % 3, balance: 0 }).unwrap();
+		}
+		assert!(sessions.get(&1).is_some());
+		let stats = sessions.lock_stats();
+		assert_eq!((stats.reads, stats.writes, stats.contended_reads + stats.contended_writes), (1, 10, 0));
+		assert_eq!(stats, (0..2).map(|s| sessions.shard_lock_stats(s)).fold(LockStats::default(), |a, s| a.merge(&s)));
+
+		sessions.reset_lock_stats();
+		std::thread::scope(|s| {
+			let all = sessions.write();
+			let reader = s.spawn(|| sessions.get(&4).map(|s| s.owner));
+			while sessions.lock_stats().waiting == 0 {
+				std::thread::yield_now();
+			}
+			std::thread::sleep(Duration::from_millis(5));
+			drop(all);
+			assert_eq!(reader.join().unwrap(), Some(1));
+		});
+		let stats = sessions.lock_stats();
+		assert_eq!((stats.writes, stats.reads, stats.contended_reads, stats.waiting, stats.max_waiting), (2, 1, 1, 0, 1));
+		assert!(stats.read_wait >= Duration::from_millis(5) && stats.write_wait.is_zero());
+		assert_eq!(stats.wait_histogram[4..].iter().sum::<u64>(), 1);
+	}
+}
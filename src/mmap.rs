@@ -0,0 +1,281 @@
+//! Read-only tables memory-mapped from a file, for data too big to load. [`MicroTable::freeze_to_file`] writes the file,
+//! [`MmapTable::open`] maps it: queries read only the pages they touch, and decode only the records they return.
+//!
+//! The file holds the records, keys and categories encoded with postcard, hash tables of keys and of categories,
+//! and for every category the list of its records' numbers. Keys and categories are hashed and compared as encoded bytes,
+//! so the file doesn't depend on the table's hasher. Buckets, partial indexes and TTLs aren't stored.
+
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, marker::PhantomData, path::Path};
+use core::hash::BuildHasher;
+use memmap2::Mmap;
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{MicroRecord, MicroTable, probe};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"MTBL\0\0\0\x01");
+/// Words of the header: magic, then the counts and lengths that give the sections' sizes.
+const HEADER: usize = 9;
+
+// sections after the header, in file order: arrays of u64 words, then byte blobs
+const REC_OFFSETS: usize = 0;
+const KEY_OFFSETS: usize = 1;
+const KEY_TABLE: usize = 2;
+const CAT_OFFSETS: usize = 3;
+const POST_OFFSETS: usize = 4;
+const CAT_TABLE: usize = 5;
+const POSTINGS: usize = 6;
+const RECORDS: usize = 7;
+const KEYS: usize = 8;
+const CATS: usize = 9;
+
+fn encode<V: Serialize + ?Sized>(v: &V) -> io::Result<Vec<u8>> {
+	postcard::to_allocvec(v).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+	crate::fnv1a(crate::FNV_OFFSET, bytes)
+}
+
+/// [`probe::build`] of the encoded entries.
+fn hash_table(entries: &[Vec<u8>]) -> Vec<u64> {
+	probe::build(entries.iter().map(|bytes| hash(bytes))).into_iter().map(|e| e as u64).collect()
+}
+
+fn offsets(blobs: &[Vec<u8>]) -> Vec<u64> {
+	let mut offsets = Vec::with_capacity(blobs.len() + 1);
+	offsets.push(0);
+	for b in blobs {
+		offsets.push(offsets[offsets.len() - 1] + b.len() as u64);
+	}
+	offsets
+}
+
+pub(crate) fn write<T, H>(table: &MicroTable<T, H>, path: &Path) -> io::Result<()>
+where
+	T: MicroRecord + Serialize,
+	T::Key: Serialize,
+	T::Category: Serialize,
+	H: BuildHasher + Clone,
+{
+	let (mut records, mut keys) = (Vec::with_capacity(table.len()), Vec::with_capacity(table.len()));
+	// records are numbered in the file without the empty slots
+	let mut numbers = HashMap::with_capacity(table.len());
+	for (i, (slot, key, val)) in table.data.iter_slots().enumerate() {
+		records.push(encode(val)?);
+		keys.push(encode(key)?);
+		numbers.insert(slot as u32, i as u64);
+	}
+	let (mut cats, mut post_offsets, mut postings) = (vec![], vec![0], vec![]);
+	for (cat, slots) in table.index.iter() {
+		cats.push(encode(cat)?);
+		postings.extend(slots.iter().map(|s| numbers[&s]));
+		post_offsets.push(postings.len() as u64);
+	}
+	let (rec_offsets, key_offsets, cat_offsets) = (offsets(&records), offsets(&keys), offsets(&cats));
+	let (key_table, cat_table) = (hash_table(&keys), hash_table(&cats));
+	let header = [
+		MAGIC,
+		records.len() as u64,
+		key_table.len() as u64,
+		cats.len() as u64,
+		cat_table.len() as u64,
+		postings.len() as u64,
+		rec_offsets[records.len()],
+		key_offsets[keys.len()],
+		cat_offsets[cats.len()],
+	];
+	let mut w = BufWriter::new(File::create(path)?);
+	for words in [&header[..], &rec_offsets, &key_offsets, &key_table, &cat_offsets, &post_offsets, &cat_table, &postings] {
+		for word in words {
+			w.write_all(&word.to_le_bytes())?;
+		}
+	}
+	for blob in records.iter().chain(&keys).chain(&cats) {
+		w.write_all(blob)?;
+	}
+	w.flush()
+}
+
+/// Read-only table in a memory-mapped file written by [`MicroTable::freeze_to_file`]. Its read methods mirror
+/// the in-memory table's, but return decoded records rather than references.
+/// The file is trusted: its layout is checked on open, but a file corrupted inside may make queries panic.
+pub struct MmapTable<T> {
+	map: Mmap,
+	len: usize,
+	key_slots: usize,
+	cats: usize,
+	cat_slots: usize,
+	/// Byte positions of the sections.
+	start: [usize; 10],
+	record: PhantomData<fn() -> T>,
+}
+
+impl<T> core::fmt::Debug for MmapTable<T> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("MmapTable").field("len", &self.len).field("categories", &self.cats).finish_non_exhaustive()
+	}
+}
+
+impl<T> MmapTable<T>
+where
+	T: MicroRecord + DeserializeOwned,
+	T::Key: Serialize + DeserializeOwned,
+	T::Category: Serialize + DeserializeOwned,
+{
+	pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+		let file = File::open(path)?;
+		// SAFETY: the map is only read; as with any mapped file, it must not be changed while the table is open
+		let map = unsafe { Mmap::map(&file)? };
+		let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a microtable file");
+		if map.len() < HEADER * 8 {
+			return Err(invalid());
+		}
+		let h: Vec<u64> = (0..HEADER).map(|i| u64::from_le_bytes(map[i * 8..i * 8 + 8].try_into().expect("8 bytes"))).collect();
+		let [magic, len, key_slots, cats, cat_slots, postings, rec_bytes, key_bytes, cat_bytes] = h[..] else { unreachable!() };
+		if magic != MAGIC || !key_slots.is_power_of_two() || !cat_slots.is_power_of_two() {
+			return Err(invalid());
+		}
+		let words = |count: u64, extra: u64| count.checked_add(extra)?.checked_mul(8);
+		let sizes = [words(len, 1), words(len, 1), words(key_slots, 0), words(cats, 1), words(cats, 1), words(cat_slots, 0), words(postings, 0), Some(rec_bytes), Some(key_bytes), Some(cat_bytes)];
+		let mut start = [0; 10];
+		let mut pos = (HEADER * 8) as u64;
+		for (s, size) in start.iter_mut().zip(sizes) {
+			*s = pos as usize;
+			pos = size.and_then(|size| pos.checked_add(size)).ok_or_else(invalid)?;
+		}
+		if pos != map.len() as u64 || key_slots <= len || cat_slots <= cats {
+			return Err(invalid());
+		}
+		// all counts are below the file size now
+		let (len, key_slots, cats, cat_slots) = (len as usize, key_slots as usize, cats as usize, cat_slots as usize);
+		Ok(Self { map, len, key_slots, cats, cat_slots, start, record: PhantomData })
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.key_number(key).is_some()
+	}
+
+	pub fn contains_cat(&self, cat: &T::Category) -> bool {
+		self.cat_number(cat).is_some()
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<T> {
+		self.key_number(key).map(|i| self.record(i))
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<T> {
+		let Some(c) = self.cat_number(cat) else { return vec![] };
+		self.posting(c).map(|i| self.record(i)).collect()
+	}
+
+	/// Records of any of the categories, each once.
+	pub fn find_many(&self, cats: &[T::Category]) -> Vec<T> {
+		let mut numbers: Vec<usize> = cats.iter().filter_map(|c| self.cat_number(c)).flat_map(|c| self.posting(c)).collect();
+		numbers.sort_unstable();
+		numbers.dedup();
+		numbers.into_iter().map(|i| self.record(i)).collect()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (T::Key, T)> + '_ {
+		(0..self.len).map(|i| (self.decode(self.blob(KEY_OFFSETS, KEYS, i)), self.record(i)))
+	}
+
+	pub fn values(&self) -> impl Iterator<Item = T> + '_ {
+		(0..self.len).map(|i| self.record(i))
+	}
+
+	pub fn iter_keys(&self) -> impl Iterator<Item = T::Key> + '_ {
+		(0..self.len).map(|i| self.decode(self.blob(KEY_OFFSETS, KEYS, i)))
+	}
+
+	pub fn iter_cats(&self) -> impl Iterator<Item = T::Category> + '_ {
+		(0..self.cats).map(|c| self.decode(self.blob(CAT_OFFSETS, CATS, c)))
+	}
+
+	fn word(&self, section: usize, i: usize) -> u64 {
+		let at = self.start[section] + i * 8;
+		u64::from_le_bytes(self.map[at..at + 8].try_into().expect("8 bytes"))
+	}
+
+	/// Bytes of entry `i` of a blob section, between two offsets of its offsets section.
+	fn blob(&self, offsets: usize, blob: usize, i: usize) -> &[u8] {
+		let (from, to) = (self.word(offsets, i) as usize, self.word(offsets, i + 1) as usize);
+		&self.map[self.start[blob] + from..self.start[blob] + to]
+	}
+
+	fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> V {
+		postcard::from_bytes(bytes).expect("corrupt table file")
+	}
+
+	pub(crate) fn record(&self, i: usize) -> T {
+		self.decode(self.blob(REC_OFFSETS, RECORDS, i))
+	}
+
+	/// Record numbers of category `c`.
+	pub(crate) fn posting(&self, c: usize) -> impl Iterator<Item = usize> + '_ {
+		(self.word(POST_OFFSETS, c) as usize..self.word(POST_OFFSETS, c + 1) as usize).map(|j| self.word(POSTINGS, j) as usize)
+	}
+
+	fn key_number(&self, key: &T::Key) -> Option<usize> {
+		self.lookup(KEY_TABLE, self.key_slots, KEY_OFFSETS, KEYS, &encode(key).ok()?)
+	}
+
+	fn cat_number(&self, cat: &T::Category) -> Option<usize> {
+		self.lookup(CAT_TABLE, self.cat_slots, CAT_OFFSETS, CATS, &encode(cat).ok()?)
+	}
+
+	fn lookup(&self, table: usize, slots: usize, offsets: usize, blob: usize, bytes: &[u8]) -> Option<usize> {
+		probe::find(slots, hash(bytes), |s| self.word(table, s) as usize, |i| self.blob(offsets, blob, i) == bytes)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Edge;
+
+	#[test]
+	fn freeze_and_open() {
+		let path = std::env::temp_dir().join(format!("microtable-mmap-{}.mtbl", std::process::id()));
+		let edge = |id, v1, v2| Edge { id, v1, v2 };
+		let mut edges = MicroTable::new();
+		for i in 0..100 {
+			edges.insert(edge(i, i % 10, 100 + i % 7)).unwrap();
+		}
+		edges.freeze_to_file(&path).unwrap();
+
+		let frozen: MmapTable<Edge> = MmapTable::open(&path).unwrap();
+		assert_eq!(frozen.len(), 100);
+		assert_eq!(frozen.get(&42), Some(edge(42, 2, 100)));
+		assert_eq!(frozen.get(&100), None);
+		assert!(frozen.contains_cat(&103) && !frozen.contains_cat(&11));
+		let mut found: Vec<u32> = frozen.find(&3).iter().map(|e| e.id).collect();
+		found.sort();
+		assert_eq!(found, vec![3, 13, 23, 33, 43, 53, 63, 73, 83, 93]);
+		assert_eq!(frozen.find_many(&[3, 103]).len(), edges.find_many(&[3, 103]).len());
+		assert_eq!(frozen.iter_cats().count(), 17);
+		assert_eq!(frozen.values().count(), 100);
+
+		let lazy: crate::LazyTable<Edge> = crate::LazyTable::open(&path).unwrap();
+		assert_eq!((lazy.len(), lazy.decoded_len()), (100, 0));
+		assert_eq!(lazy.find(&3).len(), 10);
+		assert!(std::ptr::eq(lazy.get(&13).unwrap(), lazy.find_iter(&3).find(|e| e.id == 13).unwrap()));
+		assert_eq!((lazy.decoded_len(), lazy.iter_cats().count()), (10, 17));
+		assert_eq!(lazy.get(&42), Some(&edge(42, 2, 100)));
+
+		MicroTable::<Edge>::new().freeze_to_file(&path).unwrap();
+		let empty: MmapTable<Edge> = MmapTable::open(&path).unwrap();
+		assert!(empty.is_empty() && empty.find(&3).is_empty());
+
+		std::fs::write(&path, b"not a table").unwrap();
+		assert!(MmapTable::<Edge>::open(&path).is_err());
+		std::fs::remove_file(&path).unwrap();
+	}
+}
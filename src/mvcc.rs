@@ -0,0 +1,343 @@
+//! Multi-version tables: [`MvccTable`] keeps the past versions of records as long as a reader may need them. A
+//! [`Snapshot`] reads the table as it was when it was taken, however long it's kept and whatever is written meanwhile,
+//! and a [`Transaction`] publishes all its changes as one new version, or none of them.
+//!
+//! Readers and the writer lock the table only for a lookup or for publishing, so a long report doesn't hold up writes.
+//! Unlike [`crate::swap::SwapTable`], a write copies only the records it changes. A replaced or removed record is
+//! dropped once no snapshot older than the change is left.
+
+use core::hash::BuildHasher;
+use alloc::{collections::{BTreeMap, VecDeque}, sync::Arc, vec::Vec};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock};
+use crate::{KeyError, MicroRecord, RandomState, map::{HashMap, HashSet}};
+
+/// A record as written by a version, or its removal.
+struct Version<T> {
+	at: u64,
+	val: Option<Arc<T>>,
+}
+
+struct State<T: MicroRecord, H> {
+	committed: u64,
+	/// Versions of every key, oldest first.
+	chains: HashMap<T::Key, Vec<Version<T>>, H>,
+	/// Keys that have a kept version in the category.
+	index: HashMap<T::Category, HashSet<T::Key, H>, H>,
+	/// Keys with versions replaced by a later one, by the version that replaced them, to drop when no reader needs them.
+	garbage: VecDeque<(u64, T::Key)>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> State<T, H> {
+	fn visible(&self, key: &T::Key, version: u64) -> Option<&Arc<T>> {
+		let chain = self.chains.get(key)?;
+		chain.iter().rev().find(|v| v.at <= version)?.val.as_ref()
+	}
+
+	/// Drops the versions replaced at or before `horizon`, the oldest version that a reader may read.
+	fn collect(&mut self, horizon: u64) {
+		while self.garbage.front().is_some_and(|(at, _)| *at <= horizon) {
+			let (_, key) = self.garbage.pop_front().expect("checked above");
+			let Some(chain) = self.chains.get_mut(&key) else { continue };
+			let keep_from = chain.iter().rposition(|v| v.at <= horizon).unwrap_or(0);
+			let mut dropped: Vec<Version<T>> = chain.drain(..keep_from).collect();
+			if chain.len() == 1 && chain[0].val.is_none() {
+				dropped.extend(chain.pop());
+			}
+			for cat in dropped.iter().filter_map(|v| v.val.as_ref()).flat_map(|val| val.categories()) {
+				let kept = chain.iter().filter_map(|v| v.val.as_ref()).any(|val| val.categories().into_iter().any(|c| c == cat));
+				if !kept {
+					if let Some(keys) = self.index.get_mut(&cat) {
+						keys.remove(&key);
+						if keys.is_empty() {
+							self.index.remove(&cat);
+						}
+					}
+				}
+			}
+			if chain.is_empty() {
+				self.chains.remove(&key);
+			}
+		}
+	}
+}
+
+/// Table of records with versions, see the [module docs](crate::mvcc).
+pub struct MvccTable<T: MicroRecord, H = RandomState> {
+	state: RwLock<State<T, H>>,
+	/// Versions read by open snapshots, and how many of them read each. Locked before `state`, never after.
+	readers: Mutex<BTreeMap<u64, usize>>,
+	/// Taken by the open transaction, so that transactions take turns.
+	writer: Mutex<()>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for MvccTable<T, H> {
+	fn default() -> Self {
+		Self::with_hasher(H::default())
+	}
+}
+
+impl<T: MicroRecord> MvccTable<T> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MvccTable<T, H> {
+	pub fn with_hasher(hasher: H) -> Self {
+		let state = State {
+			committed: 0,
+			chains: HashMap::with_hasher(hasher.clone()),
+			index: HashMap::with_hasher(hasher),
+			garbage: VecDeque::new(),
+		};
+		Self { state: RwLock::new(state), readers: Mutex::new(BTreeMap::new()), writer: Mutex::new(()) }
+	}
+
+	/// The last published version. It's 0 for a new table, and each transaction with changes adds 1.
+	pub fn version(&self) -> u64 {
+		self.state.read().unwrap_or_else(PoisonError::into_inner).committed
+	}
+
+	/// The table as of the last published version, until the snapshot is dropped.
+	pub fn snapshot(&self) -> Snapshot<'_, T, H> {
+		let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+		let version = self.version();
+		*readers.entry(version).or_default() += 1;
+		Snapshot { table: self, version }
+	}
+
+	/// Starts a transaction over the last published version. It waits for the open transaction, if any.
+	pub fn begin(&self) -> Transaction<'_, T, H> {
+		let writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+		let state = self.state.read().unwrap_or_else(PoisonError::into_inner);
+		Transaction { table: self, _writer: writer, version: state.committed, changes: HashMap::with_hasher(state.chains.hasher().clone()) }
+	}
+
+	/// Drops the versions that no open snapshot reads. It's done after each transaction and after the last snapshot
+	/// of a version is dropped, unless the table is busy then.
+	pub fn collect_garbage(&self) {
+		let readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+		let horizon = readers.keys().next().copied().unwrap_or(state.committed);
+		state.collect(horizon);
+	}
+
+	/// Number of kept versions of records, including removals: the memory that old versions hold.
+	pub fn kept_versions(&self) -> usize {
+		self.state.read().unwrap_or_else(PoisonError::into_inner).chains.values().map(Vec::len).sum()
+	}
+}
+
+/// The table as of one version. Reads lock the table only while they run.
+pub struct Snapshot<'a, T: MicroRecord, H: BuildHasher + Clone> {
+	table: &'a MvccTable<T, H>,
+	version: u64,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> Snapshot<'_, T, H> {
+	fn read<R>(&self, f: impl FnOnce(&State<T, H>) -> R) -> R {
+		f(&self.table.state.read().unwrap_or_else(PoisonError::into_inner))
+	}
+
+	pub fn version(&self) -> u64 {
+		self.version
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<Arc<T>> {
+		self.read(|state| state.visible(key, self.version).cloned())
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.read(|state| state.visible(key, self.version).is_some())
+	}
+
+	pub fn find(&self, cat: &T::Category) -> Vec<Arc<T>> {
+		self.read(|state| {
+			let Some(keys) = state.index.get(cat) else { return vec![] };
+			keys.iter()
+				.filter_map(|key| state.visible(key, self.version))
+				.filter(|val| val.categories().into_iter().any(|c| c == *cat))
+				.cloned()
+				.collect()
+		})
+	}
+
+	/// All records of the version.
+	pub fn values(&self) -> Vec<Arc<T>> {
+		self.read(|state| state.chains.keys().filter_map(|key| state.visible(key, self.version)).cloned().collect())
+	}
+
+	pub fn len(&self) -> usize {
+		self.read(|state| state.chains.keys().filter(|key| state.visible(key, self.version).is_some()).count())
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> Drop for Snapshot<'_, T, H> {
+	fn drop(&mut self) {
+		let mut readers = self.table.readers.lock().unwrap_or_else(PoisonError::into_inner);
+		let count = readers.get_mut(&self.version).expect("registered by MvccTable::snapshot");
+		*count -= 1;
+		if *count > 0 {
+			return;
+		}
+		readers.remove(&self.version);
+		// a reader doesn't wait for the writer to collect: the next transaction will
+		if let Ok(mut state) = self.table.state.try_write() {
+			let horizon = readers.keys().next().copied().unwrap_or(state.committed);
+			state.collect(horizon);
+		}
+	}
+}
+
+/// Changes published together by [`Transaction::commit`]. Dropping it drops the changes. Reads see the published
+/// version it started from, with its own changes.
+pub struct Transaction<'a, T: MicroRecord, H: BuildHasher + Clone> {
+	table: &'a MvccTable<T, H>,
+	_writer: MutexGuard<'a, ()>,
+	version: u64,
+	changes: HashMap<T::Key, Option<Arc<T>>, H>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> Transaction<'_, T, H> {
+	pub fn get(&self, key: &T::Key) -> Option<Arc<T>> {
+		match self.changes.get(key) {
+			Some(change) => change.clone(),
+			None => self.table.state.read().unwrap_or_else(PoisonError::into_inner).visible(key, self.version).cloned(),
+		}
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.get(key).is_some()
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		let key = val.key();
+		if self.contains_key(&key) {
+			return Err(KeyError::Collision);
+		}
+		self.changes.insert(key, Some(Arc::new(val)));
+		Ok(())
+	}
+
+	/// [`crate::MicroTable::upsert`]: replaces the record of `key`, or inserts the new one if there's none.
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), KeyError<T::Key>> {
+		let new_key = new_val.key();
+		if new_key != key {
+			if self.contains_key(&new_key) {
+				return Err(KeyError::Collision);
+			}
+			self.remove(&key);
+		}
+		self.changes.insert(new_key, Some(Arc::new(new_val)));
+		Ok(())
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Option<Arc<T>> {
+		let old = self.get(key)?;
+		self.changes.insert(key.clone(), None);
+		Some(old)
+	}
+
+	/// Publishes the changes as the next version, and returns it. A transaction without changes publishes nothing
+	/// and returns the version it read.
+	pub fn commit(self) -> u64 {
+		if self.changes.is_empty() {
+			return self.version;
+		}
+		let mut state = self.table.state.write().unwrap_or_else(PoisonError::into_inner);
+		let at = state.committed + 1;
+		let hasher = state.chains.hasher().clone();
+		for (key, val) in self.changes {
+			if val.is_none() && !state.chains.contains_key(&key) {
+				// inserted and removed by this transaction: there's no earlier version to hide
+				continue;
+			}
+			for cat in val.iter().flat_map(|val| val.categories()) {
+				state.index.entry(cat).or_insert_with(|| HashSet::with_hasher(hasher.clone())).insert(key.clone());
+			}
+			let chain = state.chains.entry(key.clone()).or_default();
+			let replaces = !chain.is_empty();
+			chain.push(Version { at, val });
+			if replaces {
+				state.garbage.push_back((at, key));
+			}
+		}
+		state.committed = at;
+		drop(state);
+		self.table.collect_garbage();
+		at
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Account;
+
+	fn total(accounts: &[Arc<Account>]) -> i64 {
+		accounts.iter().map(|a| a.balance).sum()
+	}
+
+	#[test]
+	fn snapshots_are_isolated() {
+		let bank = MvccTable::<Account>::new();
+		let mut tx = bank.begin();
+		for id in 0..10 {
+			tx.insert(Account { id, owner: if id < 5 { 1 } else { 2 }, balance: 100 }).unwrap();
+		}
+		assert_eq!(tx.insert(Account { id: 3, owner: 1, balance: 0 }), Err(KeyError::Collision));
+		assert_eq!(tx.commit(), 1);
+
+		let report = bank.snapshot();
+		std::thread::scope(|s| {
+			s.spawn(|| {
+				for round in 0..50 {
+					let mut tx = bank.begin();
+					let (from, to) = (round % 10, (round + 3) % 10);
+					let mut a = Account::clone(&tx.get(&from).unwrap());
+					let mut b = Account::clone(&tx.get(&to).unwrap());
+					a.balance -= 10;
+					b.balance += 10;
+					tx.upsert(from, a).unwrap();
+					tx.upsert(to, b).unwrap();
+					tx.commit();
+				}
+			});
+			for _ in 0..50 {
+				assert_eq!(total(&report.values()), 1000);
+			}
+		});
+		assert_eq!((report.version(), total(&report.find(&1))), (1, 500));
+		assert_eq!(total(&bank.snapshot().values()), 1000);
+		assert!(bank.kept_versions() > 10);
+
+		let mut tx = bank.begin();
+		tx.remove(&0);
+		tx.upsert(9, Account { id: 10, owner: 1, balance: 0 }).unwrap();
+		assert!(tx.get(&0).is_none());
+		drop(tx);
+		let mut tx = bank.begin();
+		tx.remove(&0);
+		tx.upsert(9, Account { id: 10, owner: 1, balance: 0 }).unwrap();
+		assert_eq!(tx.commit(), 52);
+		assert_eq!((report.get(&0).map(|a| a.balance), report.contains_key(&10)), (Some(100), false));
+		assert_eq!(report.find(&2).len(), 5);
+		let now = bank.snapshot();
+		assert_eq!((now.len(), now.find(&1).len(), now.find(&2).len()), (9, 5, 4));
+
+		// old versions go once the report is done
+		drop((report, now));
+		bank.collect_garbage();
+		assert_eq!(bank.kept_versions(), 9);
+
+		let mut tx = bank.begin();
+		tx.insert(Account { id: 20, owner: 3, balance: 0 }).unwrap();
+		tx.remove(&20);
+		tx.commit();
+		assert_eq!(bank.kept_versions(), 9);
+	}
+}
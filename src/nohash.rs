@@ -0,0 +1,93 @@
+//! Hasher for tables keyed by plain integers (or newtypes of them): an integer hashes to itself.
+//! Other writes, like strings in categories, are still mixed, so it's usable for every map of the table,
+//! but it's only fast and well-spread for integer keys.
+
+use core::hash::{BuildHasherDefault, Hasher};
+use crate::MicroTable;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntHasher(u64);
+
+impl Hasher for IntHasher {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		// FNV-1a
+		for b in bytes {
+			self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+		}
+	}
+
+	fn write_u64(&mut self, n: u64) {
+		// a single integer is kept as is, and several (e.g. an enum discriminant and its field) are combined
+		self.0 = self.0.rotate_left(7) ^ n;
+	}
+
+	fn write_u8(&mut self, n: u8) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_u16(&mut self, n: u16) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_u32(&mut self, n: u32) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_usize(&mut self, n: usize) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_i8(&mut self, n: i8) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_i16(&mut self, n: i16) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_i32(&mut self, n: i32) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_i64(&mut self, n: i64) {
+		self.write_u64(n as u64)
+	}
+
+	fn write_isize(&mut self, n: isize) {
+		self.write_u64(n as u64)
+	}
+}
+
+pub type BuildIntHasher = BuildHasherDefault<IntHasher>;
+
+/// Table that doesn't hash integer keys. Create it with `IntTable::default()`.
+pub type IntTable<T> = MicroTable<T, BuildIntHasher>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::hash::BuildHasher;
+	use crate::fixtures::Note;
+
+	#[test]
+	fn identity() {
+		let b = BuildIntHasher::default();
+		assert_eq!(b.hash_one(42u32), 42);
+		assert_ne!(b.hash_one("a"), b.hash_one("b"));
+		assert_ne!(b.hash_one((1u8, 2u64)), 2);
+	}
+
+	#[test]
+	fn int_table() {
+		let mut it: IntTable<Note> = IntTable::default();
+		for id in 0..100 {
+			it.insert(Note { id, tag: format!("z{}", id % 3) }).unwrap();
+		}
+		assert_eq!(it.find(&"z0".into()).len(), 34);
+		assert!(it.contains_key(&99));
+	}
+}
@@ -0,0 +1,98 @@
+//! Changes to a table as messages, for a writer to send its changes to replicas that keep the same table.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+use crate::{KeyError, MicroRecord, MicroTable};
+
+/// A change to a table, to serialize and [apply](MicroTable::apply_op) to a replica. A writer makes the change on its
+/// table with [`MicroTable::apply_op`] too, or sends the op for each change it makes by the table's methods: an
+/// [`MicroTable::update_with`] is sent as the `Upsert` of the updated record under the old key, which covers a changed key
+/// and records moving between categories.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(
+	serialize = "T: Serialize, T::Key: Serialize, T::Category: Serialize",
+	deserialize = "T: Deserialize<'de>, T::Key: Deserialize<'de>, T::Category: Deserialize<'de>",
+))]
+pub enum TableOp<T: MicroRecord> {
+	Insert(T),
+	/// [`MicroTable::upsert`] of the record under the old key.
+	Upsert(T::Key, T),
+	/// Removes the record if it's there, like [`MicroTable::remove`].
+	Remove(T::Key),
+	RemoveCat(T::Category),
+	Clear,
+	/// Ops applied in order.
+	Batch(Vec<TableOp<T>>),
+}
+
+impl<T: MicroRecord + core::fmt::Debug> core::fmt::Debug for TableOp<T>
+where T::Key: core::fmt::Debug, T::Category: core::fmt::Debug {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Insert(val) => f.debug_tuple("Insert").field(val).finish(),
+			Self::Upsert(key, val) => f.debug_tuple("Upsert").field(key).field(val).finish(),
+			Self::Remove(key) => f.debug_tuple("Remove").field(key).finish(),
+			Self::RemoveCat(cat) => f.debug_tuple("RemoveCat").field(cat).finish(),
+			Self::Clear => f.write_str("Clear"),
+			Self::Batch(ops) => f.debug_tuple("Batch").field(ops).finish(),
+		}
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Makes the change, as the method of the same name would, so a replica that applies the writer's ops in order
+	/// keeps the writer's records. A change the writer's table would reject is rejected here too: an error means the
+	/// replica has drifted or missed an op. A batch stops at the first rejected op, with the ops before it applied.
+	pub fn apply_op(&mut self, op: TableOp<T>) -> Result<(), KeyError<T::Key>> {
+		match op {
+			TableOp::Insert(val) => self.insert(val),
+			TableOp::Upsert(key, val) => self.upsert(key, val),
+			TableOp::Remove(key) => {
+				self.remove(&key);
+				Ok(())
+			}
+			TableOp::RemoveCat(cat) => {
+				self.remove_cat(&cat);
+				Ok(())
+			}
+			TableOp::Clear => {
+				self.clear();
+				Ok(())
+			}
+			TableOp::Batch(ops) => ops.into_iter().try_for_each(|op| self.apply_op(op)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Note;
+
+	fn note(id: u32, tag: &str) -> Note {
+		Note { id, tag: tag.into() }
+	}
+
+	#[test]
+	fn replay_ops_on_replica() {
+		let ops = [
+			TableOp::Batch((1..=4).map(|id| TableOp::Insert(note(id, "open"))).collect()),
+			TableOp::Upsert(2, note(20, "closed")), // new key and category
+			TableOp::Remove(3),
+			TableOp::RemoveCat("closed".into()),
+			TableOp::Insert(note(5, "closed")),
+		];
+		let (mut writer, mut replica) = (MicroTable::<Note>::default(), MicroTable::<Note>::default());
+		for op in ops {
+			let bytes = postcard::to_allocvec(&op).unwrap();
+			writer.apply_op(op).unwrap();
+			replica.apply_op(postcard::from_bytes(&bytes).unwrap()).unwrap();
+		}
+		assert_eq!((replica.len(), replica.find(&"open".into()).len()), (3, 2));
+		assert!(replica.iter().all(|(key, val)| writer.get(key) == Some(val)));
+		assert!(replica.apply_op(TableOp::Remove(3)).is_ok());
+		assert_eq!(replica.len(), 3);
+		assert!(matches!(replica.apply_op(TableOp::Insert(note(1, "open"))), Err(KeyError::Collision)));
+	}
+}
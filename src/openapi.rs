@@ -0,0 +1,65 @@
+//! OpenAPI schema of tables, with utoipa: the array of records that the table's `Serialize` writes.
+//! Fields using [`crate::keyed`], [`crate::indexed`] or [`crate::versioned`] have other shapes and need their own schemas.
+
+use core::hash::BuildHasher;
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use utoipa::{ToSchema, __dev::ComposeSchema, openapi::{Ref, RefOr, schema::{ArrayBuilder, Schema}}};
+use crate::{MicroRecord, MicroTable};
+
+/// Derived schemas of structs compose the schemas of their generic fields from the schemas of the type arguments,
+/// which they pass in `generics`: here, the schema of the records. utoipa derives `PartialSchema` from this.
+/// The trait is in utoipa's `__dev` module, outside its semver guarantees, so Cargo.toml holds utoipa to 5.5 patch releases.
+impl<T: MicroRecord + ToSchema, H: BuildHasher + Clone> ComposeSchema for MicroTable<T, H> {
+	fn compose(generics: Vec<RefOr<Schema>>) -> RefOr<Schema> {
+		let items = generics.into_iter().next().unwrap_or_else(|| RefOr::Ref(Ref::from_schema_name(T::name())));
+		ArrayBuilder::new()
+			.items(items)
+			.description(Some("Records of the table, with distinct keys"))
+			.into()
+	}
+}
+
+impl<T: MicroRecord + ToSchema, H: BuildHasher + Clone> ToSchema for MicroTable<T, H> {
+	/// utoipa appends the names of the type arguments, e.g. `MicroTable_Pet`.
+	fn name() -> Cow<'static, str> {
+		"MicroTable".into()
+	}
+
+	fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+		schemas.push((T::name().into(), T::schema()));
+		T::schemas(schemas);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Note;
+	use serde::Serialize;
+	use utoipa::OpenApi;
+
+	#[derive(Serialize, ToSchema)]
+	struct Notebook {
+		name: String,
+		notes: MicroTable<Note>,
+	}
+
+	#[test]
+	fn openapi_matches_serialized() {
+		#[derive(OpenApi)]
+		#[openapi(components(schemas(Notebook)))]
+		struct Api;
+
+		let doc = serde_json::to_value(Api::openapi()).unwrap();
+		let schemas = &doc["components"]["schemas"];
+		assert_eq!(schemas["Notebook"]["properties"]["notes"]["$ref"], "#/components/schemas/MicroTable_Note");
+		let notes = &schemas["MicroTable_Note"];
+		assert_eq!(notes["type"], "array");
+		assert_eq!(notes["items"]["required"], serde_json::json!(["id", "tag"]));
+
+		let mut notes = MicroTable::new();
+		notes.insert(Note { id: 1, tag: "cat".into() }).unwrap();
+		let notebook = serde_json::to_value(Notebook { name: "work".into(), notes }).unwrap();
+		assert!(notebook["notes"].is_array() && notebook["notes"][0]["tag"] == "cat");
+	}
+}
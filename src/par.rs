@@ -0,0 +1,130 @@
+//! Updates that run their callbacks on many threads, and bulk loads that decode records and compute their keys
+//! on many threads, with rayon.
+
+use core::hash::BuildHasher;
+use alloc::{sync::Arc, vec::Vec};
+use rayon::prelude::*;
+use crate::{KeyError, MicroRecord, MicroTable};
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// [`MicroTable::update_by_cat`] with the callbacks run in parallel on copies of the records. The copies are then
+	/// checked and put into the table on this thread, so it fails and leaves the table unchanged in the same cases.
+	pub fn par_update_by_cat(&mut self, cat: T::Category, cb: impl Fn(&mut T) + Sync) -> Result<usize, KeyError<T::Key>>
+	where T: Clone + Send + Sync, T::Key: Send + Sync, H: Sync {
+		let Some(slots) = self.index.get(&cat) else { return Ok(0); };
+		let old_slots = Arc::clone(slots);
+		let slots: Vec<u32> = old_slots.iter().collect();
+		let data = &self.data;
+		let updates = slots.par_iter().map(|&slot| {
+			let (old_key, old_val) = data.at(slot as usize);
+			let mut item = old_val.clone();
+			cb(&mut item);
+			(old_key.clone(), item)
+		}).collect();
+		self.apply_cat_updates(&old_slots, updates)
+	}
+
+	/// Builds a table of the records, with their keys computed in parallel. Fails on a repeated key.
+	pub fn try_from_par_iter(vals: impl IntoParallelIterator<Item = T>) -> Result<Self, KeyError<T::Key>>
+	where T: Send, T::Key: Send, H: Default {
+		let mut table = Self::with_hasher(H::default());
+		table.extend_pairs(vals.into_par_iter().map(|val| (val.key(), val)).collect::<Vec<_>>(), Self::insert_keyed)?;
+		Ok(table)
+	}
+
+	/// [`MicroTable::load_snapshot`] that decodes the records on many threads. The file is read and its checksum
+	/// checked first, so it holds all the encoded records in memory for a moment.
+	#[cfg(feature = "binary")]
+	pub fn par_load_snapshot(reader: impl std::io::Read) -> std::io::Result<Self>
+	where T: serde::de::DeserializeOwned + Send, T::Key: Send, H: Default {
+		use std::io::{Error, ErrorKind};
+		let mut frames = crate::binary::Frames::open(reader)?;
+		let (mut bytes, mut ends) = (vec![], Vec::with_capacity(frames.count.min(crate::seed::MAX_PRESIZE as u64) as usize));
+		for _ in 0..frames.count {
+			frames.next_into(&mut bytes)?;
+			ends.push(bytes.len());
+		}
+		frames.finish()?;
+		let records = (0..ends.len()).into_par_iter().map(|i| {
+			let start = if i == 0 { 0 } else { ends[i - 1] };
+			postcard::from_bytes::<T>(&bytes[start..ends[i]]).map(|val| (val.key(), val))
+		}).collect::<Result<Vec<_>, _>>().map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+		drop(bytes);
+		let mut table = Self::with_hasher(H::default());
+		table.extend_pairs(records, Self::insert_keyed).map_err(|_| Error::new(ErrorKind::InvalidData, "duplicate key in snapshot"))?;
+		Ok(table)
+	}
+}
+
+/// A record replaces an earlier one with the same key, as with [`FromIterator`]; [`MicroTable::try_from_par_iter`]
+/// fails on it instead.
+impl<T: MicroRecord + Send, H: BuildHasher + Clone + Default> FromParallelIterator<T> for MicroTable<T, H>
+where T::Key: Send {
+	fn from_par_iter<I: IntoParallelIterator<Item = T>>(vals: I) -> Self {
+		let mut table = Self::default();
+		table.extend_last_wins(vals.into_par_iter().map(|val| (val.key(), val)).collect::<Vec<_>>());
+		table
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq)]
+	#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+	struct Cell {
+		id: u32,
+		sheet: u8,
+		value: u64,
+	}
+
+	impl MicroRecord for Cell {
+		type Key = u32;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.sheet]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn parallel_update_by_cat() {
+		let mut cells: MicroTable<Cell> = (0..10_000).map(|id| Cell { id, sheet: (id % 4) as u8, value: id as u64 }).collect();
+		let mut serial = cells.clone();
+		let recompute = |c: &mut Cell| c.value = (0..c.value % 100).map(|i| i * c.value).sum();
+		assert_eq!(cells.par_update_by_cat(1, recompute), Ok(2_500));
+		assert_eq!(serial.update_by_cat(1, recompute), Ok(2_500));
+		assert!(cells.iter().all(|(k, c)| serial.get(k) == Some(c)));
+
+		assert_eq!(cells.par_update_by_cat(2, |c| c.id %= 100), Err(KeyError::Collision));
+		assert_eq!(cells.get(&6).map(|c| c.value), Some(6));
+		// keys swapped among the updated records are fine
+		assert_eq!(cells.par_update_by_cat(3, |c| c.id = 10_002 - c.id), Ok(2_500));
+		assert_eq!(cells.get(&7).map(|c| c.value), Some(9_995));
+		assert_eq!(cells.par_update_by_cat(9, |_| unreachable!()), Ok(0));
+	}
+
+	#[test]
+	fn parallel_bulk_load() {
+		let cells: MicroTable<Cell> = (0..10_000).into_par_iter().map(|id| Cell { id, sheet: (id % 4) as u8, value: 0 }).collect();
+		assert_eq!((cells.len(), cells.find(&3).len()), (10_000, 2_500));
+		let repeated = MicroTable::<Cell>::try_from_par_iter((0..100).into_par_iter().map(|id| Cell { id: id % 50, sheet: 0, value: 0 }));
+		assert_eq!(repeated.err(), Some(KeyError::Collision));
+		let last: MicroTable<Cell> = (0..100).into_par_iter().map(|id| Cell { id: id % 50, sheet: 0, value: id as u64 }).collect();
+		assert_eq!((last.len(), last.get(&7).map(|c| c.value)), (50, Some(57)));
+
+		#[cfg(feature = "binary")]
+		{
+			let mut bytes = vec![];
+			cells.save_snapshot(&mut bytes).unwrap();
+			let loaded = MicroTable::<Cell>::par_load_snapshot(&bytes[..]).unwrap();
+			assert_eq!((loaded.len(), loaded.find(&3).len()), (10_000, 2_500));
+			assert!(cells.iter().all(|(k, c)| loaded.get(k) == Some(c)));
+			bytes[30] ^= 1;
+			assert!(MicroTable::<Cell>::par_load_snapshot(&bytes[..]).is_err());
+		}
+	}
+}
@@ -0,0 +1,125 @@
+//! Parquet files, written and read through the [Arrow](crate::MicroTable::to_record_batches) conversion:
+//! columnar, Snappy-compressed, and readable by most data tools.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use std::{fs::File, io, path::Path};
+use parquet::{arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder}, basic::Compression, file::properties::WriterProperties};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{KeyError, MicroRecord, MicroTable};
+
+/// Rows per row group and per batch.
+const BATCH: usize = 8192;
+
+/// Error of [`MicroTable::write_parquet`] and [`MicroTable::read_parquet`].
+#[derive(Debug)]
+pub enum ParquetError<K> {
+	Io(io::Error),
+	Parquet(parquet::errors::ParquetError),
+	/// The records don't convert to Arrow columns or back.
+	Arrow(serde_arrow::Error),
+	/// The table rejected a record that was read, e.g. for a repeated key.
+	Rejected(KeyError<K>),
+}
+
+impl<K> From<io::Error> for ParquetError<K> {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl<K> From<parquet::errors::ParquetError> for ParquetError<K> {
+	fn from(e: parquet::errors::ParquetError) -> Self {
+		Self::Parquet(e)
+	}
+}
+
+impl<K> From<serde_arrow::Error> for ParquetError<K> {
+	fn from(e: serde_arrow::Error) -> Self {
+		Self::Arrow(e)
+	}
+}
+
+impl<K> From<arrow_schema::ArrowError> for ParquetError<K> {
+	fn from(e: arrow_schema::ArrowError) -> Self {
+		Self::Parquet(e.into())
+	}
+}
+
+impl<K: core::fmt::Debug> core::fmt::Display for ParquetError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::Parquet(e) => e.fmt(f),
+			Self::Arrow(e) => e.fmt(f),
+			Self::Rejected(e) => e.fmt(f),
+		}
+	}
+}
+
+impl<K: core::fmt::Debug> std::error::Error for ParquetError<K> {}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Writes the records to a Parquet file. The schema is traced from the records, so an empty table can't be written.
+	pub fn write_parquet(&self, path: impl AsRef<Path>) -> Result<(), ParquetError<T::Key>>
+	where T: Serialize {
+		let batches = self.to_record_batches(BATCH)?;
+		let props = WriterProperties::builder().set_compression(Compression::SNAPPY).set_max_row_group_size(BATCH).build();
+		let mut writer = ArrowWriter::try_new(File::create(path)?, batches[0].schema(), Some(props))?;
+		for batch in batches.iter() {
+			writer.write(batch)?;
+		}
+		writer.close()?;
+		Ok(())
+	}
+
+	/// Reads the records of a Parquet file whose columns match the fields of `T`.
+	pub fn read_parquet(path: impl AsRef<Path>) -> Result<Self, ParquetError<T::Key>>
+	where T: DeserializeOwned, H: Default {
+		let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.with_batch_size(BATCH).build()?;
+		let mut table = Self::default();
+		for batch in reader {
+			let records: Vec<T> = serde_arrow::from_record_batch(&batch?)?;
+			table.try_extend(records).map_err(ParquetError::Rejected)?;
+		}
+		Ok(table)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+	use serde::Deserialize;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Sale {
+		id: u64,
+		store: String,
+		amount: f64,
+		coupon: Option<String>,
+	}
+
+	impl MicroRecord for Sale {
+		type Key = u64;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.store.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn parquet_roundtrip() {
+		let sales: MicroTable<Sale> = (0..20_000).map(|id| Sale { id, store: format!("s{}", id % 7), amount: id as f64 / 4.0, coupon: (id % 100 == 0).then(|| "HALF".into()) }).collect();
+		let path = std::env::temp_dir().join(format!("microtable-{}.parquet", std::process::id()));
+		sales.write_parquet(&path).unwrap();
+		let back = MicroTable::<Sale>::read_parquet(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!((back.len(), back.find(&"s3".into()).len()), (20_000, sales.find(&"s3".into()).len()));
+		assert_eq!(back.get(&1200), sales.get(&1200));
+		assert!(matches!(MicroTable::<Sale>::read_parquet(std::env::temp_dir().join("missing-microtable.parquet")), Err(ParquetError::Io(_))));
+	}
+}
@@ -0,0 +1,103 @@
+//! JSON merge patches ([RFC 7386](https://www.rfc-editor.org/rfc/rfc7386)) applied to the serialized form of records.
+
+use core::hash::BuildHasher;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use crate::{KeyError, MicroRecord, MicroTable};
+
+/// Error of [`MicroTable::patch`]. The record is left as it was.
+#[derive(Debug)]
+pub enum PatchError<K> {
+	/// The patched JSON isn't a valid record, or the record didn't serialize.
+	Json(serde_json::Error),
+	/// There's no record with the key, or the patched record's key or unique value is taken.
+	Table(KeyError<K>),
+}
+
+impl<K: core::fmt::Debug> core::fmt::Display for PatchError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Json(e) => e.fmt(f),
+			Self::Table(e) => e.fmt(f),
+		}
+	}
+}
+
+impl<K: core::fmt::Debug> std::error::Error for PatchError<K> {}
+
+/// Merges `patch` into `target`: objects field by field, `null` removing a field, anything else replacing the value.
+fn merge(target: &mut Value, patch: Value) {
+	let Value::Object(fields) = patch else {
+		*target = patch;
+		return;
+	};
+	if !target.is_object() {
+		*target = Value::Object(serde_json::Map::new());
+	}
+	let Value::Object(target) = target else { unreachable!() };
+	for (name, value) in fields {
+		if value.is_null() {
+			target.remove(&name);
+		} else {
+			merge(target.entry(name).or_insert(Value::Null), value);
+		}
+	}
+}
+
+impl<T: MicroRecord + Serialize + DeserializeOwned, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Applies a JSON merge patch to the record, e.g. `{"price": 10, "discount": null}`, and reindexes it.
+	/// The patch may change the key, as long as the new key is free.
+	pub fn patch(&mut self, key: &T::Key, patch: Value) -> Result<(), PatchError<T::Key>> {
+		let val = self.get(key).ok_or(PatchError::Table(KeyError::NotFound))?;
+		let mut json = serde_json::to_value(val).map_err(PatchError::Json)?;
+		merge(&mut json, patch);
+		let patched: T = serde_json::from_value(json).map_err(PatchError::Json)?;
+		self.upsert(key.clone(), patched).map_err(PatchError::Table)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::{string::String, vec::Vec};
+	use serde::Deserialize;
+	use serde_json::json;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Product {
+		sku: String,
+		shelf: String,
+		price: u32,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		discount: Option<u32>,
+		tags: Vec<String>,
+	}
+
+	impl MicroRecord for Product {
+		type Key = String;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.shelf.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.sku.clone()
+		}
+	}
+
+	#[test]
+	fn merge_patch() {
+		let mut products = MicroTable::new();
+		products.insert(Product { sku: "a1".into(), shelf: "top".into(), price: 10, discount: Some(2), tags: vec!["new".into()] }).unwrap();
+		products.insert(Product { sku: "b2".into(), shelf: "top".into(), price: 5, discount: None, tags: vec![] }).unwrap();
+
+		products.patch(&"a1".into(), json!({"shelf": "bottom", "discount": null, "tags": ["sale"]})).unwrap();
+		let a1 = products.get(&"a1".into()).unwrap();
+		assert_eq!((a1.price, a1.discount, &a1.tags[..]), (10, None, &["sale".to_string()][..]));
+		assert_eq!(products.find(&"bottom".into()).len(), 1);
+
+		assert!(matches!(products.patch(&"b2".into(), json!({"price": "free"})), Err(PatchError::Json(_))));
+		assert!(matches!(products.patch(&"b2".into(), json!({"sku": "a1"})), Err(PatchError::Table(KeyError::Collision))));
+		assert!(matches!(products.patch(&"zz".into(), json!({})), Err(PatchError::Table(KeyError::NotFound))));
+		assert_eq!(products.get(&"b2".into()).map(|p| p.price), Some(5));
+	}
+}
@@ -0,0 +1,109 @@
+//! Polars interop: [`MicroTable::to_dataframe`] turns the records into a `DataFrame`, one column per field, and
+//! [`MicroTable::from_dataframe`] makes records of the rows of a frame, e.g. the result of a query.
+
+use core::hash::BuildHasher;
+use alloc::{string::String, vec::Vec};
+use polars::prelude::{AnyValue, Column, DataFrame, PolarsError, PolarsResult};
+use serde::Serialize;
+use serde_json::Value;
+use crate::{MicroRecord, MicroTable};
+
+/// Column of the field values, of the narrowest type that holds them all: booleans, integers, floats or strings.
+/// Missing fields and nulls are nulls; nested values are stored as JSON text.
+fn column(name: &str, values: Vec<Value>) -> Column {
+	if values.iter().all(|v| v.is_boolean() || v.is_null()) {
+		Column::new(name.into(), values.iter().map(Value::as_bool).collect::<Vec<_>>())
+	} else if values.iter().all(|v| v.is_i64() || v.is_null()) {
+		Column::new(name.into(), values.iter().map(Value::as_i64).collect::<Vec<_>>())
+	} else if values.iter().all(|v| v.is_number() || v.is_null()) {
+		Column::new(name.into(), values.iter().map(Value::as_f64).collect::<Vec<_>>())
+	} else {
+		let text = |v: Value| match v {
+			Value::Null => None,
+			Value::String(s) => Some(s),
+			other => Some(other.to_string()),
+		};
+		Column::new(name.into(), values.into_iter().map(text).collect::<Vec<Option<String>>>())
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// The records as a frame, in the order they're stored. The records must serialize as structs or maps;
+	/// their fields become the columns, in alphabetical order.
+	pub fn to_dataframe(&self) -> PolarsResult<DataFrame>
+	where T: Serialize {
+		let mut names: Vec<String> = Vec::new();
+		let mut rows = Vec::with_capacity(self.len());
+		for val in self.values() {
+			match serde_json::to_value(val).map_err(|e| PolarsError::ComputeError(e.to_string().into()))? {
+				Value::Object(fields) => {
+					names.extend(fields.keys().filter(|k| !names.contains(k)).cloned().collect::<Vec<_>>());
+					rows.push(fields);
+				}
+				_ => return Err(PolarsError::ComputeError("records must serialize as structs or maps".into())),
+			}
+		}
+		let columns = names.iter().map(|name| {
+			column(name, rows.iter_mut().map(|fields| fields.remove(name).unwrap_or(Value::Null)).collect())
+		}).collect();
+		DataFrame::new(columns)
+	}
+
+	/// Makes a record of every row of the frame with `mapper`, which gets the row's values in the order of the columns.
+	/// A repeated key fails with a compute error.
+	pub fn from_dataframe(df: &DataFrame, mut mapper: impl FnMut(&[AnyValue<'_>]) -> PolarsResult<T>) -> PolarsResult<Self>
+	where H: Default {
+		let mut table = Self::with_capacity_and_hasher(df.height(), 0, H::default());
+		let mut row = Vec::with_capacity(df.width());
+		for i in 0..df.height() {
+			row.clear();
+			for col in df.get_columns() {
+				row.push(col.get(i)?);
+			}
+			table.insert(mapper(&row)?).map_err(|_| PolarsError::ComputeError(alloc::format!("row {i} repeats a key or unique value").into()))?;
+		}
+		Ok(table)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq, Serialize)]
+	struct Sensor {
+		id: i64,
+		room: String,
+		temp: f64,
+		online: bool,
+	}
+
+	impl MicroRecord for Sensor {
+		type Key = i64;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.room.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn dataframe_roundtrip() {
+		let sensors: MicroTable<Sensor> = (0..6).map(|id| Sensor { id, room: format!("r{}", id / 2), temp: 20.0 + id as f64, online: id != 4 }).collect();
+		let df = sensors.to_dataframe().unwrap();
+		assert_eq!(df.shape(), (6, 4));
+		assert_eq!(df.get_column_names(), ["id", "online", "room", "temp"]);
+
+		let sensor = |row: &[AnyValue<'_>]| -> PolarsResult<Sensor> {
+			let room = row[2].get_str().ok_or_else(|| PolarsError::ComputeError("room".into()))?.into();
+			Ok(Sensor { id: row[0].try_extract()?, online: row[1] == AnyValue::Boolean(true), room, temp: row[3].try_extract()? })
+		};
+		let back = MicroTable::<Sensor>::from_dataframe(&df, sensor).unwrap();
+		assert_eq!(back.get(&4), sensors.get(&4));
+		assert_eq!(back.find(&"r1".into()).len(), 2);
+		let doubled = df.vstack(&df).unwrap();
+		assert!(MicroTable::<Sensor>::from_dataframe(&doubled, sensor).is_err());
+	}
+}
@@ -0,0 +1,248 @@
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use crate::map::{Set, set_insert, set_remove};
+#[cfg(feature="imbl")]
+use crate::map::NoCapacity;
+
+/// How a table keeps the records of each category, set with [`crate::MicroTable::set_postings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PostingKind {
+	/// Hash sets: inserts, removals and lookups in O(1). The default.
+	#[default]
+	Hashed,
+	/// Sorted vectors: several times smaller than hash sets and quick to intersect in [`crate::MicroTable::find_all`],
+	/// but an insert or removal shifts the entries after it, so they suit categories that are read much more than changed.
+	Sorted,
+}
+
+/// Records of one category, by their storage slots. Slots are dense `u32` numbers, so postings don't copy
+/// or hash the records' keys, whatever they are. The table renumbers them when it moves records between slots.
+/// A category of one record holds its slot inline, without allocating a set; it becomes a set of the table's
+/// [`PostingKind`] when a second record joins.
+#[derive(Clone)]
+pub(crate) enum Posting<H> {
+	One(u32),
+	Hashed(Set<u32, H>),
+	Sorted(Vec<u32>),
+}
+
+impl<H: BuildHasher> core::fmt::Debug for Posting<H> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::One(slot) => f.debug_set().entry(slot).finish(),
+			Self::Hashed(set) => f.debug_set().entries(set.iter()).finish(),
+			Self::Sorted(slots) => f.debug_set().entries(slots).finish(),
+		}
+	}
+}
+
+impl<H: BuildHasher + Clone> Posting<H> {
+	/// Posting of a new category, that takes the first slot inline. An empty vector doesn't allocate.
+	pub(crate) fn empty() -> Self {
+		Self::Sorted(Vec::new())
+	}
+
+	/// The same slots, put through `remap` (slot numbers to new ones), and kept in the `kind` of posting.
+	pub(crate) fn convert(&self, kind: PostingKind, hasher: H, remap: impl Fn(u32) -> u32) -> Self {
+		match kind {
+			PostingKind::Hashed => {
+				let mut set = Set::with_capacity_and_hasher(self.len(), hasher);
+				set.extend(self.iter().map(remap));
+				Self::Hashed(set)
+			}
+			PostingKind::Sorted => {
+				let mut slots: Vec<u32> = self.iter().map(remap).collect();
+				slots.sort_unstable();
+				Self::Sorted(slots)
+			}
+		}
+	}
+
+	/// The same posting with the slots put through `remap`.
+	pub(crate) fn renumber(&self, hasher: H, remap: impl Fn(u32) -> u32) -> Self {
+		match self.kind() {
+			Some(kind) => self.convert(kind, hasher, remap),
+			None => Self::One(self.iter().map(remap).next().expect("one slot")),
+		}
+	}
+
+	/// Kind of the set, `None` for a single inline slot.
+	pub(crate) fn kind(&self) -> Option<PostingKind> {
+		match self {
+			Self::One(_) => None,
+			Self::Hashed(_) => Some(PostingKind::Hashed),
+			Self::Sorted(_) => Some(PostingKind::Sorted),
+		}
+	}
+
+	/// Adds the slot, telling if it's new. A second slot makes a set of `kind`.
+	pub(crate) fn insert(&mut self, slot: u32, kind: PostingKind, hasher: &H) -> bool {
+		if self.is_empty() && self.capacity() == 0 {
+			*self = Self::One(slot);
+			return true;
+		}
+		match self {
+			Self::One(first) => {
+				if *first == slot {
+					return false;
+				}
+				*self = match kind {
+					PostingKind::Hashed => {
+						let mut set = Set::with_capacity_and_hasher(2, hasher.clone());
+						set.extend([*first, slot]);
+						Self::Hashed(set)
+					}
+					PostingKind::Sorted => Self::Sorted(alloc::vec![(*first).min(slot), (*first).max(slot)]),
+				};
+				true
+			}
+			Self::Hashed(set) => set_insert(set, slot),
+			Self::Sorted(slots) => match slots.binary_search(&slot) {
+				Ok(_) => false,
+				Err(i) => {
+					slots.insert(i, slot);
+					true
+				}
+			},
+		}
+	}
+
+	pub(crate) fn remove(&mut self, slot: u32) -> bool {
+		match self {
+			Self::One(s) if *s == slot => {
+				*self = Self::empty();
+				true
+			}
+			Self::One(_) => false,
+			Self::Hashed(set) => set_remove(set, &slot),
+			Self::Sorted(slots) => match slots.binary_search(&slot) {
+				Ok(i) => {
+					slots.remove(i);
+					true
+				}
+				Err(_) => false,
+			},
+		}
+	}
+
+	pub(crate) fn contains(&self, slot: u32) -> bool {
+		match self {
+			Self::One(s) => *s == slot,
+			Self::Hashed(set) => set.contains(&slot),
+			Self::Sorted(slots) => slots.binary_search(&slot).is_ok(),
+		}
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		match self {
+			Self::One(_) => 1,
+			Self::Hashed(set) => set.len(),
+			Self::Sorted(slots) => slots.len(),
+		}
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub(crate) fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+		let (one, set, sorted) = match self {
+			Self::One(slot) => (Some(*slot), None, None),
+			Self::Hashed(set) => (None, Some(set), None),
+			Self::Sorted(slots) => (None, None, Some(slots)),
+		};
+		one.into_iter().chain(set.into_iter().flat_map(|s| s.iter()).chain(sorted.into_iter().flatten()).copied())
+	}
+
+	pub(crate) fn capacity(&self) -> usize {
+		match self {
+			Self::One(_) => 0,
+			Self::Hashed(set) => set.capacity(),
+			Self::Sorted(slots) => slots.capacity(),
+		}
+	}
+
+	pub(crate) fn shrink_to(&mut self, min_capacity: usize) {
+		match self {
+			Self::One(_) => {},
+			Self::Hashed(set) => set.shrink_to(min_capacity),
+			Self::Sorted(slots) => slots.shrink_to(min_capacity),
+		}
+	}
+
+	pub(crate) fn shrink_to_fit(&mut self) {
+		match self {
+			Self::One(_) => {},
+			Self::Hashed(set) => set.shrink_to_fit(),
+			Self::Sorted(slots) => slots.shrink_to_fit(),
+		}
+	}
+
+	/// Estimate of the allocated bytes, by capacity.
+	pub(crate) fn bytes(&self) -> usize {
+		match self {
+			Self::One(_) => 0,
+			// hashbrown keeps roughly one control byte per bucket on top of the slots
+			Self::Hashed(set) => set.capacity() * (core::mem::size_of::<u32>() + 1),
+			Self::Sorted(slots) => slots.capacity() * core::mem::size_of::<u32>(),
+		}
+	}
+}
+
+/// First position from `from` on whose slot isn't below `slot`. The step doubles until it passes the position,
+/// so walking a short list through a long one skips most of the long one.
+fn gallop(slots: &[u32], slot: u32, from: usize) -> usize {
+	let mut bound = 1;
+	while from + bound <= slots.len() && slots[from + bound - 1] < slot {
+		bound *= 2;
+	}
+	let end = (from + bound).min(slots.len());
+	from + slots[from..end].partition_point(|s| *s < slot)
+}
+
+/// Slots present in all the postings.
+pub(crate) fn intersect<H: BuildHasher + Clone>(postings: &mut [&Posting<H>]) -> Vec<u32> {
+	postings.sort_by_key(|p| p.len());
+	let Some((smallest, others)) = postings.split_first() else { return Vec::new() };
+	if let Posting::Sorted(slots) = smallest {
+		let sorted: Option<Vec<&[u32]>> = others.iter().map(|p| match p { Posting::Sorted(o) => Some(&o[..]), _ => None }).collect();
+		if let Some(sorted) = sorted {
+			return intersect_sorted(slots, &sorted);
+		}
+	}
+	smallest.iter().filter(|s| others.iter().all(|p| p.contains(*s))).collect()
+}
+
+/// Slots of `smallest` present in all the `others`, all sorted.
+pub(crate) fn intersect_sorted(smallest: &[u32], others: &[&[u32]]) -> Vec<u32> {
+	// every list is in the same order, so each is walked once, from where the previous slot was
+	let mut cursors = alloc::vec![0; others.len()];
+	smallest.iter().copied().filter(|slot| {
+		others.iter().zip(cursors.iter_mut()).all(|(other, cursor)| {
+			*cursor = gallop(other, *slot, *cursor);
+			other.get(*cursor) == Some(slot)
+		})
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::hash::RandomState;
+
+	#[test]
+	fn intersect_sorted() {
+		let posting = |slots: &[u32]| Posting::<RandomState>::Sorted(slots.to_vec());
+		let (a, b, c) = (posting(&[1, 3, 5, 7, 9]), posting(&(0..100).collect::<Vec<_>>()), posting(&[3, 9, 50]));
+		assert_eq!(intersect(&mut [&a, &b, &c]), vec![3, 9]);
+		let hasher = RandomState::new();
+		let mut hashed = Posting::empty();
+		hashed.insert(9, PostingKind::Hashed, &hasher);
+		assert!(matches!(hashed, Posting::One(9)));
+		assert_eq!(intersect(&mut [&a, &hashed]), vec![9]);
+		hashed.insert(42, PostingKind::Hashed, &hasher);
+		assert_eq!(hashed.kind(), Some(PostingKind::Hashed));
+		assert_eq!(intersect(&mut [&a, &hashed]), vec![9]);
+		assert!(intersect::<RandomState>(&mut []).is_empty());
+	}
+}
@@ -0,0 +1,34 @@
+//! Open addressing hash tables of the file formats, [`crate::mmap`] and [`crate::archive`]: slots hold entry
+//! numbers + 1 (0 is empty), collisions go to the next slot. Each format brings its own hash and comparison.
+
+use alloc::vec::Vec;
+
+/// Slots for entries with these hashes, at most half full, a power of two of them.
+pub(crate) fn build(hashes: impl ExactSizeIterator<Item = u64>) -> Vec<usize> {
+	let slots = (hashes.len() * 2).next_power_of_two();
+	let mut table = vec![0; slots];
+	for (i, hash) in hashes.enumerate() {
+		let mut s = hash as usize & (slots - 1);
+		while table[s] != 0 {
+			s = (s + 1) & (slots - 1);
+		}
+		table[s] = i + 1;
+	}
+	table
+}
+
+/// Finds the number of the entry with the hash for which `matches` holds, reading slot `s` with `slot(s)`.
+pub(crate) fn find(slots: usize, hash: u64, slot: impl Fn(usize) -> usize, matches: impl Fn(usize) -> bool) -> Option<usize> {
+	let mut s = hash as usize & (slots - 1);
+	for _ in 0..slots {
+		let entry = slot(s);
+		if entry == 0 {
+			return None;
+		}
+		if matches(entry - 1) {
+			return Some(entry - 1);
+		}
+		s = (s + 1) & (slots - 1);
+	}
+	None
+}
@@ -0,0 +1,57 @@
+//! JSON Schema of tables, with schemars: the array of records that the table's `Serialize` writes.
+//! Fields using [`crate::keyed`], [`crate::indexed`] or [`crate::versioned`] have other shapes and need their own schemas.
+
+use core::hash::BuildHasher;
+use alloc::{borrow::Cow, format};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use crate::{MicroRecord, MicroTable};
+
+impl<T: MicroRecord + JsonSchema, H: BuildHasher + Clone> JsonSchema for MicroTable<T, H> {
+	fn inline_schema() -> bool {
+		true
+	}
+
+	fn schema_name() -> Cow<'static, str> {
+		format!("MicroTable_of_{}", T::schema_name()).into()
+	}
+
+	fn schema_id() -> Cow<'static, str> {
+		format!("microtable::MicroTable<{}>", T::schema_id()).into()
+	}
+
+	fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"type": "array",
+			"items": generator.subschema_for::<T>(),
+			"description": "Records of the table, with distinct keys",
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Note;
+	use alloc::string::String;
+	use serde::Serialize;
+
+	#[derive(Serialize, JsonSchema)]
+	struct Notebook {
+		name: String,
+		notes: MicroTable<Note>,
+	}
+
+	#[test]
+	fn schema_matches_serialized() {
+		let schema = schemars::schema_for!(Notebook);
+		let notes = &schema.as_value()["properties"]["notes"];
+		assert_eq!(notes["type"], "array");
+		assert_eq!(notes["items"]["$ref"], "#/$defs/Note");
+		assert_eq!(schema.as_value()["$defs"]["Note"]["required"], serde_json::json!(["id", "tag"]));
+
+		let mut notes = MicroTable::new();
+		notes.insert(Note { id: 1, tag: "cat".into() }).unwrap();
+		let notebook = serde_json::to_value(Notebook { name: "work".into(), notes }).unwrap();
+		assert!(notebook["notes"].is_array() && notebook["notes"][0]["tag"] == "cat");
+	}
+}
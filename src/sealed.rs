@@ -0,0 +1,211 @@
+//! Encrypted [binary snapshots](crate::binary), so that the records are never on disk in plaintext. The key is the
+//! caller's: 32 bytes, from a key management system or a key derivation function.
+//!
+//! Layout: the magic bytes, the format version (`u32`, little-endian) and a random 19-byte nonce, then the snapshot,
+//! encrypted with XChaCha20-Poly1305 in the STREAM construction: chunks of 64 KiB, each with its own tag and the header
+//! as associated data, the last one marked as last. A wrong key, a changed byte, or chunks that were cut, dropped
+//! or reordered fail the load.
+
+use core::hash::BuildHasher;
+use alloc::vec::Vec;
+use std::io::{self, Read, Write};
+use chacha20poly1305::{Key, XChaCha20Poly1305, aead::{OsRng, rand_core::RngCore, stream::{DecryptorBE32, EncryptorBE32}}};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{MicroRecord, MicroTable};
+
+const MAGIC: [u8; 8] = *b"MTSEAL\0\0";
+/// Version of the layout, not of the records.
+const VERSION: u32 = 1;
+const NONCE: usize = 19;
+const HEADER: usize = MAGIC.len() + 4 + NONCE;
+const CHUNK: usize = 64 * 1024;
+const TAG: usize = 16;
+
+fn undecryptable() -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, "snapshot doesn't decrypt: wrong key, or damaged file")
+}
+
+/// Writer that encrypts whole chunks as they fill up, and the rest in [`Seal::finish`].
+struct Seal<W> {
+	inner: W,
+	header: [u8; HEADER],
+	stream: Option<EncryptorBE32<XChaCha20Poly1305>>,
+	buf: Vec<u8>,
+}
+
+impl<W: Write> Seal<W> {
+	fn new(mut inner: W, key: &[u8; 32]) -> io::Result<Self> {
+		let mut header = [0; HEADER];
+		header[..MAGIC.len()].copy_from_slice(&MAGIC);
+		header[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&VERSION.to_le_bytes());
+		let nonce = &mut header[MAGIC.len() + 4..];
+		OsRng.try_fill_bytes(nonce).map_err(|_| io::Error::other("no random nonce from the OS"))?;
+		let stream = EncryptorBE32::new(Key::from_slice(key), (&*nonce).into());
+		inner.write_all(&header)?;
+		Ok(Self { inner, header, stream: Some(stream), buf: Vec::with_capacity(CHUNK + TAG) })
+	}
+
+	fn finish(mut self) -> io::Result<()> {
+		let stream = self.stream.take().expect("finished once");
+		stream.encrypt_last_in_place(&self.header, &mut self.buf).map_err(|_| io::Error::other("encryption failed"))?;
+		self.inner.write_all(&self.buf)?;
+		self.inner.flush()
+	}
+}
+
+impl<W: Write> Write for Seal<W> {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+		self.buf.extend_from_slice(bytes);
+		// a full chunk is written once more bytes come, since the last chunk must be marked as last
+		while self.buf.len() > CHUNK {
+			let mut chunk: Vec<u8> = self.buf.drain(..CHUNK).collect();
+			let stream = self.stream.as_mut().expect("not finished");
+			stream.encrypt_next_in_place(&self.header, &mut chunk).map_err(|_| io::Error::other("too many chunks"))?;
+			self.inner.write_all(&chunk)?;
+		}
+		Ok(bytes.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// Reader that decrypts a chunk at a time, handing out only bytes that passed their tag.
+struct Open<R> {
+	inner: R,
+	header: [u8; HEADER],
+	stream: Option<DecryptorBE32<XChaCha20Poly1305>>,
+	plain: Vec<u8>,
+	pos: usize,
+	/// Encrypted bytes read ahead, to tell whether a chunk is the last one.
+	ahead: Vec<u8>,
+}
+
+impl<R: Read> Open<R> {
+	fn new(mut inner: R, key: &[u8; 32]) -> io::Result<Self> {
+		let mut header = [0; HEADER];
+		inner.read_exact(&mut header)?;
+		if header[..MAGIC.len()] != MAGIC {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "not an encrypted microtable snapshot"));
+		}
+		if header[MAGIC.len()..MAGIC.len() + 4] != VERSION.to_le_bytes() {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported encrypted snapshot version"));
+		}
+		let stream = DecryptorBE32::new(Key::from_slice(key), header[MAGIC.len() + 4..].into());
+		Ok(Self { inner, header, stream: Some(stream), plain: Vec::new(), pos: 0, ahead: Vec::with_capacity(CHUNK + TAG + 1) })
+	}
+
+	/// Decrypts the next chunk into `plain`. Returns `false` after the last one.
+	fn next_chunk(&mut self) -> io::Result<bool> {
+		if self.stream.is_none() {
+			return Ok(false);
+		}
+		let want = (CHUNK + TAG + 1 - self.ahead.len()) as u64;
+		(&mut self.inner).take(want).read_to_end(&mut self.ahead)?;
+		self.pos = 0;
+		if self.ahead.len() > CHUNK + TAG {
+			self.plain.clear();
+			self.plain.extend(self.ahead.drain(..CHUNK + TAG));
+			let stream = self.stream.as_mut().expect("checked above");
+			stream.decrypt_next_in_place(&self.header, &mut self.plain).map_err(|_| undecryptable())?;
+		} else {
+			self.plain = core::mem::take(&mut self.ahead);
+			let stream = self.stream.take().expect("checked above");
+			stream.decrypt_last_in_place(&self.header, &mut self.plain).map_err(|_| undecryptable())?;
+		}
+		Ok(true)
+	}
+
+	/// Checks that the rest of the file is the end of the stream.
+	fn finish(mut self) -> io::Result<()> {
+		while self.next_chunk()? {
+			if !self.plain.is_empty() {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, "data after the snapshot"));
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<R: Read> Read for Open<R> {
+	fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+		while self.pos == self.plain.len() {
+			if !self.next_chunk()? {
+				return Ok(0);
+			}
+		}
+		let n = out.len().min(self.plain.len() - self.pos);
+		out[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+		self.pos += n;
+		Ok(n)
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Writes a [binary snapshot](MicroTable::save_snapshot) encrypted with `key`, see the [layout](crate::sealed).
+	pub fn save_snapshot_encrypted(&self, writer: impl Write, key: &[u8; 32]) -> io::Result<()>
+	where T: Serialize {
+		let mut seal = Seal::new(writer, key)?;
+		self.save_snapshot(&mut seal)?;
+		seal.finish()
+	}
+
+	/// Reads a snapshot written by [`MicroTable::save_snapshot_encrypted`] with the same key.
+	pub fn load_snapshot_encrypted(reader: impl Read, key: &[u8; 32]) -> io::Result<Self>
+	where T: DeserializeOwned, H: Default {
+		let mut open = Open::new(reader, key)?;
+		let table = Self::load_snapshot(&mut open)?;
+		open.finish()?;
+		Ok(table)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::{format, string::String};
+	use serde::Deserialize;
+
+	#[derive(Debug, PartialEq, Serialize, Deserialize)]
+	struct Patient {
+		id: u32,
+		name: String,
+		ward: u8,
+	}
+
+	impl MicroRecord for Patient {
+		type Key = u32;
+		type Category = u8;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.ward]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn encrypted_snapshot() {
+		let key = [7; 32];
+		// a few chunks, so the stream has more than the last one
+		let patients: MicroTable<Patient> = (0..20_000).map(|id| Patient { id, name: format!("patient {id}"), ward: (id % 5) as u8 }).collect();
+		let mut bytes = vec![];
+		patients.save_snapshot_encrypted(&mut bytes, &key).unwrap();
+		assert!(bytes.len() > 2 * CHUNK);
+		assert!(!bytes.windows(11).any(|w| w == b"patient 123"), "no plaintext");
+		let back = MicroTable::<Patient>::load_snapshot_encrypted(&bytes[..], &key).unwrap();
+		assert_eq!((back.len(), back.find(&3).len()), (20_000, 4_000));
+		assert_eq!(back.get(&123), patients.get(&123));
+
+		let load = |bytes: &[u8], key| MicroTable::<Patient>::load_snapshot_encrypted(bytes, key).unwrap_err().kind();
+		assert_eq!(load(&bytes, &[8; 32]), io::ErrorKind::InvalidData);
+		let mut damaged = bytes.clone();
+		damaged[HEADER + 100] ^= 1;
+		assert_eq!(load(&damaged, &key), io::ErrorKind::InvalidData);
+		assert_eq!(load(&bytes[..HEADER + CHUNK + TAG], &key), io::ErrorKind::InvalidData);
+		let mut nonce = bytes.clone();
+		nonce[HEADER - 1] ^= 1;
+		assert_eq!(load(&nonce, &key), io::ErrorKind::InvalidData);
+	}
+}
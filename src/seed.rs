@@ -0,0 +1,136 @@
+//! Loading tables whose records may repeat keys, e.g. concatenated or hand-edited snapshots.
+//! The plain `Deserialize` impl fails on a repeated key; [`TableSeed`] can skip it or keep the last record instead:
+//!
+//! ```
+//! # use microtable::{MicroTable, MicroRecord, OnDuplicate, TableSeed};
+//! # use serde::{Deserialize, de::DeserializeSeed};
+//! # #[derive(Deserialize)]
+//! # struct Edge { id: u32, v1: u32 }
+//! # impl MicroRecord for Edge {
+//! #     type Key = u32;
+//! #     type Category = u32;
+//! #     fn categories(&self) -> impl IntoIterator<Item = u32> { [self.v1] }
+//! #     fn key(&self) -> u32 { self.id }
+//! # }
+//! let json = r#"[{"id": 1, "v1": 2}, {"id": 1, "v1": 3}]"#;
+//! let mut de = serde_json::Deserializer::from_str(json);
+//! let edges: MicroTable<Edge> = TableSeed::new(OnDuplicate::LastWins).deserialize(&mut de).unwrap();
+//! assert_eq!(edges.get(&1).unwrap().v1, 3);
+//! ```
+
+use core::{hash::BuildHasher, marker::PhantomData};
+use serde::{Deserialize, Deserializer, de::{DeserializeSeed, Error, SeqAccess, Visitor}};
+use crate::{IndexPresize, MicroRecord, MicroTable};
+
+/// What loading does with a record whose key an earlier record has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDuplicate {
+	/// Fail with a deserialization error. The default, and what `Deserialize` does.
+	#[default]
+	Error,
+	/// Keep the first record, drop the later ones.
+	Skip,
+	/// Keep the last record.
+	LastWins,
+}
+
+/// Deserializes a table in the default format (a sequence of records), handling repeated keys by the policy.
+pub struct TableSeed<T, H> {
+	on_duplicate: OnDuplicate,
+	hasher: H,
+	record: PhantomData<fn() -> T>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> TableSeed<T, H> {
+	pub fn new(on_duplicate: OnDuplicate) -> Self {
+		Self::with_hasher(on_duplicate, H::default())
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> TableSeed<T, H> {
+	pub fn with_hasher(on_duplicate: OnDuplicate, hasher: H) -> Self {
+		Self { on_duplicate, hasher, record: PhantomData }
+	}
+}
+
+impl<'de, T: MicroRecord + Deserialize<'de>, H: BuildHasher + Clone> DeserializeSeed<'de> for TableSeed<T, H> {
+	type Value = MicroTable<T, H>;
+
+	fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+		deserializer.deserialize_seq(self)
+	}
+}
+
+/// Records more than this aren't reserved up front, so a corrupt length can't make loading allocate it all.
+pub(crate) const MAX_PRESIZE: usize = 1 << 20;
+
+/// Inserts the records as they're decoded, so the whole list is never in memory next to the table.
+impl<'de, T: MicroRecord + Deserialize<'de>, H: BuildHasher + Clone> Visitor<'de> for TableSeed<T, H> {
+	type Value = MicroTable<T, H>;
+
+	fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		f.write_str("a sequence of records")
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+		let expected = seq.size_hint().unwrap_or(0).min(MAX_PRESIZE);
+		let mut t = MicroTable::with_capacity_and_hasher(expected, 0, self.hasher);
+		let mut presize = IndexPresize::new(&t, expected);
+		for loaded in 0.. {
+			presize.reserve(&mut t, loaded);
+			let Some(val) = seq.next_element::<T>()? else { break };
+			add(&mut t, val, self.on_duplicate)?;
+		}
+		Ok(t)
+	}
+}
+
+/// Inserts a loaded record, handling a repeated key by the policy.
+pub(crate) fn add<T: MicroRecord, H: BuildHasher + Clone, E: Error>(t: &mut MicroTable<T, H>, val: T, on_duplicate: OnDuplicate) -> Result<(), E> {
+	let key = val.key();
+	if t.contains_key(&key) {
+		match on_duplicate {
+			OnDuplicate::Error => return Err(E::custom("duplicate key in table data")),
+			OnDuplicate::Skip => return Ok(()),
+			OnDuplicate::LastWins => { t.remove(&key); },
+		}
+	}
+	t.insert_keyed(key, val).map_err(|_| E::custom("record rejected by the table"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+
+	#[derive(Deserialize)]
+	struct Line {
+		id: u32,
+		text: String,
+	}
+
+	impl MicroRecord for Line {
+		type Key = u32;
+		type Category = usize;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.text.len()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn duplicate_policies() {
+		let json = r#"[{"id": 1, "text": "a"}, {"id": 2, "text": "bb"}, {"id": 1, "text": "ccc"}]"#;
+		let load = |policy| TableSeed::<Line, crate::RandomState>::new(policy).deserialize(&mut serde_json::Deserializer::from_str(json));
+		assert!(load(OnDuplicate::Error).is_err());
+		assert!(serde_json::from_str::<MicroTable<Line>>(json).is_err());
+		let skipped = load(OnDuplicate::Skip).unwrap();
+		assert_eq!((skipped.len(), skipped.get(&1).unwrap().text.as_str()), (2, "a"));
+		let last = load(OnDuplicate::LastWins).unwrap();
+		assert_eq!(last.get(&1).unwrap().text, "ccc");
+		assert!(last.find(&1).is_empty() && last.find(&3).len() == 1);
+		assert!(serde_json::from_str::<MicroTable<Line>>(r#"{"id": 1}"#).is_err());
+	}
+}
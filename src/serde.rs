@@ -0,0 +1,227 @@
+//! `#[serde(with = ...)]` modules for tables inside your own structs, choosing the format and what loading does
+//! with a repeated key, without wrapping the table in a newtype:
+//!
+//! | module | format | repeated key |
+//! |---|---|---|
+//! | [`as_seq`] | list of records, like `Serialize` | error |
+//! | [`as_seq::skip_duplicates`] | list of records | first record kept |
+//! | [`as_seq::last_wins`] | list of records | last record kept |
+//! | [`as_map`] | map of keys to records, like [`crate::keyed`] | error |
+//! | [`as_map::skip_duplicates`] | map of keys to records | first record kept |
+//! | [`as_map::last_wins`] | map of keys to records | last record kept |
+//!
+//! The plain formats write the records in the table's order, which depends on the history of inserts and removals.
+//! For output that is the same for equal tables, e.g. snapshots kept in version control, `as_seq::sorted` and
+//! `as_map::sorted` write them by key, and `as_seq::stable` and `as_map::stable` by a fixed hash of the key for keys
+//! that aren't `Ord`. The hash is the same from run to run, but may differ between platforms (e.g. for `usize` keys).
+//!
+//! ```
+//! # use microtable::{MicroTable, MicroRecord};
+//! # use serde::{Serialize, Deserialize};
+//! # #[derive(Serialize, Deserialize)]
+//! # struct Job { id: u32, queue: u8 }
+//! # impl MicroRecord for Job {
+//! #     type Key = u32;
+//! #     type Category = u8;
+//! #     fn categories(&self) -> impl IntoIterator<Item = u8> { [self.queue] }
+//! #     fn key(&self) -> u32 { self.id }
+//! # }
+//! #[derive(Serialize, Deserialize)]
+//! struct State {
+//!     #[serde(with = "microtable::serde::as_map::last_wins")]
+//!     jobs: MicroTable<Job>,
+//! }
+//! ```
+
+use core::hash::{BuildHasher, Hash, Hasher};
+use alloc::vec::Vec;
+use crate::{MicroRecord, MicroTable};
+
+/// Records ordered by key.
+fn by_key<T: MicroRecord, H: BuildHasher + Clone>(table: &MicroTable<T, H>) -> Vec<(&T::Key, &T)>
+where T::Key: Ord {
+	let mut records: Vec<_> = table.iter().collect();
+	records.sort_unstable_by(|a, b| a.0.cmp(b.0));
+	records
+}
+
+/// Records ordered by a fixed hash of the key. Keys with one hash (rare) keep the table's order.
+fn by_hash<T: MicroRecord, H: BuildHasher + Clone>(table: &MicroTable<T, H>) -> Vec<(&T::Key, &T)> {
+	let mut records: Vec<_> = table.iter().collect();
+	records.sort_by_cached_key(|(key, _)| {
+		let mut h = crate::Fnv::default();
+		key.hash(&mut h);
+		h.finish()
+	});
+	records
+}
+
+/// The table as a list of records.
+pub mod as_seq {
+	use core::hash::BuildHasher;
+	use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeSeed};
+	use crate::{MicroRecord, MicroTable, OnDuplicate, TableSeed};
+
+	pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+	where T: MicroRecord + Serialize, H: BuildHasher + Clone, S: Serializer {
+		table.serialize(serializer)
+	}
+
+	/// Fails on a repeated key.
+	pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+	where T: MicroRecord + Deserialize<'de>, H: BuildHasher + Clone + Default, D: Deserializer<'de> {
+		TableSeed::new(OnDuplicate::Error).deserialize(deserializer)
+	}
+
+	/// Keeps the first record of a repeated key.
+	pub mod skip_duplicates {
+		use super::*;
+		pub use super::serialize;
+
+		pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+		where T: MicroRecord + Deserialize<'de>, H: BuildHasher + Clone + Default, D: Deserializer<'de> {
+			TableSeed::new(OnDuplicate::Skip).deserialize(deserializer)
+		}
+	}
+
+	/// Keeps the last record of a repeated key.
+	pub mod last_wins {
+		use super::*;
+		pub use super::serialize;
+
+		pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+		where T: MicroRecord + Deserialize<'de>, H: BuildHasher + Clone + Default, D: Deserializer<'de> {
+			TableSeed::new(OnDuplicate::LastWins).deserialize(deserializer)
+		}
+	}
+
+	/// Records sorted by key. Loads like [`as_seq`](self).
+	pub mod sorted {
+		use super::*;
+		pub use super::deserialize;
+
+		pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+		where T: MicroRecord + Serialize, T::Key: Ord, H: BuildHasher + Clone, S: Serializer {
+			serializer.collect_seq(crate::serde::by_key(table).into_iter().map(|(_, val)| val))
+		}
+	}
+
+	/// Records in an order fixed by their keys' hashes. Loads like [`as_seq`](self).
+	pub mod stable {
+		use super::*;
+		pub use super::deserialize;
+
+		pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+		where T: MicroRecord + Serialize, H: BuildHasher + Clone, S: Serializer {
+			serializer.collect_seq(crate::serde::by_hash(table).into_iter().map(|(_, val)| val))
+		}
+	}
+}
+
+/// The table as a map of keys to records. Every map key must be the key of its record.
+pub mod as_map {
+	use core::hash::BuildHasher;
+	use serde::{Deserialize, Deserializer};
+	use crate::{MicroRecord, MicroTable, OnDuplicate, keyed::deserialize_with};
+	pub use crate::keyed::{deserialize, serialize};
+
+	/// Keeps the first record of a repeated key.
+	pub mod skip_duplicates {
+		use super::*;
+		pub use super::serialize;
+
+		pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+		where T: MicroRecord + Deserialize<'de>, T::Key: Deserialize<'de>, H: BuildHasher + Clone + Default, D: Deserializer<'de> {
+			deserialize_with(deserializer, OnDuplicate::Skip)
+		}
+	}
+
+	/// Keeps the last record of a repeated key.
+	pub mod last_wins {
+		use super::*;
+		pub use super::serialize;
+
+		pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+		where T: MicroRecord + Deserialize<'de>, T::Key: Deserialize<'de>, H: BuildHasher + Clone + Default, D: Deserializer<'de> {
+			deserialize_with(deserializer, OnDuplicate::LastWins)
+		}
+	}
+
+	/// Map sorted by key. Loads like [`as_map`](self).
+	pub mod sorted {
+		use super::*;
+		use serde::{Serialize, Serializer};
+		pub use super::deserialize;
+
+		pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+		where T: MicroRecord + Serialize, T::Key: Ord + Serialize, H: BuildHasher + Clone, S: Serializer {
+			serializer.collect_map(crate::serde::by_key(table))
+		}
+	}
+
+	/// Map in an order fixed by the keys' hashes. Loads like [`as_map`](self).
+	pub mod stable {
+		use super::*;
+		use serde::{Serialize, Serializer};
+		pub use super::deserialize;
+
+		pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+		where T: MicroRecord + Serialize, T::Key: Serialize, H: BuildHasher + Clone, S: Serializer {
+			serializer.collect_map(crate::serde::by_hash(table))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde::{Deserialize, Serialize};
+	use crate::{MicroTable, fixtures::Account};
+
+	#[derive(Serialize, Deserialize)]
+	struct State {
+		#[serde(with = "crate::serde::as_seq::skip_duplicates")]
+		pending: MicroTable<Account>,
+		#[serde(with = "crate::serde::as_map::last_wins")]
+		running: MicroTable<Account>,
+		#[serde(with = "crate::serde::as_map")]
+		done: MicroTable<Account>,
+	}
+
+	#[test]
+	fn field_formats() {
+		let json = r#"{
+			"pending": [{"id": 1, "owner": 0, "balance": 0}, {"id": 1, "owner": 5, "balance": 0}],
+			"running": {"2": {"id": 2, "owner": 0, "balance": 0}, "2": {"id": 2, "owner": 5, "balance": 0}},
+			"done": {"3": {"id": 3, "owner": 1, "balance": 0}}
+		}"#;
+		let state: State = serde_json::from_str(json).unwrap();
+		assert_eq!((state.pending.get(&1).unwrap().owner, state.running.get(&2).unwrap().owner), (0, 5));
+		let out = serde_json::to_string(&state).unwrap();
+		assert_eq!(out, r#"{"pending":[{"id":1,"owner":0,"balance":0}],"running":{"2":{"id":2,"owner":5,"balance":0}},"done":{"3":{"id":3,"owner":1,"balance":0}}}"#);
+		assert!(serde_json::from_str::<State>(&json.replace(r#""3": {"id": 3, "owner": 1, "balance": 0}"#, r#""3": {"id": 3, "owner": 1, "balance": 0}, "3": {"id": 3, "owner": 1, "balance": 0}"#)).is_err());
+	}
+
+	#[derive(Serialize, Deserialize)]
+	struct Snapshot {
+		#[serde(with = "crate::serde::as_seq::sorted")]
+		by_key: MicroTable<Account>,
+		#[serde(with = "crate::serde::as_map::stable")]
+		by_hash: MicroTable<Account>,
+	}
+
+	#[test]
+	fn deterministic_order() {
+		let accounts = |ids: &mut dyn Iterator<Item = u32>| -> MicroTable<Account> { ids.map(|id| Account { id, owner: id % 3, balance: 0 }).collect() };
+		let forward = Snapshot { by_key: accounts(&mut (0..50)), by_hash: accounts(&mut (0..50)) };
+		let mut churned = accounts(&mut (0..80).rev());
+		for id in 50..80 {
+			churned.remove(&id);
+		}
+		let backward = Snapshot { by_key: churned.clone(), by_hash: churned };
+		let json = serde_json::to_string(&forward).unwrap();
+		assert_eq!(json, serde_json::to_string(&backward).unwrap());
+		assert!(json.starts_with(r#"{"by_key":[{"id":0,"owner":0,"balance":0},{"id":1,"owner":1,"balance":0},"#));
+		let back: Snapshot = serde_json::from_str(&json).unwrap();
+		assert_eq!((back.by_key.len(), back.by_hash.find(&2).len()), (50, 16));
+	}
+}
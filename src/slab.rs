@@ -0,0 +1,335 @@
+use core::{hash::{BuildHasher, Hash}, marker::PhantomData};
+use alloc::{vec, vec::Vec};
+use crate::{columns::{ColumnId, Columns}, map::{Entry, Map, VacantEntry, map_swap_remove}};
+#[cfg(feature="imbl")]
+use crate::map::NoCapacity;
+#[cfg(feature="imbl")]
+use crate::chunks::{self, Chunks};
+
+/// Vector of the slots. With the `imbl` feature it's in chunks that clones share, so a clone doesn't copy the records.
+#[cfg(not(feature="imbl"))]
+type Slots<E> = Vec<E>;
+#[cfg(feature="imbl")]
+type Slots<E> = Chunks<E>;
+#[cfg(not(feature="imbl"))]
+type SlotIter<'a, E> = core::slice::Iter<'a, E>;
+#[cfg(feature="imbl")]
+type SlotIter<'a, E> = chunks::Iter<'a, E>;
+
+/// Record storage: records sit in a contiguous vector, so iterating over all of them doesn't chase pointers,
+/// and a map finds their slots by key. Removal leaves an empty slot that the next insert reuses.
+/// With the `indexmap` feature slots are not reused, so records are iterated in insertion order;
+/// the empty slots are dropped once they outnumber the records (see [`Slab::wants_compaction`]).
+/// Slot numbers are the records' internal ids in the indexes, so compaction returns how they changed.
+/// With the `imbl` feature the slots are kept in chunks shared between clones, which copy only the chunks they change.
+/// Each slot counts its reuses (generation), for handles that must not match a later occupant.
+/// Columns copy fields of the records into arrays by slot, for scans that need only these fields.
+#[derive(Debug, Clone)]
+pub(crate) struct Slab<K, T, H> {
+	slots: Slots<Option<(K, T)>>,
+	free: Vec<usize>,
+	keys: Map<K, usize, H>,
+	generations: Slots<u32>,
+	columns: Columns<T>,
+}
+
+/// Iterator over the filled slots, that knows how many are left, so serializers and `collect` get the exact length.
+struct Occupied<'a, K, T> {
+	slots: SlotIter<'a, Option<(K, T)>>,
+	left: usize,
+}
+
+impl<'a, K, T> Iterator for Occupied<'a, K, T> {
+	type Item = &'a (K, T);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let next = self.slots.find_map(Option::as_ref)?;
+		self.left -= 1;
+		Some(next)
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(self.left, Some(self.left))
+	}
+}
+
+impl<K, T> ExactSizeIterator for Occupied<'_, K, T> {}
+
+impl<K: Hash + Eq + Clone, T, H: BuildHasher + Clone> Slab<K, T, H> {
+	pub(crate) fn with_hasher(hasher: H) -> Self {
+		Self { slots: Slots::new(), free: vec![], keys: Map::with_hasher(hasher), generations: Slots::new(), columns: Columns::new() }
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.keys.len()
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.keys.is_empty()
+	}
+
+	pub(crate) fn capacity(&self) -> usize {
+		self.keys.capacity().min(self.slots.capacity())
+	}
+
+	pub(crate) fn hasher(&self) -> &H {
+		self.keys.hasher()
+	}
+
+	pub(crate) fn contains_key(&self, key: &K) -> bool {
+		self.keys.contains_key(key)
+	}
+
+	pub(crate) fn get(&self, key: &K) -> Option<&T> {
+		let slot = *self.keys.get(key)?;
+		self.slots[slot].as_ref().map(|(_, v)| v)
+	}
+
+	/// Slot and generation that the next new key will get.
+	pub(crate) fn next_slot(&self) -> (usize, u32) {
+		#[cfg(not(feature="indexmap"))]
+		let slot = self.free.last().copied().unwrap_or(self.slots.len());
+		#[cfg(feature="indexmap")]
+		let slot = self.slots.len();
+		(slot, if slot < self.generations.len() { self.generations[slot] } else { 0 })
+	}
+
+	/// Place for a new key, if it's not taken. The key is hashed once for the check and the insertion.
+	pub(crate) fn vacant(&mut self, key: K) -> Option<Vacant<'_, K, T, H>> {
+		self.entry(key).err()
+	}
+
+	/// Slot of the key, or the place for it if it's not stored, with one lookup.
+	pub(crate) fn entry(&mut self, key: K) -> Result<usize, Vacant<'_, K, T, H>> {
+		match self.keys.entry(key) {
+			Entry::Occupied(entry) => Ok(*entry.get()),
+			Entry::Vacant(entry) => Err(Vacant { entry, slots: &mut self.slots, free: &mut self.free, columns: &mut self.columns, hasher: PhantomData }),
+		}
+	}
+
+	/// Slot of the key, to access it again without hashing.
+	pub(crate) fn slot_of(&self, key: &K) -> Option<usize> {
+		self.keys.get(key).copied()
+	}
+
+	/// Key and record in the slot, from [`Slab::slot_of`] or [`Vacant::insert`].
+	pub(crate) fn at(&self, slot: usize) -> (&K, &T) {
+		let (k, v) = self.slots[slot].as_ref().expect("empty slot");
+		(k, v)
+	}
+
+	/// Replaces the record in the slot, returning the old one.
+	pub(crate) fn replace_at(&mut self, slot: usize, val: T) -> T {
+		self.columns.set(slot, &val);
+		let (_, v) = self.slots[slot].as_mut().expect("empty slot");
+		core::mem::replace(v, val)
+	}
+
+	/// Removes the record, returning it with the slot it was in.
+	pub(crate) fn remove(&mut self, key: &K) -> Option<(usize, T)> {
+		let slot = map_swap_remove(&mut self.keys, key)?;
+		self.free.push(slot);
+		self.bump_generation(slot);
+		let (_, val) = self.slots[slot].take().expect("slot of a stored key");
+		self.columns.unset(slot);
+		Some((slot, val))
+	}
+
+	/// Whether empty slots outnumber the records. Only with `indexmap`, since otherwise inserts reuse them.
+	pub(crate) fn wants_compaction(&self) -> bool {
+		cfg!(feature="indexmap") && self.free.len() > self.keys.len().max(16)
+	}
+
+	/// Moves the records together, keeping their order, and drops the empty slots.
+	/// Returns the new slot of every old one (`u32::MAX` for the empty ones).
+	pub(crate) fn compact_slots(&mut self) -> Vec<u32> {
+		// records change slots, so every slot starts a new generation
+		for slot in 0..self.slots.len() {
+			self.bump_generation(slot);
+		}
+		if !self.columns.is_empty() {
+			let keep: Vec<bool> = self.slots.iter().map(Option::is_some).collect();
+			self.columns.retain(&keep);
+		}
+		let mut remap = Vec::with_capacity(self.slots.len());
+		let mut next = 0;
+		for s in self.slots.iter() {
+			remap.push(if s.is_some() { next += 1; next - 1 } else { u32::MAX });
+		}
+		self.slots.retain(|s| s.is_some());
+		self.free.clear();
+		for (i, (k, _)) in self.slots.iter().flatten().enumerate() {
+			*self.keys.get_mut(k).unwrap() = i; // every stored key is in the map
+		}
+		remap
+	}
+
+	/// The key map and the records, in slot order. There must be no empty slots (see [`Slab::shrink_to_fit`]).
+	pub(crate) fn into_dense(self) -> (Map<K, usize, H>, Vec<T>) {
+		debug_assert!(self.free.is_empty());
+		(self.keys, self.slots.into_iter().flatten().map(|(_, v)| v).collect())
+	}
+
+	/// The records with their keys, in slot order.
+	pub(crate) fn into_pairs(self) -> impl Iterator<Item = (K, T)> {
+		self.slots.into_iter().flatten()
+	}
+
+	pub(crate) fn clear(&mut self) {
+		for slot in 0..self.slots.len() {
+			self.bump_generation(slot);
+		}
+		self.slots.clear();
+		self.free.clear();
+		self.keys.clear();
+		self.columns.clear();
+	}
+
+	fn bump_generation(&mut self, slot: usize) {
+		while self.generations.len() <= slot {
+			self.generations.push(0);
+		}
+		self.generations[slot] = self.generations[slot].wrapping_add(1);
+	}
+
+	pub(crate) fn reserve(&mut self, additional: usize) {
+		self.slots.reserve(additional.saturating_sub(self.free.len()));
+		self.keys.reserve(additional);
+	}
+
+	/// Moves the records together, dropping the empty slots, and frees the spare memory.
+	/// Returns the new slots if records moved, as [`Slab::compact_slots`] does.
+	pub(crate) fn shrink_to_fit(&mut self) -> Option<Vec<u32>> {
+		let remap = (!self.free.is_empty()).then(|| self.compact_slots());
+		self.slots.shrink_to_fit();
+		self.free.shrink_to_fit();
+		self.keys.shrink_to_fit();
+		self.columns.shrink_to_fit();
+		remap
+	}
+
+	pub(crate) fn add_column<U: Clone + Default + Send + Sync + 'static>(&mut self, field: fn(&T) -> U) -> ColumnId<U>
+	where T: 'static {
+		self.columns.push(field, self.slots.iter().map(|s| s.as_ref().map(|(_, v)| v)))
+	}
+
+	pub(crate) fn column<U: 'static>(&self, id: ColumnId<U>) -> &[U]
+	where T: 'static {
+		self.columns.get(id)
+	}
+
+	/// Records with their slots.
+	pub(crate) fn iter_slots(&self) -> impl Iterator<Item = (usize, &K, &T)> {
+		self.slots.iter().enumerate().filter_map(|(slot, s)| s.as_ref().map(|(k, v)| (slot, k, v)))
+	}
+
+	pub(crate) fn iter(&self) -> impl ExactSizeIterator<Item = (&K, &T)> {
+		self.occupied().map(|(k, v)| (k, v))
+	}
+
+	pub(crate) fn keys(&self) -> impl ExactSizeIterator<Item = &K> {
+		self.occupied().map(|(k, _)| k)
+	}
+
+	pub(crate) fn values(&self) -> impl ExactSizeIterator<Item = &T> {
+		self.occupied().map(|(_, v)| v)
+	}
+
+	fn occupied(&self) -> Occupied<'_, K, T> {
+		Occupied { slots: self.slots.iter(), left: self.len() }
+	}
+
+	/// Whether the slot is still shared with the clone `other`, rather than copied.
+	#[cfg(all(test, feature="imbl"))]
+	pub(crate) fn shares_slot(&self, other: &Self, slot: usize) -> bool {
+		self.slots.shares(&other.slots, slot)
+	}
+
+	/// Estimate of the allocated bytes, by capacity.
+	pub(crate) fn bytes(&self) -> usize {
+		#[cfg(not(feature="imbl"))]
+		let slots = self.slots.capacity() * core::mem::size_of::<Option<(K, T)>>() + self.generations.capacity() * core::mem::size_of::<u32>();
+		#[cfg(feature="imbl")]
+		let slots = self.slots.bytes() + self.generations.bytes();
+		slots
+			+ self.free.capacity() * core::mem::size_of::<usize>()
+			+ crate::map_bytes(&self.keys)
+			+ self.columns.bytes()
+	}
+}
+
+pub(crate) struct Vacant<'a, K: Hash + Eq + Clone, T, H: BuildHasher + Clone> {
+	#[cfg(all(any(feature="std", feature="indexmap"), not(feature="imbl")))]
+	entry: VacantEntry<'a, K, usize>,
+	#[cfg(any(not(any(feature="std", feature="indexmap")), feature="imbl"))]
+	entry: VacantEntry<'a, K, usize, H>,
+	slots: &'a mut Slots<Option<(K, T)>>,
+	#[cfg_attr(feature="indexmap", allow(dead_code))] // slots aren't reused in insertion order
+	free: &'a mut Vec<usize>,
+	columns: &'a mut Columns<T>,
+	hasher: PhantomData<H>, // only hashbrown's and imbl's entries name the hasher
+}
+
+impl<K: Hash + Eq + Clone, T, H: BuildHasher + Clone> Vacant<'_, K, T, H> {
+	/// Stores the record and returns its slot.
+	pub(crate) fn insert(self, val: T) -> usize {
+		let key = self.entry.key().clone();
+		#[cfg(not(feature="indexmap"))]
+		let reused = self.free.pop();
+		#[cfg(feature="indexmap")]
+		let reused = None;
+		let slot = match reused {
+			Some(slot) => {
+				self.slots[slot] = Some((key, val));
+				slot
+			},
+			None => {
+				self.slots.push(Some((key, val)));
+				self.slots.len() - 1
+			}
+		};
+		self.columns.set(slot, &self.slots[slot].as_ref().expect("just stored").1);
+		self.entry.insert(slot);
+		slot
+	}
+}
+
+impl<K: Hash + Eq + Clone, T, H: BuildHasher + Clone> core::ops::Index<&K> for Slab<K, T, H> {
+	type Output = T;
+
+	fn index(&self, key: &K) -> &T {
+		self.get(key).expect("key not found")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::hash::RandomState;
+
+	#[test]
+	fn reuse_and_shrink() {
+		let mut s: Slab<u32, &str, RandomState> = Slab::with_hasher(RandomState::new());
+		s.vacant(1).unwrap().insert("a");
+		s.vacant(2).unwrap().insert("b");
+		s.vacant(3).unwrap().insert("c");
+		assert!(s.vacant(2).is_none());
+		assert_eq!(s.replace_at(s.slot_of(&2).unwrap(), "B"), "b");
+		assert_eq!(s.remove(&1), Some((0, "a")));
+		s.vacant(4).unwrap().insert("d"); // takes the slot of 1, unless slots are kept in order
+		#[cfg(not(feature="indexmap"))]
+		assert_eq!(s.values().copied().collect::<Vec<_>>(), vec!["d", "B", "c"]);
+		#[cfg(feature="indexmap")]
+		assert_eq!(s.values().copied().collect::<Vec<_>>(), vec!["B", "c", "d"]);
+
+		s.remove(&4);
+		let remap = s.shrink_to_fit();
+		assert_eq!(s.values().copied().collect::<Vec<_>>(), vec!["B", "c"]);
+		#[cfg(feature="indexmap")]
+		assert_eq!(remap, Some(vec![u32::MAX, 0, 1, u32::MAX]));
+		#[cfg(not(feature="indexmap"))]
+		assert_eq!(remap, Some(vec![u32::MAX, 0, 1]));
+		assert_eq!(s.get(&3), Some(&"c"));
+		assert_eq!(s.len(), 2);
+	}
+}
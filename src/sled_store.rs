@@ -0,0 +1,199 @@
+//! Tables mirrored into a [sled](https://docs.rs/sled) tree: every change is written through to the tree, keyed by the
+//! postcard bytes of the record's key, so the table can be reopened from it. Queries run on the table in memory.
+
+use core::{hash::BuildHasher, ops::Deref};
+use alloc::vec::Vec;
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{IndexPresize, KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Error of a [`SledTable`].
+#[derive(Debug)]
+pub enum SledError<K> {
+	/// The table rejected the change, so nothing was written.
+	Table(KeyError<K>),
+	/// The tree failed. After a change, the change is made in memory but may be missing from the tree.
+	Sled(::sled::Error),
+	/// A record or key didn't encode, or a value in the tree didn't decode.
+	Codec(postcard::Error),
+}
+
+impl<K> From<KeyError<K>> for SledError<K> {
+	fn from(e: KeyError<K>) -> Self {
+		Self::Table(e)
+	}
+}
+
+impl<K> From<::sled::Error> for SledError<K> {
+	fn from(e: ::sled::Error) -> Self {
+		Self::Sled(e)
+	}
+}
+
+impl<K> From<postcard::Error> for SledError<K> {
+	fn from(e: postcard::Error) -> Self {
+		Self::Codec(e)
+	}
+}
+
+impl<K: core::fmt::Debug> core::fmt::Display for SledError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Table(e) => e.fmt(f),
+			Self::Sled(e) => write!(f, "sled: {e}"),
+			Self::Codec(e) => write!(f, "record encoding: {e}"),
+		}
+	}
+}
+
+impl<K: core::fmt::Debug> std::error::Error for SledError<K> {}
+
+/// Table that writes its changes through to a sled tree. It reads like the [`MicroTable`] it derefs to,
+/// and changes go through its own methods. The tree should hold only this table's records.
+pub struct SledTable<T: MicroRecord, H = RandomState> {
+	table: MicroTable<T, H>,
+	tree: ::sled::Tree,
+}
+
+impl<T: MicroRecord + Serialize + DeserializeOwned, H: BuildHasher + Clone + Default> SledTable<T, H>
+where T::Key: Serialize {
+	/// Loads the records of the tree (none for a new tree) and indexes them.
+	pub fn open(tree: ::sled::Tree) -> Result<Self, SledError<T::Key>> {
+		let mut table = MicroTable::with_capacity_and_hasher(tree.len(), 0, H::default());
+		let mut presize = IndexPresize::new(&table, tree.len());
+		for (loaded, entry) in tree.iter().enumerate() {
+			presize.reserve(&mut table, loaded);
+			let (_, bytes) = entry?;
+			table.insert(postcard::from_bytes::<T>(&bytes)?)?;
+		}
+		Ok(Self { table, tree })
+	}
+}
+
+impl<T: MicroRecord + Serialize, H: BuildHasher + Clone> SledTable<T, H>
+where T::Key: Serialize {
+	/// The tree the records are written to.
+	pub fn tree(&self) -> &::sled::Tree {
+		&self.tree
+	}
+
+	/// Waits until the changes written so far are on the disk. Sled also flushes by itself every few hundred milliseconds.
+	pub fn flush(&self) -> Result<usize, SledError<T::Key>> {
+		Ok(self.tree.flush()?)
+	}
+
+	fn put(&self, batch: &mut ::sled::Batch, key: &T::Key) -> Result<(), SledError<T::Key>> {
+		let val = self.table.get(key).expect("record is in the table");
+		batch.insert(postcard::to_allocvec(key)?, postcard::to_allocvec(val)?);
+		Ok(())
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), SledError<T::Key>> {
+		let key = val.key();
+		let key_bytes = postcard::to_allocvec(&key)?;
+		let val_bytes = postcard::to_allocvec(&val)?;
+		self.table.insert(val)?;
+		self.tree.insert(key_bytes, val_bytes)?;
+		Ok(())
+	}
+
+	/// [`MicroTable::upsert`], written as one batch.
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), SledError<T::Key>> {
+		let new_key = new_val.key();
+		self.table.upsert(key.clone(), new_val)?;
+		let mut batch = ::sled::Batch::default();
+		batch.remove(postcard::to_allocvec(&key)?);
+		self.put(&mut batch, &new_key)?;
+		Ok(self.tree.apply_batch(batch)?)
+	}
+
+	/// [`MicroTable::update_with`], written as one batch.
+	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), SledError<T::Key>>
+	where T: Clone {
+		let new_key = core::cell::RefCell::new(None);
+		self.table.update_with(old_key.clone(), &|val: &mut T| {
+			cb(val);
+			*new_key.borrow_mut() = Some(val.key());
+		})?;
+		let mut batch = ::sled::Batch::default();
+		batch.remove(postcard::to_allocvec(&old_key)?);
+		self.put(&mut batch, &new_key.into_inner().expect("callback ran"))?;
+		Ok(self.tree.apply_batch(batch)?)
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Result<Option<T>, SledError<T::Key>> {
+		let key_bytes = postcard::to_allocvec(key)?;
+		let Some(val) = self.table.remove(key) else { return Ok(None) };
+		self.tree.remove(key_bytes)?;
+		Ok(Some(val))
+	}
+
+	/// [`MicroTable::remove_cat`], written as one batch.
+	pub fn remove_cat(&mut self, cat: &T::Category) -> Result<Vec<T>, SledError<T::Key>> {
+		let removed = self.table.remove_cat(cat);
+		let mut batch = ::sled::Batch::default();
+		for val in removed.iter() {
+			batch.remove(postcard::to_allocvec(&val.key())?);
+		}
+		self.tree.apply_batch(batch)?;
+		Ok(removed)
+	}
+
+	pub fn clear(&mut self) -> Result<(), SledError<T::Key>> {
+		self.table.clear();
+		Ok(self.tree.clear()?)
+	}
+}
+
+impl<T: MicroRecord, H> Deref for SledTable<T, H> {
+	type Target = MicroTable<T, H>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.table
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::string::String;
+	use serde::Deserialize;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Task {
+		id: u64,
+		queue: String,
+		done: bool,
+	}
+
+	impl MicroRecord for Task {
+		type Key = u64;
+		type Category = String;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.queue.clone()]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn write_through_and_reopen() {
+		let db = ::sled::Config::new().temporary(true).open().unwrap();
+		let mut tasks = SledTable::<Task>::open(db.open_tree("tasks").unwrap()).unwrap();
+		for id in 0..6 {
+			tasks.insert(Task { id, queue: if id < 4 { "mail".into() } else { "build".into() }, done: false }).unwrap();
+		}
+		assert!(matches!(tasks.insert(Task { id: 2, queue: "mail".into(), done: false }), Err(SledError::Table(KeyError::Collision))));
+		tasks.update_with(1, &|t| t.done = true).unwrap();
+		tasks.upsert(3, Task { id: 30, queue: "build".into(), done: false }).unwrap();
+		tasks.remove(&0).unwrap();
+		assert_eq!(tasks.tree().len(), 5);
+		drop(tasks);
+
+		let mut tasks = SledTable::<Task>::open(db.open_tree("tasks").unwrap()).unwrap();
+		assert_eq!((tasks.len(), tasks.find(&"build".into()).len()), (5, 3));
+		assert_eq!(tasks.get(&1).map(|t| t.done), Some(true));
+		assert_eq!(tasks.remove_cat(&"build".into()).unwrap().len(), 3);
+		assert_eq!(SledTable::<Task>::open(db.open_tree("tasks").unwrap()).unwrap().len(), 2);
+	}
+}
@@ -0,0 +1,166 @@
+//! Table for a handful of records, for programs that keep thousands of tiny tables: up to [`INLINE`] records
+//! sit in one vector and lookups scan it, so there are no maps and posting sets to allocate.
+
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, vec::Vec};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Most records a [`SmallTable`] keeps in a vector. The next insert moves them into a [`MicroTable`].
+pub const INLINE: usize = 8;
+
+#[derive(Clone)]
+enum Repr<T: MicroRecord, H> {
+	Inline(Vec<T>, H),
+	// boxed, so that a small table takes a few words
+	Table(Box<MicroTable<T, H>>),
+}
+
+/// Table that scans its records while there are at most [`INLINE`] of them, and becomes an indexed [`MicroTable`]
+/// when it grows past that. It stays indexed if records are removed later, so it doesn't switch back and forth.
+#[derive(Clone)]
+pub struct SmallTable<T: MicroRecord, H = RandomState>(Repr<T, H>);
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for SmallTable<T, H> {
+	fn default() -> Self {
+		Self::with_hasher(H::default())
+	}
+}
+
+impl<T: MicroRecord> SmallTable<T> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> SmallTable<T, H> {
+	/// Empty table, that will use `hasher` once it becomes indexed.
+	pub fn with_hasher(hasher: H) -> Self {
+		Self(Repr::Inline(Vec::new(), hasher))
+	}
+
+	pub fn len(&self) -> usize {
+		match &self.0 {
+			Repr::Inline(vals, _) => vals.len(),
+			Repr::Table(t) => t.len(),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Whether the records are still scanned rather than indexed.
+	pub fn is_inline(&self) -> bool {
+		matches!(self.0, Repr::Inline(..))
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.get(key).is_some()
+	}
+
+	pub fn contains_cat(&self, cat: &T::Category) -> bool {
+		self.find_iter(cat).next().is_some()
+	}
+
+	pub fn get(&self, key: &T::Key) -> Option<&T> {
+		match &self.0 {
+			Repr::Inline(vals, _) => vals.iter().find(|v| &v.key() == key),
+			Repr::Table(t) => t.get(key),
+		}
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		match &mut self.0 {
+			Repr::Inline(vals, _) if vals.len() < INLINE => {
+				if vals.iter().any(|v| v.key() == val.key()) {
+					return Err(KeyError::Collision);
+				}
+				vals.push(val);
+				Ok(())
+			}
+			Repr::Inline(..) => {
+				self.index();
+				self.insert(val)
+			}
+			Repr::Table(t) => t.insert(val),
+		}
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+		match &mut self.0 {
+			Repr::Inline(vals, _) => {
+				let i = vals.iter().position(|v| &v.key() == key)?;
+				Some(vals.remove(i))
+			}
+			Repr::Table(t) => t.remove(key),
+		}
+	}
+
+	pub fn find_iter<'a>(&'a self, cat: &'a T::Category) -> impl Iterator<Item = &'a T> {
+		let (vals, table) = match &self.0 {
+			Repr::Inline(vals, _) => (Some(vals), None),
+			Repr::Table(t) => (None, Some(&**t)),
+		};
+		let scanned = vals.into_iter().flatten().filter(move |v| v.categories().into_iter().any(|c| &c == cat));
+		scanned.chain(table.into_iter().flat_map(move |t| t.find_iter(cat)))
+	}
+
+	pub fn find<'a>(&'a self, cat: &'a T::Category) -> Vec<&'a T> {
+		self.find_iter(cat).collect()
+	}
+
+	pub fn values(&self) -> impl Iterator<Item = &T> {
+		let (vals, table) = match &self.0 {
+			Repr::Inline(vals, _) => (Some(vals), None),
+			Repr::Table(t) => (None, Some(&**t)),
+		};
+		vals.into_iter().flatten().chain(table.into_iter().flat_map(|t| t.values()))
+	}
+
+	/// The records in an indexed table.
+	pub fn into_table(mut self) -> MicroTable<T, H> {
+		self.index();
+		let Repr::Table(t) = self.0 else { unreachable!() };
+		*t
+	}
+
+	/// Moves the records into a [`MicroTable`].
+	fn index(&mut self) {
+		if let Repr::Inline(vals, hasher) = &mut self.0 {
+			let mut t = MicroTable::with_capacity_and_hasher(INLINE * 2, 0, hasher.clone());
+			assert!(t.try_extend(vals.drain(..)).is_ok(), "inline records have distinct keys");
+			self.0 = Repr::Table(Box::new(t));
+		}
+	}
+}
+
+impl<T: MicroRecord + core::fmt::Debug, H: BuildHasher + Clone> core::fmt::Debug for SmallTable<T, H> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_list().entries(self.values()).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Item;
+
+	#[test]
+	fn inline_then_indexed() {
+		let mut parts = SmallTable::new();
+		for id in 0..INLINE as u32 {
+			parts.insert(Item { id, kind: if id % 2 == 0 { "left" } else { "right" } }).unwrap();
+		}
+		assert!(parts.is_inline());
+		assert_eq!(parts.insert(Item { id: 3, kind: "left" }), Err(KeyError::Collision));
+		assert_eq!(parts.find(&"left").len(), INLINE / 2);
+		assert_eq!(parts.remove(&0).map(|p| p.kind), Some("left"));
+		parts.insert(Item { id: 0, kind: "top" }).unwrap();
+		parts.insert(Item { id: 100, kind: "top" }).unwrap();
+		assert!(!parts.is_inline());
+		assert_eq!((parts.len(), parts.find(&"top").len()), (INLINE + 1, 2));
+		assert_eq!(parts.get(&100), Some(&Item { id: 100, kind: "top" }));
+		assert!(parts.contains_cat(&"right") && !parts.contains_key(&50));
+		assert_eq!(parts.into_table().find(&"left").len(), INLINE / 2 - 1);
+	}
+}
@@ -0,0 +1,38 @@
+use core::ops::Deref;
+use alloc::sync::Arc;
+use crate::{MicroRecord, MicroTable, RandomState};
+
+/// Read-only copy of a table as it was at [`MicroTable::copy_snapshot`]. It derefs to the table, so all the queries work on it,
+/// and clones of it are cheap, to hand out to readers while the table keeps changing.
+pub struct TableSnapshot<T: MicroRecord, H = RandomState>(Arc<MicroTable<T, H>>);
+
+impl<T: MicroRecord, H> Deref for TableSnapshot<T, H> {
+	type Target = MicroTable<T, H>;
+
+	fn deref(&self) -> &MicroTable<T, H> {
+		&self.0
+	}
+}
+
+impl<T: MicroRecord, H> Clone for TableSnapshot<T, H> {
+	fn clone(&self) -> Self {
+		Self(Arc::clone(&self.0))
+	}
+}
+
+impl<T: MicroRecord, H> core::fmt::Debug for TableSnapshot<T, H> where MicroTable<T, H>: core::fmt::Debug {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_tuple("TableSnapshot").field(&*self.0).finish()
+	}
+}
+
+impl<T: MicroRecord + Clone, H: core::hash::BuildHasher + Clone> MicroTable<T, H> {
+	/// Copies the table into a read-only view, for readers to query while the table keeps changing. Every record
+	/// is cloned, so it costs about a clone of the table: only the category posting sets are shared, and copied when the
+	/// table changes them. Records in an [`crate::ArcTable`] are cloned as pointers. With the `imbl` feature the maps
+	/// and the records, kept in chunks of 64, are shared too, and the table copies only the chunks and the parts of the
+	/// maps that it changes later; what's copied up front is the list of free slots and the columns.
+	pub fn copy_snapshot(&self) -> TableSnapshot<T, H> {
+		TableSnapshot(Arc::new(self.clone()))
+	}
+}
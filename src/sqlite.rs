@@ -0,0 +1,126 @@
+//! Loading tables from SQLite queries and writing them to SQLite tables, with rusqlite.
+
+use core::hash::BuildHasher;
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use rusqlite::{Connection, Row, types::Value};
+use serde::Serialize;
+use crate::{KeyError, MicroRecord, MicroTable};
+
+/// Error of [`MicroTable::from_sqlite`].
+#[derive(Debug)]
+pub enum SqliteError<K> {
+	Sql(rusqlite::Error),
+	/// The table rejected the record of a row (counting from 0), e.g. for a repeated key.
+	Rejected { row: usize, error: KeyError<K> },
+}
+
+impl<K> From<rusqlite::Error> for SqliteError<K> {
+	fn from(e: rusqlite::Error) -> Self {
+		Self::Sql(e)
+	}
+}
+
+impl<K: core::fmt::Debug> core::fmt::Display for SqliteError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Sql(e) => e.fmt(f),
+			Self::Rejected { row, error } => write!(f, "row {row}: {error}"),
+		}
+	}
+}
+
+impl<K: core::fmt::Debug> std::error::Error for SqliteError<K> {}
+
+/// Identifier in double quotes, so any name works as a table or column.
+fn quote(name: &str) -> String {
+	format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// SQLite value of a field. Nested values are stored as JSON text.
+fn sql_value(json: serde_json::Value) -> Value {
+	match json {
+		serde_json::Value::Null => Value::Null,
+		serde_json::Value::Bool(b) => Value::Integer(b as i64),
+		serde_json::Value::Number(n) => match n.as_i64() {
+			Some(i) => Value::Integer(i),
+			None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+		},
+		serde_json::Value::String(s) => Value::Text(s),
+		nested => Value::Text(nested.to_string()),
+	}
+}
+
+fn conversion(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> rusqlite::Error {
+	rusqlite::Error::ToSqlConversionFailure(e.into())
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Runs the query and makes a record of every row with `mapper`.
+	pub fn from_sqlite(conn: &Connection, query: &str, mut mapper: impl FnMut(&Row<'_>) -> rusqlite::Result<T>) -> Result<Self, SqliteError<T::Key>>
+	where H: Default {
+		let mut table = Self::default();
+		let mut stmt = conn.prepare(query)?;
+		let mut rows = stmt.query([])?;
+		let mut row = 0;
+		while let Some(r) = rows.next()? {
+			table.insert(mapper(r)?).map_err(|error| SqliteError::Rejected { row, error })?;
+			row += 1;
+		}
+		Ok(table)
+	}
+
+	/// Writes the records as rows of `table_name`, in one transaction, creating the table if it doesn't exist.
+	/// The records must serialize as structs or maps; their fields become the columns, in alphabetical order,
+	/// and nested fields are stored as JSON text. Returns the number of rows written.
+	pub fn to_sqlite(&self, conn: &Connection, table_name: &str) -> rusqlite::Result<usize>
+	where T: Serialize {
+		let mut rows = Vec::with_capacity(self.len());
+		for val in self.values() {
+			match serde_json::to_value(val).map_err(conversion)? {
+				serde_json::Value::Object(fields) => rows.push(fields),
+				_ => return Err(conversion("records must serialize as structs or maps")),
+			}
+		}
+		let Some(columns) = rows.first().map(|fields| fields.keys().cloned().collect::<Vec<_>>()) else { return Ok(0) };
+		let names = columns.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", ");
+		let params = (1..=columns.len()).map(|i| format!("?{i}")).collect::<Vec<_>>().join(", ");
+		let tx = conn.unchecked_transaction()?;
+		tx.execute(&format!("CREATE TABLE IF NOT EXISTS {} ({names})", quote(table_name)), [])?;
+		{
+			let mut insert = tx.prepare(&format!("INSERT INTO {} ({names}) VALUES ({params})", quote(table_name)))?;
+			for mut fields in rows {
+				let values: Vec<Value> = columns.iter().map(|c| fields.remove(c).map_or(Value::Null, sql_value)).collect();
+				insert.execute(rusqlite::params_from_iter(values))?;
+			}
+		}
+		tx.commit()?;
+		Ok(self.len())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::City;
+
+	fn city(row: &Row<'_>) -> rusqlite::Result<City> {
+		Ok(City { id: row.get("id")?, country: row.get("country")?, population: row.get("population")?, capital: row.get("capital")? })
+	}
+
+	#[test]
+	fn sqlite_roundtrip() {
+		let conn = Connection::open_in_memory().unwrap();
+		conn.execute_batch("CREATE TABLE cities (id INTEGER, country TEXT, population INTEGER, capital INTEGER);
+			INSERT INTO cities VALUES (1, 'FR', 2100000, 1), (2, 'FR', 870000, 0), (3, 'DE', 3600000, 1);").unwrap();
+		let cities = MicroTable::<City>::from_sqlite(&conn, "SELECT * FROM cities", city).unwrap();
+		assert_eq!((cities.len(), cities.find(&"FR".into()).len()), (3, 2));
+
+		assert_eq!(cities.to_sqlite(&conn, "city \"copy\"").unwrap(), 3);
+		let copy = MicroTable::<City>::from_sqlite(&conn, "SELECT * FROM \"city \"\"copy\"\"\"", city).unwrap();
+		assert_eq!(copy.get(&3), cities.get(&3));
+
+		conn.execute("INSERT INTO cities VALUES (2, 'IT', 1, 0)", []).unwrap();
+		let dup = MicroTable::<City>::from_sqlite(&conn, "SELECT * FROM cities ORDER BY rowid", city);
+		assert!(matches!(dup, Err(SqliteError::Rejected { row: 3, error: KeyError::Collision })));
+	}
+}
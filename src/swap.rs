@@ -0,0 +1,121 @@
+//! Tables read without locks: [`SwapTable`] holds the current version of a table behind an atomic pointer. Readers take
+//! the version that's current when they ask and keep it as long as they like; a writer changes a copy and publishes it
+//! as the next version. Writes copy the table, so they suit tables read much more often than they change: batch the
+//! changes into one [`SwapTable::update`], and keep the records in an [`crate::ArcTable`] with the `"imbl"` feature
+//! to make the copy cheap.
+
+use core::hash::BuildHasher;
+use alloc::sync::Arc;
+use std::sync::{Mutex, PoisonError};
+use arc_swap::ArcSwap;
+use crate::{MicroRecord, MicroTable};
+
+/// A table with versions published atomically, see the [module docs](crate::swap).
+pub struct SwapTable<T: MicroRecord, H = crate::RandomState> {
+	current: ArcSwap<MicroTable<T, H>>,
+	/// Taken by writers, so that two of them don't both change the same version and lose one's changes.
+	writer: Mutex<()>,
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone + Default> Default for SwapTable<T, H> {
+	fn default() -> Self {
+		Self::new(MicroTable::default())
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> SwapTable<T, H> {
+	pub fn new(table: MicroTable<T, H>) -> Self {
+		Self { current: ArcSwap::from_pointee(table), writer: Mutex::new(()) }
+	}
+
+	/// The current version. It doesn't change when newer ones are published.
+	pub fn load(&self) -> Arc<MicroTable<T, H>> {
+		self.current.load_full()
+	}
+
+	/// Runs `f` on the current version, without the reference counting of [`SwapTable::load`], for short reads.
+	pub fn read<R>(&self, f: impl FnOnce(&MicroTable<T, H>) -> R) -> R {
+		f(&self.current.load())
+	}
+
+	/// Publishes `table` as the next version, after the write in progress, if any.
+	pub fn store(&self, table: MicroTable<T, H>) {
+		let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+		self.current.store(Arc::new(table));
+	}
+
+	/// Changes a copy of the current version and publishes it. Writers take turns, so each changes the version
+	/// published by the one before.
+	pub fn update<R>(&self, f: impl FnOnce(&mut MicroTable<T, H>) -> R) -> R
+	where T: Clone {
+		match self.try_update(|table| Ok::<R, core::convert::Infallible>(f(table))) {
+			Ok(result) => result,
+		}
+	}
+
+	/// [`SwapTable::update`] that publishes the copy only if `f` succeeds, so readers never see half of a failed batch.
+	pub fn try_update<R, E>(&self, f: impl FnOnce(&mut MicroTable<T, H>) -> Result<R, E>) -> Result<R, E>
+	where T: Clone {
+		let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut table = MicroTable::clone(&self.current.load());
+		let result = f(&mut table)?;
+		self.current.store(Arc::new(table));
+		Ok(result)
+	}
+
+	/// The last version, shared with the readers that still hold it.
+	pub fn into_inner(self) -> Arc<MicroTable<T, H>> {
+		self.current.into_inner()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::KeyError;
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Route {
+		path: &'static str,
+		host: &'static str,
+	}
+
+	impl MicroRecord for Route {
+		type Key = &'static str;
+		type Category = &'static str;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.host]
+		}
+		fn key(&self) -> Self::Key {
+			self.path
+		}
+	}
+
+	#[test]
+	fn readers_keep_their_version() {
+		let routes = SwapTable::<Route>::default();
+		routes.update(|t| t.insert(Route { path: "/", host: "a" })).unwrap();
+		let before = routes.load();
+		std::thread::scope(|s| {
+			for host in ["a", "b", "c", "d"] {
+				let routes = &routes;
+				s.spawn(move || {
+					for path in ["/x", "/y", "/z"].map(|p| &*format!("{p}{host}").leak()) {
+						routes.update(|t| t.insert(Route { path, host })).unwrap();
+						assert!(routes.read(|t| t.contains_key(&path)));
+					}
+				});
+			}
+		});
+		assert_eq!((before.len(), routes.load().len()), (1, 13));
+
+		let failed: Result<(), KeyError<_>> = routes.try_update(|t| {
+			t.remove_cat(&"a");
+			t.insert(Route { path: "/xb", host: "a" })
+		});
+		assert_eq!(failed, Err(KeyError::Collision));
+		assert_eq!(routes.read(|t| t.find(&"a").len()), 4);
+		routes.store(MicroTable::new());
+		assert!(routes.into_inner().is_empty());
+	}
+}
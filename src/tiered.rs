@@ -0,0 +1,193 @@
+//! Two-tier table, for long histories of which queries touch only a recent slice. Up to `max_hot` recently used
+//! records live in a plain [`MicroTable`]; the least recently used ones go to a cold tier, encoded with postcard
+//! into compact byte strings and indexed by the same categories.
+
+use core::hash::BuildHasher;
+use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+use serde::{Serialize, de::DeserializeOwned};
+use crate::{KeyError, MicroRecord, MicroTable, RandomState};
+
+/// Encoded record of the cold tier, with its key and categories kept aside for the index.
+#[derive(Clone)]
+struct Cold<T: MicroRecord> {
+	key: T::Key,
+	categories: Vec<T::Category>,
+	bytes: Box<[u8]>,
+}
+
+impl<T: MicroRecord> MicroRecord for Cold<T> {
+	type Key = T::Key;
+	type Category = T::Category;
+	fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+		self.categories.iter().cloned()
+	}
+	fn key(&self) -> Self::Key {
+		self.key.clone()
+	}
+	fn heap_size(&self) -> usize {
+		self.bytes.len() + self.categories.capacity() * core::mem::size_of::<T::Category>()
+	}
+}
+
+impl<T: MicroRecord + Serialize + DeserializeOwned> Cold<T> {
+	fn encode(val: &T) -> Self {
+		let bytes = postcard::to_allocvec(val).expect("records of a tiered table must encode with postcard");
+		Self { key: val.key(), categories: val.categories().into_iter().collect(), bytes: bytes.into_boxed_slice() }
+	}
+
+	fn decode(&self) -> T {
+		postcard::from_bytes(&self.bytes).expect("records of a tiered table must decode from what they encoded to")
+	}
+}
+
+/// Table that keeps its recently used records as they are, and the rest encoded. [`TieredTable::get`] brings
+/// a cold record back (promotes it), and the least recently used hot one goes cold in its place.
+/// [`TieredTable::find`] decodes the cold records it returns but leaves them cold, so scans over history
+/// don't push the recent records out. The records must round-trip through postcard (so no `#[serde(flatten)]`).
+#[derive(Clone)]
+pub struct TieredTable<T: MicroRecord, H = RandomState> {
+	hot: MicroTable<T, H>,
+	cold: MicroTable<Cold<T>, H>,
+}
+
+impl<T: MicroRecord + Serialize + DeserializeOwned> TieredTable<T> {
+	pub fn new(max_hot: usize) -> Self {
+		Self::with_hasher(max_hot, RandomState::default())
+	}
+}
+
+impl<T: MicroRecord + Serialize + DeserializeOwned, H: BuildHasher + Clone> TieredTable<T, H> {
+	pub fn with_hasher(max_hot: usize, hasher: H) -> Self {
+		let mut hot = MicroTable::with_hasher(hasher.clone());
+		hot.set_limits(|e| e.max_records = Some(max_hot.max(1)));
+		Self { hot, cold: MicroTable::with_hasher(hasher) }
+	}
+
+	pub fn len(&self) -> usize {
+		self.hot.len() + self.cold.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.hot.is_empty() && self.cold.is_empty()
+	}
+
+	/// Records kept as they are.
+	pub fn hot_len(&self) -> usize {
+		self.hot.len()
+	}
+
+	/// Records kept encoded.
+	pub fn cold_len(&self) -> usize {
+		self.cold.len()
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.hot.contains_key(key) || self.cold.contains_key(key)
+	}
+
+	pub fn contains_cat(&self, cat: &T::Category) -> bool {
+		self.hot.contains_cat(cat) || self.cold.contains_cat(cat)
+	}
+
+	/// Inserts the record into the hot tier.
+	pub fn insert(&mut self, val: T) -> Result<(), KeyError<T::Key>> {
+		if self.cold.contains_key(&val.key()) {
+			return Err(KeyError::Collision);
+		}
+		// the hot tier evicts into the cold one in cool(), rather than dropping the records
+		self.hot.insert_slot(val.key(), val)?;
+		self.cool();
+		Ok(())
+	}
+
+	/// Finds the record, promoting it to the hot tier if it's cold.
+	pub fn get(&mut self, key: &T::Key) -> Option<&T> {
+		if let Some(cold) = self.cold.remove(key) {
+			// a key is in one tier only, and the hot one has no constraints to check
+			let val = cold.decode();
+			self.hot.insert_unchecked(cold.key, val);
+			self.cool();
+		}
+		self.hot.touch(key)
+	}
+
+	/// Records of the category from both tiers: the hot ones borrowed, the cold ones decoded.
+	pub fn find(&self, cat: &T::Category) -> Vec<Cow<'_, T>>
+	where T: Clone {
+		let hot = self.hot.find(cat).into_iter().map(Cow::Borrowed);
+		hot.chain(self.cold.find(cat).into_iter().map(|c| Cow::Owned(c.decode()))).collect()
+	}
+
+	pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+		self.hot.remove(key).or_else(|| self.cold.remove(key).map(|c| c.decode()))
+	}
+
+	/// Sets how many records stay hot, moving the extra ones to the cold tier. At least one does, the one
+	/// [`TieredTable::get`] returns.
+	pub fn set_max_hot(&mut self, max_hot: usize) {
+		self.hot.set_limits(|e| e.max_records = Some(max_hot.max(1)));
+		self.cool();
+	}
+
+	/// Moves the records over the limit of the hot tier to the cold one, encoded.
+	fn cool(&mut self) {
+		while let Some(val) = self.hot.evict_one() {
+			let cold = Cold::encode(&val);
+			self.cold.insert_unchecked(cold.key.clone(), cold);
+		}
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> core::fmt::Debug for TieredTable<T, H> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("TieredTable").field("hot", &self.hot.len()).field("cold", &self.cold.len()).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::Deserialize;
+
+	#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+	struct Tick {
+		id: u32,
+		day: u16,
+		price: f64,
+	}
+
+	impl MicroRecord for Tick {
+		type Key = u32;
+		type Category = u16;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.day]
+		}
+		fn key(&self) -> Self::Key {
+			self.id
+		}
+	}
+
+	#[test]
+	fn promote_and_cool() {
+		let mut ticks = TieredTable::new(3);
+		for id in 0..10 {
+			ticks.insert(Tick { id, day: (id / 4) as u16, price: id as f64 }).unwrap();
+		}
+		assert_eq!((ticks.hot_len(), ticks.cold_len()), (3, 7));
+		assert!(ticks.insert(Tick { id: 0, day: 0, price: 0.0 }).is_err());
+
+		assert_eq!(ticks.get(&1), Some(&Tick { id: 1, day: 0, price: 1.0 }));
+		assert_eq!((ticks.hot_len(), ticks.cold_len()), (3, 7));
+		assert!(ticks.hot.contains_key(&1) && !ticks.hot.contains_key(&7));
+
+		let mut day1: Vec<u32> = ticks.find(&1).iter().map(|t| t.id).collect();
+		day1.sort();
+		assert_eq!(day1, vec![4, 5, 6, 7]);
+		assert!(!ticks.hot.contains_key(&4)); // finding doesn't promote
+
+		assert_eq!(ticks.remove(&4).map(|t| t.price), Some(4.0));
+		ticks.set_max_hot(0);
+		assert_eq!((ticks.hot_len(), ticks.len()), (1, 9));
+		assert_eq!(ticks.get(&5).map(|t| t.id), Some(5));
+	}
+}
@@ -0,0 +1,136 @@
+use core::hash::Hash;
+use alloc::{boxed::Box, vec, vec::Vec};
+use crate::{MicroRecord, map::HashMap};
+
+/// Handle of a uniqueness constraint, returned by [`crate::MicroTable::add_unique`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UniqueId(pub(crate) usize);
+
+pub(crate) trait Constraint<T: MicroRecord> {
+	/// Key of a record that already holds the value of `val`, unless `skip` returns true for it.
+	fn conflict(&self, val: &T, skip: &dyn Fn(&T::Key) -> bool) -> Option<T::Key>;
+	/// Old key of the first of the `updates` whose new value is repeated later among them.
+	fn batch_conflict(&self, updates: &[(T::Key, T)]) -> Option<T::Key>;
+	fn add(&mut self, key: &T::Key, val: &T);
+	fn remove(&mut self, val: &T);
+	/// Moves the value of the record under `key` from `old` to `new`, if it changed.
+	fn replace(&mut self, key: &T::Key, old: &T, new: &T);
+	fn clear(&mut self);
+	fn box_clone(&self) -> Box<dyn Constraint<T> + Send + Sync>;
+}
+
+struct Unique<T: MicroRecord, U> {
+	field: fn(&T) -> U,
+	values: HashMap<U, T::Key>,
+}
+
+impl<T: MicroRecord + 'static, U: Hash + Eq + Clone + Send + Sync + 'static> Constraint<T> for Unique<T, U>
+where T::Key: Send + Sync {
+	fn conflict(&self, val: &T, skip: &dyn Fn(&T::Key) -> bool) -> Option<T::Key> {
+		self.values.get(&(self.field)(val)).filter(|k| !skip(k)).cloned()
+	}
+
+	fn batch_conflict(&self, updates: &[(T::Key, T)]) -> Option<T::Key> {
+		let mut seen: HashMap<U, &T::Key> = HashMap::with_capacity(updates.len());
+		for (key, val) in updates {
+			if let Some(k) = seen.insert((self.field)(val), key) {
+				return Some(k.clone());
+			}
+		}
+		None
+	}
+
+	fn add(&mut self, key: &T::Key, val: &T) {
+		self.values.insert((self.field)(val), key.clone());
+	}
+
+	fn remove(&mut self, val: &T) {
+		self.values.remove(&(self.field)(val));
+	}
+
+	fn replace(&mut self, key: &T::Key, old: &T, new: &T) {
+		let (old, new) = ((self.field)(old), (self.field)(new));
+		if old != new {
+			self.values.remove(&old);
+			self.values.insert(new, key.clone());
+		}
+	}
+
+	fn clear(&mut self) {
+		self.values.clear();
+	}
+
+	fn box_clone(&self) -> Box<dyn Constraint<T> + Send + Sync> {
+		Box::new(Unique { field: self.field, values: self.values.clone() })
+	}
+}
+
+/// Uniqueness constraints of a table.
+pub(crate) struct Constraints<T: MicroRecord>(Vec<Box<dyn Constraint<T> + Send + Sync>>);
+
+impl<T: MicroRecord> Constraints<T> {
+	pub(crate) fn new() -> Self {
+		Self(vec![])
+	}
+
+	/// Adds a constraint over the existing `data`, or returns a key that has a repeated value.
+	pub(crate) fn push<'a, U: Hash + Eq + Clone + Send + Sync + 'static>(&mut self, field: fn(&T) -> U, data: impl Iterator<Item = (&'a T::Key, &'a T)>) -> Result<UniqueId, (UniqueId, T::Key)>
+	where T: 'static, T::Key: Send + Sync {
+		let id = UniqueId(self.0.len());
+		let mut c = Unique { field, values: HashMap::new() };
+		for (key, val) in data {
+			if let Some(k) = c.conflict(val, &|_| false) {
+				return Err((id, k));
+			}
+			c.add(key, val);
+		}
+		self.0.push(Box::new(c));
+		Ok(id)
+	}
+
+	/// The first violated constraint and the key of the record violating it.
+	pub(crate) fn check(&self, val: &T, skip: &dyn Fn(&T::Key) -> bool) -> Option<(UniqueId, T::Key)> {
+		self.0.iter().enumerate().find_map(|(i, c)| c.conflict(val, skip).map(|k| (UniqueId(i), k)))
+	}
+
+	pub(crate) fn check_batch(&self, updates: &[(T::Key, T)]) -> Option<(UniqueId, T::Key)> {
+		self.0.iter().enumerate().find_map(|(i, c)| c.batch_conflict(updates).map(|k| (UniqueId(i), k)))
+	}
+
+	pub(crate) fn add(&mut self, key: &T::Key, val: &T) {
+		for c in self.0.iter_mut() {
+			c.add(key, val);
+		}
+	}
+
+	pub(crate) fn remove(&mut self, val: &T) {
+		for c in self.0.iter_mut() {
+			c.remove(val);
+		}
+	}
+
+	/// Updates the values of a record replaced under the same key, once its new values are checked.
+	pub(crate) fn replace(&mut self, key: &T::Key, old: &T, new: &T) {
+		for c in self.0.iter_mut() {
+			c.replace(key, old, new);
+		}
+	}
+
+	pub(crate) fn clear(&mut self) {
+		for c in self.0.iter_mut() {
+			c.clear();
+		}
+	}
+}
+
+impl<T: MicroRecord> Clone for Constraints<T> {
+	fn clone(&self) -> Self {
+		Self(self.0.iter().map(|c| c.box_clone()).collect())
+	}
+}
+
+impl<T: MicroRecord> core::fmt::Debug for Constraints<T> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		write!(f, "Constraints({})", self.0.len())
+	}
+}
@@ -0,0 +1,221 @@
+//! Serde format for tables whose record type changes between releases: the records are written with a version
+//! number, and records of older versions go through [`Versioned::migrate`] on load instead of failing.
+//! Use it with `#[serde(with = "microtable::versioned")]`:
+//!
+//! ```
+//! # use microtable::{MicroTable, MicroRecord, versioned::Versioned};
+//! # use serde::{Serialize, Deserialize, Deserializer, de::Error};
+//! #[derive(Deserialize)]
+//! struct UserV1 { id: u32 }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct User { id: u32, team: u32 }
+//! # impl MicroRecord for User {
+//! #     type Key = u32;
+//! #     type Category = u32;
+//! #     fn categories(&self) -> impl IntoIterator<Item = u32> { [self.team] }
+//! #     fn key(&self) -> u32 { self.id }
+//! # }
+//!
+//! impl Versioned for User {
+//!     const VERSION: u32 = 2;
+//!     fn migrate<'de, D: Deserializer<'de>>(version: u32, record: D) -> Result<Self, D::Error> {
+//!         match version {
+//!             1 => UserV1::deserialize(record).map(|u| User { id: u.id, team: 0 }),
+//!             _ => Err(D::Error::custom("unknown version")),
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Org {
+//!     #[serde(with = "microtable::versioned")]
+//!     users: MicroTable<User>,
+//! }
+//!
+//! let old: Org = serde_json::from_str(r#"{"users": {"version": 1, "records": [{"id": 5}]}}"#).unwrap();
+//! assert_eq!(old.users.find(&0).len(), 1);
+//! ```
+
+use core::{hash::BuildHasher, marker::PhantomData};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{DeserializeSeed, Error, MapAccess, SeqAccess, Visitor}, ser::SerializeStruct};
+use crate::{IndexPresize, MicroRecord, MicroTable, seed::MAX_PRESIZE};
+
+/// Record type with a version number in snapshots, and a way to read the records of its older versions.
+pub trait Versioned: Sized {
+	/// The version that snapshots are written with.
+	const VERSION: u32;
+
+	/// Reads a record written by an older (or newer) `version` of the type, usually by deserializing the old type
+	/// and converting it. Without an override, other versions are an error.
+	fn migrate<'de, D: Deserializer<'de>>(version: u32, record: D) -> Result<Self, D::Error> {
+		let _ = record;
+		Err(D::Error::custom(format_args!("no migration from record version {version}")))
+	}
+}
+
+struct Records<'a, T: MicroRecord, H>(&'a MicroTable<T, H>);
+
+impl<T: MicroRecord + Serialize, H: BuildHasher + Clone> Serialize for Records<'_, T, H> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(self.0.values())
+	}
+}
+
+pub fn serialize<T, H, S>(table: &MicroTable<T, H>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	T: MicroRecord + Versioned + Serialize,
+	H: BuildHasher + Clone,
+	S: Serializer,
+{
+	let mut st = serializer.serialize_struct("Snapshot", 2)?;
+	st.serialize_field("version", &T::VERSION)?;
+	st.serialize_field("records", &Records(table))?;
+	st.end()
+}
+
+/// One record, of the given version.
+struct RecordSeed<T>(u32, PhantomData<fn() -> T>);
+
+impl<'de, T: Versioned + Deserialize<'de>> DeserializeSeed<'de> for RecordSeed<T> {
+	type Value = T;
+
+	fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+		if self.0 == T::VERSION {
+			T::deserialize(deserializer)
+		} else {
+			T::migrate(self.0, deserializer)
+		}
+	}
+}
+
+/// The list of records, of the given version, into a table.
+struct RecordsSeed<T, H>(u32, PhantomData<fn() -> (T, H)>);
+
+impl<'de, T, H> DeserializeSeed<'de> for RecordsSeed<T, H>
+where
+	T: MicroRecord + Versioned + Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+{
+	type Value = MicroTable<T, H>;
+
+	fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+		deserializer.deserialize_seq(self)
+	}
+}
+
+impl<'de, T, H> Visitor<'de> for RecordsSeed<T, H>
+where
+	T: MicroRecord + Versioned + Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+{
+	type Value = MicroTable<T, H>;
+
+	fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		f.write_str("a sequence of records")
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+		let expected = seq.size_hint().unwrap_or(0).min(MAX_PRESIZE);
+		let mut table = MicroTable::with_capacity_and_hasher(expected, 0, H::default());
+		let mut presize = IndexPresize::new(&table, expected);
+		for loaded in 0.. {
+			presize.reserve(&mut table, loaded);
+			let Some(val) = seq.next_element_seed(RecordSeed(self.0, PhantomData))? else { break };
+			table.insert(val).map_err(|_| A::Error::custom("duplicate key in table data"))?;
+		}
+		Ok(table)
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+	Version,
+	Records,
+}
+
+struct SnapshotVisitor<T, H>(PhantomData<fn() -> (T, H)>);
+
+impl<'de, T, H> Visitor<'de> for SnapshotVisitor<T, H>
+where
+	T: MicroRecord + Versioned + Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+{
+	type Value = MicroTable<T, H>;
+
+	fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		f.write_str("a versioned table snapshot")
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+		let version: u32 = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(0, &self))?;
+		seq.next_element_seed(RecordsSeed(version, PhantomData))?.ok_or_else(|| A::Error::invalid_length(1, &self))
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+		// the version must come first, since it tells how to read the records
+		let mut version = None;
+		while let Some(field) = map.next_key()? {
+			match (field, version) {
+				(Field::Version, None) => version = Some(map.next_value()?),
+				(Field::Version, Some(_)) => return Err(A::Error::duplicate_field("version")),
+				(Field::Records, None) => return Err(A::Error::custom("snapshot records come before the version")),
+				(Field::Records, Some(v)) => return map.next_value_seed(RecordsSeed(v, PhantomData)),
+			}
+		}
+		Err(A::Error::missing_field("records"))
+	}
+}
+
+pub fn deserialize<'de, T, H, D>(deserializer: D) -> Result<MicroTable<T, H>, D::Error>
+where
+	T: MicroRecord + Versioned + Deserialize<'de>,
+	H: BuildHasher + Clone + Default,
+	D: Deserializer<'de>,
+{
+	deserializer.deserialize_struct("Snapshot", &["version", "records"], SnapshotVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Note;
+
+	#[derive(Deserialize)]
+	struct NoteV1 {
+		id: u32,
+	}
+
+	impl Versioned for Note {
+		const VERSION: u32 = 2;
+		fn migrate<'de, D: Deserializer<'de>>(version: u32, record: D) -> Result<Self, D::Error> {
+			match version {
+				1 => NoteV1::deserialize(record).map(|n| Note { id: n.id, tag: "untagged".into() }),
+				_ => Err(D::Error::custom("unknown version")),
+			}
+		}
+	}
+
+	#[derive(Serialize, Deserialize)]
+	struct Notes {
+		#[serde(with = "crate::versioned")]
+		notes: MicroTable<Note>,
+	}
+
+	#[test]
+	fn migrate_on_load() {
+		let notes = [Note { id: 1, tag: "a".into() }].into_iter().collect();
+		let s = serde_json::to_string(&Notes { notes }).unwrap();
+		assert_eq!(s, r#"{"notes":{"version":2,"records":[{"id":1,"tag":"a"}]}}"#);
+		assert_eq!(serde_json::from_str::<Notes>(&s).unwrap().notes.find(&"a".into()).len(), 1);
+
+		let old: Notes = serde_json::from_str(r#"{"notes": {"version": 1, "records": [{"id": 1}, {"id": 2}]}}"#).unwrap();
+		assert_eq!(old.notes.find(&"untagged".into()).len(), 2);
+		assert!(serde_json::from_str::<Notes>(r#"{"notes": {"version": 9, "records": [{"id": 1}]}}"#).is_err());
+		assert!(serde_json::from_str::<Notes>(r#"{"notes": {"records": [], "version": 2}}"#).is_err());
+
+		let bytes = postcard::to_allocvec(&Notes { notes: old.notes }).unwrap();
+		assert_eq!(postcard::from_bytes::<Notes>(&bytes).unwrap().notes.len(), 2);
+	}
+}
@@ -0,0 +1,119 @@
+//! Reading a table as a plain map of keys to records, for code written against standard collections.
+
+use core::hash::BuildHasher;
+use crate::{MicroRecord, MicroTable, RandomState, map::HashMap, slab::Slab};
+
+/// Read-only view of the records by key, returned by [`MicroTable::as_map`]. It has the lookup and iteration methods
+/// of a `HashMap<Key, T>`, without the categories. The table keeps its records in slots rather than in such a map,
+/// so this borrows them instead of being one.
+pub struct MapView<'a, T: MicroRecord, H = RandomState>(&'a Slab<T::Key, T, H>);
+
+impl<T: MicroRecord, H> Clone for MapView<'_, T, H> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T: MicroRecord, H> Copy for MapView<'_, T, H> {}
+
+impl<'a, T: MicroRecord, H: BuildHasher + Clone> MapView<'a, T, H> {
+	pub fn get(&self, key: &T::Key) -> Option<&'a T> {
+		self.0.get(key)
+	}
+
+	pub fn contains_key(&self, key: &T::Key) -> bool {
+		self.0.contains_key(key)
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.len() == 0
+	}
+
+	pub fn iter(&self) -> impl ExactSizeIterator<Item = (&'a T::Key, &'a T)> + 'a {
+		self.0.iter()
+	}
+
+	pub fn keys(&self) -> impl ExactSizeIterator<Item = &'a T::Key> + 'a {
+		self.0.keys()
+	}
+
+	pub fn values(&self) -> impl ExactSizeIterator<Item = &'a T> + 'a {
+		self.0.values()
+	}
+
+	pub fn hasher(&self) -> &'a H {
+		self.0.hasher()
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> core::ops::Index<&T::Key> for MapView<'_, T, H> {
+	type Output = T;
+
+	/// Panics if the key is not in the table.
+	fn index(&self, key: &T::Key) -> &T {
+		&self.0[key]
+	}
+}
+
+impl<T: MicroRecord + core::fmt::Debug, H: BuildHasher + Clone> core::fmt::Debug for MapView<'_, T, H>
+where T::Key: core::fmt::Debug {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_map().entries(self.0.iter()).finish()
+	}
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// The records by key, as a read-only map view, to pass to code that expects a map without cloning the records.
+	pub fn as_map(&self) -> MapView<'_, T, H> {
+		MapView(&self.data)
+	}
+
+	/// Takes the records out into a `HashMap` by key, with the table's hasher, discarding the category index.
+	pub fn into_inner(self) -> HashMap<T::Key, T, H> {
+		let mut map = HashMap::with_capacity_and_hasher(self.data.len(), self.data.hasher().clone());
+		map.extend(self.data.into_pairs());
+		map
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Pet {
+		name: &'static str,
+		kind: &'static str,
+	}
+
+	impl MicroRecord for Pet {
+		type Key = &'static str;
+		type Category = &'static str;
+		fn categories(&self) -> impl IntoIterator<Item = Self::Category> {
+			[self.kind]
+		}
+		fn key(&self) -> Self::Key {
+			self.name
+		}
+	}
+
+	#[test]
+	fn map_view_and_into_inner() {
+		let mut pets: MicroTable<Pet> = [("rex", "dog"), ("tom", "cat"), ("fido", "dog")].into_iter()
+			.map(|(name, kind)| Pet { name, kind }).collect();
+		pets.remove(&"tom");
+		let view = pets.as_map();
+		assert_eq!((view.len(), view.contains_key(&"tom"), view[&"rex"].kind), (2, false, "dog"));
+		assert_eq!(view.get(&"fido"), pets.get(&"fido"));
+		let mut keys: alloc::vec::Vec<_> = view.keys().copied().collect();
+		keys.sort();
+		assert_eq!(keys, ["fido", "rex"]);
+
+		let map = pets.into_inner();
+		assert_eq!((map.len(), map[&"rex"].kind), (2, "dog"));
+	}
+}
@@ -0,0 +1,411 @@
+//! Write-ahead log: a [`WalTable`] appends every change it makes to a log file, and reopening it replays the log
+//! over the last [binary snapshot](crate::binary), so the table survives a crash or a restart.
+//!
+//! The log is the magic bytes and the format version, then every change as a `u32` length, the postcard bytes of the
+//! change, and the FNV-1a hash of the two (`u64`). A crash can leave the last entry cut short; replay stops at the
+//! first entry that is incomplete or fails its hash, and [`WalTable::open`] cuts the file there.
+//!
+//! [`WalTable::checkpoint`] saves a new snapshot and empties the log, so the log doesn't grow forever; a
+//! [`CheckpointPolicy`] does it by itself when the log gets big or old. Changes in the log replace whole records,
+//! so if a crash comes between saving the snapshot and emptying the log, replaying the log over the new snapshot
+//! ends in the same table.
+
+use core::{hash::BuildHasher, ops::Deref, time::Duration};
+use alloc::vec::Vec;
+use std::{fs::{File, OpenOptions}, io::{self, BufReader, Read, Seek, Write}, path::{Path, PathBuf}, time::Instant};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use crate::{FNV_OFFSET, KeyError, MicroRecord, MicroTable, fnv1a};
+
+const MAGIC: [u8; 8] = *b"MTWAL\0\0\0";
+const VERSION: u32 = 1;
+const HEADER: u64 = MAGIC.len() as u64 + 4;
+
+/// A change as it's written, borrowing the record.
+#[derive(Serialize)]
+enum OpRef<'a, T, K> {
+	Insert(&'a T),
+	Upsert(&'a K, &'a T),
+	Remove(&'a K),
+	Clear,
+}
+
+/// A change as it's read, in the same order of variants as [`OpRef`].
+#[derive(Deserialize)]
+enum Op<T, K> {
+	Insert(T),
+	Upsert(K, T),
+	Remove(K),
+	Clear,
+}
+
+/// Error of a change to a [`WalTable`].
+#[derive(Debug)]
+pub enum WalError<K> {
+	/// The table rejected the change, so nothing was logged.
+	Table(KeyError<K>),
+	/// The change is made in memory, but writing it to the log failed.
+	Io(io::Error),
+}
+
+impl<K> From<KeyError<K>> for WalError<K> {
+	fn from(e: KeyError<K>) -> Self {
+		Self::Table(e)
+	}
+}
+
+impl<K> From<io::Error> for WalError<K> {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl<K: core::fmt::Debug> core::fmt::Display for WalError<K> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Table(e) => e.fmt(f),
+			Self::Io(e) => write!(f, "writing the log failed: {e}"),
+		}
+	}
+}
+
+impl<K: core::fmt::Debug> std::error::Error for WalError<K> {}
+
+fn invalid(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads the next whole entry into `buf`, `false` at the end of the log or at a cut or damaged entry.
+fn read_entry(r: &mut impl Read, buf: &mut Vec<u8>) -> io::Result<bool> {
+	let mut len = [0; 4];
+	// read rather than allocated up front, so a damaged length can't ask for gigabytes
+	let mut got = Vec::with_capacity(4);
+	if r.take(4).read_to_end(&mut got)? != 4 {
+		return Ok(false);
+	}
+	len.copy_from_slice(&got);
+	let size = u32::from_le_bytes(len) as u64;
+	buf.clear();
+	if r.take(size).read_to_end(buf)? as u64 != size {
+		return Ok(false);
+	}
+	got.clear();
+	if r.take(8).read_to_end(&mut got)? != 8 {
+		return Ok(false);
+	}
+	Ok(got[..] == fnv1a(fnv1a(FNV_OFFSET, &len), buf).to_le_bytes())
+}
+
+/// Writes the change as one entry, returning its size.
+fn append<T: Serialize, K: Serialize>(log: &mut File, sync: bool, op: OpRef<'_, T, K>) -> io::Result<u64> {
+	let bytes = postcard::to_allocvec(&op).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+	let len = u32::try_from(bytes.len()).map_err(|_| invalid("change longer than 4 GiB"))?.to_le_bytes();
+	let mut entry = Vec::with_capacity(4 + bytes.len() + 8);
+	entry.extend_from_slice(&len);
+	entry.extend_from_slice(&bytes);
+	entry.extend_from_slice(&fnv1a(fnv1a(FNV_OFFSET, &len), &bytes).to_le_bytes());
+	// one write per entry, so a crash cuts at most the last one
+	log.write_all(&entry)?;
+	if sync {
+		log.sync_data()?;
+	}
+	Ok(entry.len() as u64)
+}
+
+impl<T: MicroRecord, H: BuildHasher + Clone> MicroTable<T, H> {
+	/// Applies the changes of a log to the table. Returns how many bytes of the log were whole entries.
+	fn replay(&mut self, wal: impl Read) -> io::Result<u64>
+	where T: DeserializeOwned, T::Key: DeserializeOwned {
+		let mut r = BufReader::new(wal);
+		let mut header = [0; HEADER as usize];
+		if r.read_exact(&mut header).is_err() {
+			return Ok(0);
+		}
+		if header[..MAGIC.len()] != MAGIC {
+			return Err(invalid("not a microtable log"));
+		}
+		if header[MAGIC.len()..] != VERSION.to_le_bytes() {
+			return Err(invalid("unsupported log version"));
+		}
+		let (mut good, mut buf) = (HEADER, Vec::new());
+		while read_entry(&mut r, &mut buf)? {
+			let op: Op<T, T::Key> = postcard::from_bytes(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			// records are replaced rather than checked against what's there, so replaying changes
+			// that the snapshot already has ends in the same records
+			let applied = match op {
+				Op::Insert(val) => {
+					self.remove(&val.key());
+					self.insert(val)
+				}
+				Op::Upsert(key, val) => {
+					self.remove(&key);
+					self.remove(&val.key());
+					self.insert(val)
+				}
+				Op::Remove(key) => {
+					self.remove(&key);
+					Ok(())
+				}
+				Op::Clear => {
+					self.clear();
+					Ok(())
+				}
+			};
+			applied.map_err(|_| invalid("log doesn't apply to the snapshot"))?;
+			good += 4 + buf.len() as u64 + 8;
+		}
+		Ok(good)
+	}
+
+	/// Rebuilds a table from a binary snapshot and the log of the changes made after it.
+	pub fn recover(snapshot: impl Read, wal: impl Read) -> io::Result<Self>
+	where T: DeserializeOwned, T::Key: DeserializeOwned, H: Default {
+		let mut table = Self::load_snapshot(snapshot)?;
+		table.replay(wal)?;
+		Ok(table)
+	}
+}
+
+/// Table that logs its changes to a file, see the [module docs](crate::wal). It reads like the [`MicroTable`]
+/// it derefs to, and changes go through its own methods, each written to the log once the table accepts it.
+pub struct WalTable<T: MicroRecord, H = crate::RandomState> {
+	table: MicroTable<T, H>,
+	log: File,
+	/// Bytes in the log, the header included.
+	log_len: u64,
+	snapshot: PathBuf,
+	checkpointed: Instant,
+	policy: CheckpointPolicy,
+	sync: bool,
+}
+
+/// When a [`WalTable`] checkpoints by itself, checked after every change. Both limits are off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+	/// Checkpoint once the log has this many bytes.
+	pub max_log_bytes: Option<u64>,
+	/// Checkpoint once this long has passed since the last checkpoint, or since opening the table.
+	pub max_age: Option<Duration>,
+}
+
+impl<T: MicroRecord + Serialize + DeserializeOwned, H: BuildHasher + Clone + Default> WalTable<T, H>
+where T::Key: DeserializeOwned {
+	/// Loads the snapshot (an empty table if the file doesn't exist), replays the log over it (creating the log
+	/// if it doesn't exist), and cuts off a last entry that a crash left incomplete.
+	pub fn open(snapshot: impl AsRef<Path>, wal: impl AsRef<Path>) -> io::Result<Self> {
+		let snapshot = snapshot.as_ref().to_path_buf();
+		let mut table = match File::open(&snapshot) {
+			Ok(file) => MicroTable::load_snapshot(file)?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => MicroTable::default(),
+			Err(e) => return Err(e),
+		};
+		let mut log = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(wal)?;
+		let mut good = table.replay(&mut log)?;
+		if good == 0 {
+			good = HEADER;
+			log.set_len(0)?;
+			log.rewind()?;
+			log.write_all(&MAGIC)?;
+			log.write_all(&VERSION.to_le_bytes())?;
+			log.sync_data()?;
+		} else if good < log.metadata()?.len() {
+			log.set_len(good)?;
+			log.sync_data()?;
+		}
+		log.seek(io::SeekFrom::End(0))?;
+		Ok(Self { table, log, log_len: good, snapshot, checkpointed: Instant::now(), policy: CheckpointPolicy::default(), sync: true })
+	}
+}
+
+impl<T: MicroRecord + Serialize, H: BuildHasher + Clone> WalTable<T, H>
+where T::Key: Serialize {
+	/// Whether every change is synced to the disk before it returns, the default. Without it, changes reach the
+	/// OS at once but a power loss can drop the latest ones; [`WalTable::sync`] syncs them on demand.
+	pub fn set_sync(&mut self, sync: bool) {
+		self.sync = sync;
+	}
+
+	/// Syncs the log to the disk.
+	pub fn sync(&mut self) -> io::Result<()> {
+		self.log.sync_data()
+	}
+
+	pub fn set_checkpoint_policy(&mut self, policy: CheckpointPolicy) {
+		self.policy = policy;
+	}
+
+	/// Bytes in the log, the header included.
+	pub fn log_len(&self) -> u64 {
+		self.log_len
+	}
+
+	/// Saves the table as the new snapshot (through a temporary file renamed over the old one) and empties the log.
+	pub fn checkpoint(&mut self) -> io::Result<()> {
+		let name = self.snapshot.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "snapshot path has no file name"))?;
+		let tmp = self.snapshot.with_file_name(alloc::format!(".{}.tmp", name.to_string_lossy()));
+		let mut file = File::create(&tmp)?;
+		self.table.save_snapshot(&mut file)?;
+		file.sync_all()?;
+		std::fs::rename(&tmp, &self.snapshot)?;
+		// the log is emptied only once the new snapshot is sure to be found
+		crate::sync_parent(&self.snapshot)?;
+		self.log.set_len(HEADER)?;
+		self.log.seek(io::SeekFrom::End(0))?;
+		self.log.sync_data()?;
+		self.log_len = HEADER;
+		self.checkpointed = Instant::now();
+		Ok(())
+	}
+
+	/// Counts the bytes just logged, and checkpoints if the policy says so.
+	fn logged(&mut self, bytes: u64) -> io::Result<()> {
+		self.log_len += bytes;
+		let too_big = self.policy.max_log_bytes.is_some_and(|max| self.log_len >= max);
+		let too_old = self.policy.max_age.is_some_and(|max| self.checkpointed.elapsed() >= max);
+		if too_big || too_old {
+			self.checkpoint()?;
+		}
+		Ok(())
+	}
+
+	pub fn insert(&mut self, val: T) -> Result<(), WalError<T::Key>> {
+		let key = val.key();
+		self.table.insert(val)?;
+		let bytes = append(&mut self.log, self.sync, OpRef::<_, T::Key>::Insert(self.table.get(&key).expect("just inserted")))?;
+		self.logged(bytes)?;
+		Ok(())
+	}
+
+	/// [`MicroTable::upsert`], logged.
+	pub fn upsert(&mut self, key: T::Key, new_val: T) -> Result<(), WalError<T::Key>> {
+		let new_key = new_val.key();
+		self.table.upsert(key.clone(), new_val)?;
+		let bytes = append(&mut self.log, self.sync, OpRef::Upsert(&key, self.table.get(&new_key).expect("just upserted")))?;
+		self.logged(bytes)?;
+		Ok(())
+	}
+
+	/// [`MicroTable::update_with`], logged as the updated record.
+	pub fn update_with(&mut self, old_key: T::Key, cb: &impl Fn(&mut T)) -> Result<(), WalError<T::Key>>
+	where T: Clone {
+		let new_key = core::cell::RefCell::new(None);
+		self.table.update_with(old_key.clone(), &|val: &mut T| {
+			cb(val);
+			*new_key.borrow_mut() = Some(val.key());
+		})?;
+		let new_key = new_key.into_inner().expect("callback ran");
+		let bytes = append(&mut self.log, self.sync, OpRef::Upsert(&old_key, self.table.get(&new_key).expect("just updated")))?;
+		self.logged(bytes)?;
+		Ok(())
+	}
+
+	/// Removes the record, logging the removal if there was one.
+	pub fn remove(&mut self, key: &T::Key) -> Result<Option<T>, io::Error> {
+		let Some(val) = self.table.remove(key) else { return Ok(None) };
+		let bytes = append::<T, _>(&mut self.log, self.sync, OpRef::Remove(key))?;
+		self.logged(bytes)?;
+		Ok(Some(val))
+	}
+
+	/// [`MicroTable::remove_cat`], logged as the removals of the records.
+	pub fn remove_cat(&mut self, cat: &T::Category) -> Result<Vec<T>, io::Error> {
+		let removed = self.table.remove_cat(cat);
+		let mut bytes = 0;
+		for val in removed.iter() {
+			bytes += append::<T, _>(&mut self.log, false, OpRef::Remove(&val.key()))?;
+		}
+		if self.sync && !removed.is_empty() {
+			self.log.sync_data()?;
+		}
+		self.logged(bytes)?;
+		Ok(removed)
+	}
+
+	pub fn clear(&mut self) -> Result<(), io::Error> {
+		self.table.clear();
+		let bytes = append::<T, T::Key>(&mut self.log, self.sync, OpRef::Clear)?;
+		self.logged(bytes)
+	}
+}
+
+impl<T: MicroRecord, H> Deref for WalTable<T, H> {
+	type Target = MicroTable<T, H>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.table
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fixtures::Account;
+
+	#[test]
+	fn replay_after_crash() {
+		let dir = std::env::temp_dir();
+		let (snapshot, wal) = (dir.join(format!("microtable-wal-{}.snap", std::process::id())), dir.join(format!("microtable-wal-{}.log", std::process::id())));
+		let _ = std::fs::remove_file(&wal);
+		let mut accounts = WalTable::<Account>::open(&snapshot, &wal).unwrap();
+		accounts.set_sync(false);
+		for id in 0..10 {
+			accounts.insert(Account { id, owner: id % 3, balance: 100 }).unwrap();
+		}
+		assert!(matches!(accounts.insert(Account { id: 1, owner: 0, balance: 0 }), Err(WalError::Table(KeyError::Collision))));
+		accounts.update_with(4, &|a| a.balance -= 30).unwrap();
+		accounts.upsert(5, Account { id: 50, owner: 2, balance: 5 }).unwrap();
+		accounts.remove(&0).unwrap();
+		assert_eq!(accounts.remove_cat(&1).unwrap().len(), 3);
+		drop(accounts);
+		let len = std::fs::metadata(&wal).unwrap().len();
+		// a crash in the middle of writing an entry
+		let mut log = OpenOptions::new().append(true).open(&wal).unwrap();
+		log.write_all(&[9, 0, 0, 0, 1, 2]).unwrap();
+		drop(log);
+
+		let accounts = WalTable::<Account>::open(&snapshot, &wal).unwrap();
+		let mut ids: Vec<u32> = accounts.iter_keys().copied().collect();
+		ids.sort();
+		assert_eq!(ids, vec![2, 3, 6, 8, 9, 50]);
+		assert_eq!(accounts.get(&50).map(|a| a.balance), Some(5));
+		drop(accounts);
+		assert_eq!(std::fs::metadata(&wal).unwrap().len(), len, "the cut entry is gone");
+		let recovered = MicroTable::<Account>::recover(&b""[..], File::open(&wal).unwrap());
+		assert!(recovered.is_err(), "the snapshot is required");
+		let mut empty = vec![];
+		MicroTable::<Account>::new().save_snapshot(&mut empty).unwrap();
+		let recovered = MicroTable::<Account>::recover(&empty[..], File::open(&wal).unwrap()).unwrap();
+		assert_eq!((recovered.len(), recovered.find(&2).len()), (6, 3));
+		std::fs::remove_file(&wal).unwrap();
+	}
+
+	#[test]
+	fn checkpoints() {
+		let dir = std::env::temp_dir();
+		let (snapshot, wal) = (dir.join(format!("microtable-ckpt-{}.snap", std::process::id())), dir.join(format!("microtable-ckpt-{}.log", std::process::id())));
+		let _ = (std::fs::remove_file(&snapshot), std::fs::remove_file(&wal));
+		let mut accounts = WalTable::<Account>::open(&snapshot, &wal).unwrap();
+		accounts.set_sync(false);
+		for id in 0..10 {
+			accounts.insert(Account { id, owner: id % 2, balance: 0 }).unwrap();
+		}
+		accounts.remove(&3).unwrap();
+		let old_log = std::fs::read(&wal).unwrap();
+		accounts.checkpoint().unwrap();
+		assert_eq!((accounts.log_len(), std::fs::metadata(&wal).unwrap().len()), (HEADER, HEADER));
+		drop(accounts);
+		// a crash after saving the snapshot, before emptying the log
+		std::fs::write(&wal, &old_log).unwrap();
+		let mut accounts = WalTable::<Account>::open(&snapshot, &wal).unwrap();
+		assert_eq!((accounts.len(), accounts.find(&1).len()), (9, 4));
+
+		accounts.set_checkpoint_policy(CheckpointPolicy { max_log_bytes: Some(HEADER + 40), ..Default::default() });
+		let before = accounts.log_len();
+		accounts.upsert(4, Account { id: 4, owner: 1, balance: 7 }).unwrap();
+		assert!(accounts.log_len() < before, "checkpointed");
+		drop(accounts);
+		let accounts = WalTable::<Account>::open(&snapshot, &wal).unwrap();
+		assert_eq!(accounts.get(&4).map(|a| a.balance), Some(7));
+		std::fs::remove_file(&snapshot).unwrap();
+		std::fs::remove_file(&wal).unwrap();
+	}
+}